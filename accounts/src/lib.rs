@@ -0,0 +1,345 @@
+use std::fmt::{self, Display};
+use std::net::Ipv4Addr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+mod memory;
+mod password;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+pub use memory::MemoryStore;
+pub use password::{hash_password, plain_password, verify_md5_digest, verify_password, PasswordCheck, PasswordScheme};
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteStore;
+
+/// First account id handed out, ids below it are reserved.
+pub const START_ACCOUNT_ID: u32 = 2000000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sex {
+    Female,
+    Male,
+    /// Accounts used by the char and map servers to log in.
+    Server,
+}
+
+impl Sex {
+    pub fn as_char(self) -> char {
+        match self {
+            Sex::Female => 'F',
+            Sex::Male => 'M',
+            Sex::Server => 'S',
+        }
+    }
+
+    pub fn from_char(c: char) -> Option<Self> {
+        match c.to_ascii_uppercase() {
+            'F' => Some(Sex::Female),
+            'M' => Some(Sex::Male),
+            'S' => Some(Sex::Server),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccountState {
+    Active,
+    /// Banned until the given unix time, or for good when `None`.
+    Banned { until: Option<u64> },
+    /// Locked after too many failed logins, until the given unix time or
+    /// until an admin unlocks it.
+    Locked { until: Option<u64> },
+}
+
+/// Range of banned addresses, both ends included.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IpBan {
+    pub start: Ipv4Addr,
+    pub end: Ipv4Addr,
+    /// Unix time the ban ends at, or `None` for good.
+    pub until: Option<u64>,
+    pub reason: String,
+}
+
+impl IpBan {
+    pub fn contains(&self, ip: Ipv4Addr) -> bool {
+        (self.start..=self.end).contains(&ip)
+    }
+
+    pub fn is_active(&self, now: u64) -> bool {
+        self.until.is_none_or(|until| until > now)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Account {
+    pub account_id: u32,
+    pub username: String,
+    pub password_hash: String,
+    pub sex: Sex,
+    pub group_id: u32,
+    pub state: AccountState,
+    /// Unix time after which the account can't log in anymore.
+    pub expiration_time: Option<u64>,
+    pub login_count: u32,
+    /// Incorrect passwords since the last successful login.
+    pub failed_attempts: u32,
+    pub last_login: Option<u64>,
+    pub last_ip: Option<Ipv4Addr>,
+}
+
+/// Account about to be created, with its password already hashed.
+#[derive(Debug, Clone)]
+pub struct NewAccount {
+    pub username: String,
+    pub password_hash: String,
+    pub sex: Sex,
+    pub group_id: u32,
+}
+
+impl NewAccount {
+    pub fn new(username: &str, password: &str, sex: Sex, scheme: PasswordScheme) -> Result<Self, StoreError> {
+        Ok(Self {
+            username: username.to_string(),
+            password_hash: hash_password(password, scheme)?,
+            sex,
+            group_id: 0,
+        })
+    }
+
+    /// Account with its password stored in clear, see `plain_password`.
+    pub fn with_plain_password(username: &str, password: &str, sex: Sex) -> Self {
+        Self {
+            username: username.to_string(),
+            password_hash: plain_password(password),
+            sex,
+            group_id: 0,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum StoreError {
+    DuplicateUsername(String),
+    NotFound(u32),
+    Hash(String),
+    Backend(String),
+}
+
+impl Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::DuplicateUsername(username) => write!(f, "username '{}' is already taken", username),
+            StoreError::NotFound(account_id) => write!(f, "account {} doesn't exist", account_id),
+            StoreError::Hash(reason) => write!(f, "failed to hash password: {}", reason),
+            StoreError::Backend(reason) => write!(f, "account storage error: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+pub trait AccountStore: Send + Sync {
+    fn find_by_id(&self, account_id: u32) -> Result<Option<Account>, StoreError>;
+
+    fn find_by_username(&self, username: &str) -> Result<Option<Account>, StoreError>;
+
+    fn create(&self, account: NewAccount) -> Result<Account, StoreError>;
+
+    /// Saves every field of an existing account.
+    fn update(&self, account: &Account) -> Result<(), StoreError>;
+
+    /// Counts an incorrect password in a single write, so concurrent logins
+    /// can't lose attempts. A lock that ran out at `now` is lifted first, and
+    /// an active account gets locked until `lock_until` once it reached
+    /// `max` attempts. Returns the account as saved.
+    fn record_failed_attempt(&self, account_id: u32, now: u64, max: Option<u32>, lock_until: Option<u64>) -> Result<Account, StoreError>;
+
+    /// Records a successful login in a single write, lifting a ban or lock
+    /// that ran out at `now` and replacing the password hash when given.
+    /// Returns the account as saved.
+    fn record_login(&self, account_id: u32, now: u64, ip: Option<Ipv4Addr>, password_hash: Option<&str>) -> Result<Account, StoreError>;
+
+    /// Changes the state alone, forgetting the failed attempts when it
+    /// becomes active. Returns the account as saved.
+    fn set_state(&self, account_id: u32, state: AccountState) -> Result<Account, StoreError>;
+
+    fn ip_bans(&self) -> Result<Vec<IpBan>, StoreError>;
+
+    fn add_ip_ban(&self, ban: IpBan) -> Result<(), StoreError>;
+
+    /// Removes the bans of exactly this range, returns how many were removed.
+    fn remove_ip_ban(&self, start: Ipv4Addr, end: Ipv4Addr) -> Result<usize, StoreError>;
+
+    /// First ban covering `ip` that is still running at `now`.
+    fn find_ip_ban(&self, ip: Ipv4Addr, now: u64) -> Result<Option<IpBan>, StoreError> {
+        Ok(self.ip_bans()?.into_iter().find(|ban| ban.is_active(now) && ban.contains(ip)))
+    }
+}
+
+#[derive(Debug)]
+pub enum LoginError {
+    UnregisteredId,
+    IncorrectPassword,
+    Expired,
+    Banned { until: Option<u64> },
+    Locked { until: Option<u64> },
+    IpBanned { until: Option<u64> },
+    Store(StoreError),
+}
+
+impl Display for LoginError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoginError::UnregisteredId => write!(f, "unregistered id"),
+            LoginError::IncorrectPassword => write!(f, "incorrect password"),
+            LoginError::Expired => write!(f, "account expired"),
+            LoginError::Banned { until: Some(until) } => write!(f, "account banned until {}", until),
+            LoginError::Banned { until: None } => write!(f, "account banned"),
+            LoginError::Locked { until: Some(until) } => write!(f, "account locked until {}", until),
+            LoginError::Locked { until: None } => write!(f, "account locked"),
+            LoginError::IpBanned { until: Some(until) } => write!(f, "address banned until {}", until),
+            LoginError::IpBanned { until: None } => write!(f, "address banned"),
+            LoginError::Store(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for LoginError {}
+
+impl From<StoreError> for LoginError {
+    fn from(error: StoreError) -> Self {
+        LoginError::Store(error)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LoginPolicy {
+    /// Accepts unsalted MD5 hashes imported from older servers, upgrading
+    /// them on the next successful login.
+    pub allow_legacy_md5: bool,
+    /// Accepts passwords stored in clear, which clients logging in with
+    /// `CA_REQ_HASH` need. Anyone reading the accounts can read them too.
+    pub allow_plain_passwords: bool,
+    /// Incorrect passwords in a row that lock an account, `None` never locks.
+    pub max_failed_attempts: Option<u32>,
+    /// Seconds a locked account stays locked, `None` until an admin unlocks it.
+    pub lock_duration: Option<u64>,
+}
+
+/// What a client proves it knows the password with.
+#[derive(Debug, Clone, Copy)]
+pub enum Credentials<'a> {
+    Password(&'a str),
+    /// MD5 of the password salted with the challenge sent in `AC_ACK_HASH`,
+    /// refused without a challenge.
+    Md5Digest { digest: [u8; 16], challenge: &'a [u8] },
+}
+
+/// A successful login, with the account as saved after it.
+#[derive(Debug, Clone)]
+pub struct LoginSuccess {
+    pub account: Account,
+    pub previous_login: Option<u64>,
+    pub previous_ip: Option<Ipv4Addr>,
+}
+
+pub fn unix_time() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
+
+/// Changes the state of an account, as an admin. Unlocking or lifting a
+/// ban also forgets the failed login attempts.
+pub fn set_account_state(store: &dyn AccountStore, account_id: u32, state: AccountState) -> Result<Account, StoreError> {
+    store.set_state(account_id, state)
+}
+
+/// Formats a unix time as `YYYY-MM-DD HH:MM:SS` in UTC.
+pub fn format_time(time: u64) -> String {
+    let days = (time / 86400) as i64;
+    let seconds = time % 86400;
+
+    // Civil date from days since the epoch, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Checks the credentials and the state of an account and records the login.
+pub fn authenticate(
+    store: &dyn AccountStore,
+    username: &str,
+    credentials: Credentials,
+    ip: Option<Ipv4Addr>,
+    policy: LoginPolicy,
+) -> Result<LoginSuccess, LoginError> {
+    let now = unix_time();
+    if let Some(ban) = ip.map(|ip| store.find_ip_ban(ip, now)).transpose()?.flatten() {
+        return Err(LoginError::IpBanned { until: ban.until });
+    }
+
+    let account = store.find_by_username(username)?.ok_or(LoginError::UnregisteredId)?;
+
+    // Checked before the password so a locked account doesn't tell whether
+    // a guess was right.
+    if let AccountState::Locked { until } = account.state {
+        if until.is_none_or(|until| until > now) {
+            return Err(LoginError::Locked { until });
+        }
+    }
+
+    let check = match credentials {
+        Credentials::Password(password) => verify_password(&account.password_hash, password, policy),
+        Credentials::Md5Digest { digest, challenge } => {
+            verify_md5_digest(&account.password_hash, &digest, challenge, policy)
+        },
+    };
+    if check == PasswordCheck::Invalid {
+        let lock_until = policy.lock_duration.map(|duration| now + duration);
+        store.record_failed_attempt(account.account_id, now, policy.max_failed_attempts, lock_until)?;
+
+        return Err(LoginError::IncorrectPassword);
+    }
+
+    if let AccountState::Banned { until } = account.state {
+        if until.is_none_or(|until| until > now) {
+            return Err(LoginError::Banned { until });
+        }
+    }
+
+    if account.expiration_time.is_some_and(|expiration| expiration <= now) {
+        return Err(LoginError::Expired);
+    }
+
+    let password_hash = match (check, credentials) {
+        (PasswordCheck::Outdated, Credentials::Password(password)) => Some(hash_password(password, PasswordScheme::Argon2)?),
+        _ => None,
+    };
+
+    let previous_login = account.last_login;
+    let previous_ip = account.last_ip;
+    let account = store.record_login(account.account_id, now, ip, password_hash.as_deref())?;
+
+    Ok(LoginSuccess {
+        account,
+        previous_login,
+        previous_ip,
+    })
+}
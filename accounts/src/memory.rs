@@ -0,0 +1,138 @@
+use std::collections::BTreeMap;
+use std::net::Ipv4Addr;
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::{Account, AccountState, AccountStore, IpBan, NewAccount, StoreError, START_ACCOUNT_ID};
+
+/// Accounts kept in memory, for tests and throwaway servers.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    accounts: RwLock<BTreeMap<u32, Account>>,
+    ip_bans: RwLock<Vec<IpBan>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, BTreeMap<u32, Account>> {
+        self.accounts.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, BTreeMap<u32, Account>> {
+        self.accounts.write().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl AccountStore for MemoryStore {
+    fn find_by_id(&self, account_id: u32) -> Result<Option<Account>, StoreError> {
+        Ok(self.read().get(&account_id).cloned())
+    }
+
+    fn find_by_username(&self, username: &str) -> Result<Option<Account>, StoreError> {
+        Ok(self.read().values().find(|account| account.username == username).cloned())
+    }
+
+    fn create(&self, account: NewAccount) -> Result<Account, StoreError> {
+        let mut accounts = self.write();
+
+        if accounts.values().any(|existing| existing.username == account.username) {
+            return Err(StoreError::DuplicateUsername(account.username));
+        }
+
+        let account_id = accounts.keys().next_back().map_or(START_ACCOUNT_ID, |id| id + 1);
+        let account = Account {
+            account_id,
+            username: account.username,
+            password_hash: account.password_hash,
+            sex: account.sex,
+            group_id: account.group_id,
+            state: AccountState::Active,
+            expiration_time: None,
+            login_count: 0,
+            failed_attempts: 0,
+            last_login: None,
+            last_ip: None,
+        };
+
+        accounts.insert(account_id, account.clone());
+        Ok(account)
+    }
+
+    fn update(&self, account: &Account) -> Result<(), StoreError> {
+        match self.write().get_mut(&account.account_id) {
+            Some(existing) => {
+                *existing = account.clone();
+                Ok(())
+            },
+            None => Err(StoreError::NotFound(account.account_id)),
+        }
+    }
+
+    fn record_failed_attempt(&self, account_id: u32, now: u64, max: Option<u32>, lock_until: Option<u64>) -> Result<Account, StoreError> {
+        let mut accounts = self.write();
+        let account = accounts.get_mut(&account_id).ok_or(StoreError::NotFound(account_id))?;
+
+        if let AccountState::Locked { until: Some(until) } = account.state {
+            if until <= now {
+                account.state = AccountState::Active;
+                account.failed_attempts = 0;
+            }
+        }
+        account.failed_attempts = account.failed_attempts.saturating_add(1);
+        if account.state == AccountState::Active && max.is_some_and(|max| account.failed_attempts >= max) {
+            account.state = AccountState::Locked { until: lock_until };
+        }
+
+        Ok(account.clone())
+    }
+
+    fn record_login(&self, account_id: u32, now: u64, ip: Option<Ipv4Addr>, password_hash: Option<&str>) -> Result<Account, StoreError> {
+        let mut accounts = self.write();
+        let account = accounts.get_mut(&account_id).ok_or(StoreError::NotFound(account_id))?;
+
+        if let AccountState::Banned { until: Some(until) } | AccountState::Locked { until: Some(until) } = account.state {
+            if until <= now {
+                account.state = AccountState::Active;
+            }
+        }
+        if let Some(password_hash) = password_hash {
+            account.password_hash = password_hash.to_string();
+        }
+        account.login_count = account.login_count.saturating_add(1);
+        account.last_login = Some(now);
+        account.last_ip = ip;
+        account.failed_attempts = 0;
+
+        Ok(account.clone())
+    }
+
+    fn set_state(&self, account_id: u32, state: AccountState) -> Result<Account, StoreError> {
+        let mut accounts = self.write();
+        let account = accounts.get_mut(&account_id).ok_or(StoreError::NotFound(account_id))?;
+
+        account.state = state;
+        if state == AccountState::Active {
+            account.failed_attempts = 0;
+        }
+
+        Ok(account.clone())
+    }
+
+    fn ip_bans(&self) -> Result<Vec<IpBan>, StoreError> {
+        Ok(self.ip_bans.read().unwrap_or_else(|poisoned| poisoned.into_inner()).clone())
+    }
+
+    fn add_ip_ban(&self, ban: IpBan) -> Result<(), StoreError> {
+        self.ip_bans.write().unwrap_or_else(|poisoned| poisoned.into_inner()).push(ban);
+        Ok(())
+    }
+
+    fn remove_ip_ban(&self, start: Ipv4Addr, end: Ipv4Addr) -> Result<usize, StoreError> {
+        let mut ip_bans = self.ip_bans.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let before = ip_bans.len();
+        ip_bans.retain(|ban| ban.start != start || ban.end != end);
+        Ok(before - ip_bans.len())
+    }
+}
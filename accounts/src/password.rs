@@ -0,0 +1,97 @@
+use argon2::password_hash::phc::PasswordHash;
+use argon2::password_hash::{PasswordHasher, PasswordVerifier};
+use argon2::Argon2;
+use md5::{Digest, Md5};
+
+use crate::{LoginPolicy, StoreError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordScheme {
+    Argon2,
+    /// Unsalted lowercase MD5 hex digest, as stored by older servers.
+    LegacyMd5,
+}
+
+const PLAIN_PREFIX: &str = "$plain$";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordCheck {
+    Valid,
+    /// The password matches but is stored with a weak scheme.
+    Outdated,
+    Invalid,
+}
+
+pub fn hash_password(password: &str, scheme: PasswordScheme) -> Result<String, StoreError> {
+    match scheme {
+        PasswordScheme::Argon2 => Argon2::default()
+            .hash_password(password.as_bytes())
+            .map(|hash| hash.to_string())
+            .map_err(|err| StoreError::Hash(err.to_string())),
+        PasswordScheme::LegacyMd5 => Ok(md5_hex(password)),
+    }
+}
+
+/// Stores the password itself, in clear, behind a `$plain$` prefix. Only
+/// clients hashing the password with the challenge of `CA_REQ_HASH` need
+/// it, and it is only accepted with `LoginPolicy::allow_plain_passwords`.
+pub fn plain_password(password: &str) -> String {
+    format!("{}{}", PLAIN_PREFIX, password)
+}
+
+pub fn verify_password(stored: &str, password: &str, policy: LoginPolicy) -> PasswordCheck {
+    if stored.starts_with("$argon2") {
+        let valid = PasswordHash::new(stored)
+            .is_ok_and(|hash| Argon2::default().verify_password(password.as_bytes(), &hash).is_ok());
+        return if valid { PasswordCheck::Valid } else { PasswordCheck::Invalid };
+    }
+
+    if let Some(plain) = stored.strip_prefix(PLAIN_PREFIX) {
+        let valid = policy.allow_plain_passwords && constant_time_eq(plain.as_bytes(), password.as_bytes());
+        return if valid { PasswordCheck::Valid } else { PasswordCheck::Invalid };
+    }
+
+    if policy.allow_legacy_md5 && is_md5_hex(stored) && constant_time_eq(stored.as_bytes(), md5_hex(password).as_bytes()) {
+        return PasswordCheck::Outdated;
+    }
+
+    PasswordCheck::Invalid
+}
+
+/// Checks the MD5 digest sent by clients instead of the password, made of
+/// the challenge followed by the password or the other way around.
+/// Needs a plain password and a challenge, a digest without one being the
+/// same every time and as good as the password to whoever sees it.
+pub fn verify_md5_digest(stored: &str, digest: &[u8; 16], challenge: &[u8], policy: LoginPolicy) -> PasswordCheck {
+    if challenge.is_empty() {
+        return PasswordCheck::Invalid;
+    }
+
+    if let Some(plain) = stored.strip_prefix(PLAIN_PREFIX) {
+        if !policy.allow_plain_passwords {
+            return PasswordCheck::Invalid;
+        }
+        let key_first = Md5::new().chain_update(challenge).chain_update(plain).finalize();
+        let key_last = Md5::new().chain_update(plain).chain_update(challenge).finalize();
+        let valid = constant_time_eq(&key_first, digest) | constant_time_eq(&key_last, digest);
+        return if valid { PasswordCheck::Valid } else { PasswordCheck::Invalid };
+    }
+
+    PasswordCheck::Invalid
+}
+
+fn md5_hex(password: &str) -> String {
+    to_hex(&Md5::digest(password.as_bytes()))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn is_md5_hex(stored: &str) -> bool {
+    stored.len() == 32 && stored.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
@@ -0,0 +1,295 @@
+use std::net::Ipv4Addr;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+
+use rusqlite::{params, Connection, OptionalExtension, Row};
+
+use crate::{Account, AccountState, AccountStore, IpBan, NewAccount, Sex, StoreError, START_ACCOUNT_ID};
+
+/// Statements bringing the schema from version `n` to `n + 1`, the version
+/// being kept in `PRAGMA user_version`.
+const MIGRATIONS: &[&str] = &[
+    "
+    CREATE TABLE login (
+        account_id      INTEGER PRIMARY KEY,
+        userid          TEXT    NOT NULL UNIQUE,
+        user_pass       TEXT    NOT NULL,
+        sex             TEXT    NOT NULL DEFAULT 'M',
+        group_id        INTEGER NOT NULL DEFAULT 0,
+        banned          INTEGER NOT NULL DEFAULT 0,
+        unban_time      INTEGER,
+        expiration_time INTEGER,
+        logincount      INTEGER NOT NULL DEFAULT 0,
+        lastlogin       INTEGER,
+        last_ip         TEXT
+    );
+    ",
+    "
+    ALTER TABLE login ADD COLUMN state INTEGER NOT NULL DEFAULT 0;
+    UPDATE login SET state = 1 WHERE banned != 0;
+    ALTER TABLE login DROP COLUMN banned;
+    ALTER TABLE login RENAME COLUMN unban_time TO state_until;
+    ALTER TABLE login ADD COLUMN failed_attempts INTEGER NOT NULL DEFAULT 0;
+    CREATE TABLE ipbanlist (
+        start_ip    TEXT    NOT NULL,
+        end_ip      TEXT    NOT NULL,
+        until       INTEGER,
+        reason      TEXT    NOT NULL DEFAULT ''
+    );
+    ",
+];
+
+const COLUMNS: &str = "account_id, userid, user_pass, sex, group_id, state, state_until, expiration_time, logincount, lastlogin, last_ip, failed_attempts";
+
+const STATE_ACTIVE: u8 = 0;
+const STATE_BANNED: u8 = 1;
+const STATE_LOCKED: u8 = 2;
+
+// SQLite integers are signed, unix times are stored as they are.
+fn time_from_sql(time: Option<i64>) -> Option<u64> {
+    time.map(|time| time.max(0) as u64)
+}
+
+fn time_to_sql(time: Option<u64>) -> Option<i64> {
+    time.map(|time| time.min(i64::MAX as u64) as i64)
+}
+
+impl From<rusqlite::Error> for StoreError {
+    fn from(error: rusqlite::Error) -> Self {
+        StoreError::Backend(error.to_string())
+    }
+}
+
+/// Accounts stored in an SQLite database, in a `login` table.
+#[derive(Debug)]
+pub struct SqliteStore {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, StoreError> {
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self, StoreError> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(mut connection: Connection) -> Result<Self, StoreError> {
+        let version: u32 = connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        let Some(pending) = MIGRATIONS.get(version as usize..) else {
+            return Err(StoreError::Backend(format!("unsupported schema version {}", version)));
+        };
+
+        for (version, migration) in (version + 1..).zip(pending) {
+            let transaction = connection.transaction()?;
+            transaction.execute_batch(migration)?;
+            transaction.pragma_update(None, "user_version", version)?;
+            transaction.commit()?;
+        }
+
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    fn connection(&self) -> MutexGuard<'_, Connection> {
+        self.connection.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn account_from_row(row: &Row) -> rusqlite::Result<Account> {
+        let sex: String = row.get(3)?;
+        let state: u8 = row.get(5)?;
+        let until = time_from_sql(row.get(6)?);
+        let last_ip: Option<String> = row.get(10)?;
+
+        Ok(Account {
+            account_id: row.get(0)?,
+            username: row.get(1)?,
+            password_hash: row.get(2)?,
+            sex: sex.chars().next().and_then(Sex::from_char).unwrap_or(Sex::Male),
+            group_id: row.get(4)?,
+            state: match state {
+                STATE_BANNED => AccountState::Banned { until },
+                STATE_LOCKED => AccountState::Locked { until },
+                _ => AccountState::Active,
+            },
+            expiration_time: time_from_sql(row.get(7)?),
+            login_count: row.get(8)?,
+            last_login: time_from_sql(row.get(9)?),
+            last_ip: last_ip.and_then(|ip| ip.parse::<Ipv4Addr>().ok()),
+            failed_attempts: row.get(11)?,
+        })
+    }
+
+    fn state_to_sql(state: AccountState) -> (u8, Option<i64>) {
+        match state {
+            AccountState::Active => (STATE_ACTIVE, None),
+            AccountState::Banned { until } => (STATE_BANNED, time_to_sql(until)),
+            AccountState::Locked { until } => (STATE_LOCKED, time_to_sql(until)),
+        }
+    }
+
+    /// Runs an `UPDATE ... RETURNING` of one account.
+    fn update_returning(&self, account_id: u32, query: &str, params: &[&dyn rusqlite::ToSql]) -> Result<Account, StoreError> {
+        self.connection()
+            .query_row(query, params, Self::account_from_row)
+            .optional()?
+            .ok_or(StoreError::NotFound(account_id))
+    }
+
+    fn find(&self, condition: &str, value: &dyn rusqlite::ToSql) -> Result<Option<Account>, StoreError> {
+        let query = format!("SELECT {} FROM login WHERE {} = ?1", COLUMNS, condition);
+        Ok(self.connection().query_row(&query, [value], Self::account_from_row).optional()?)
+    }
+}
+
+impl AccountStore for SqliteStore {
+    fn find_by_id(&self, account_id: u32) -> Result<Option<Account>, StoreError> {
+        self.find("account_id", &account_id)
+    }
+
+    fn find_by_username(&self, username: &str) -> Result<Option<Account>, StoreError> {
+        self.find("userid", &username)
+    }
+
+    fn create(&self, account: NewAccount) -> Result<Account, StoreError> {
+        let mut connection = self.connection();
+        let transaction = connection.transaction()?;
+
+        let taken: bool = transaction.query_row("SELECT EXISTS(SELECT 1 FROM login WHERE userid = ?1)", [&account.username], |row| row.get(0))?;
+        if taken {
+            return Err(StoreError::DuplicateUsername(account.username));
+        }
+
+        let account_id: u32 = transaction.query_row(
+            "SELECT COALESCE(MAX(account_id) + 1, ?1) FROM login",
+            [START_ACCOUNT_ID],
+            |row| row.get(0),
+        )?;
+        transaction.execute(
+            "INSERT INTO login (account_id, userid, user_pass, sex, group_id) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![account_id, account.username, account.password_hash, account.sex.as_char().to_string(), account.group_id],
+        )?;
+        let created = transaction.query_row(
+            &format!("SELECT {} FROM login WHERE account_id = ?1", COLUMNS),
+            [account_id],
+            Self::account_from_row,
+        )?;
+        transaction.commit()?;
+
+        Ok(created)
+    }
+
+    fn update(&self, account: &Account) -> Result<(), StoreError> {
+        let (state, until) = Self::state_to_sql(account.state);
+
+        let updated = self.connection().execute(
+            "UPDATE login SET userid = ?2, user_pass = ?3, sex = ?4, group_id = ?5, state = ?6, state_until = ?7,
+                expiration_time = ?8, logincount = ?9, lastlogin = ?10, last_ip = ?11, failed_attempts = ?12
+            WHERE account_id = ?1",
+            params![
+                account.account_id,
+                account.username,
+                account.password_hash,
+                account.sex.as_char().to_string(),
+                account.group_id,
+                state,
+                until,
+                time_to_sql(account.expiration_time),
+                account.login_count,
+                time_to_sql(account.last_login),
+                account.last_ip.map(|ip| ip.to_string()),
+                account.failed_attempts,
+            ],
+        )?;
+
+        if updated == 0 {
+            return Err(StoreError::NotFound(account.account_id));
+        }
+        Ok(())
+    }
+
+    fn record_failed_attempt(&self, account_id: u32, now: u64, max: Option<u32>, lock_until: Option<u64>) -> Result<Account, StoreError> {
+        // Every expression sees the row as it was before the update.
+        let expired = format!("(state = {} AND state_until <= ?2)", STATE_LOCKED);
+        let attempts = format!("(CASE WHEN {} THEN 1 ELSE failed_attempts + 1 END)", expired);
+        let lock = format!("(?3 IS NOT NULL AND {} >= ?3 AND (state = {} OR {}))", attempts, STATE_ACTIVE, expired);
+        let query = format!(
+            "UPDATE login SET failed_attempts = {attempts},
+                state = CASE WHEN {lock} THEN {locked} WHEN {expired} THEN {active} ELSE state END,
+                state_until = CASE WHEN {lock} THEN ?4 WHEN {expired} THEN NULL ELSE state_until END
+            WHERE account_id = ?1 RETURNING {columns}",
+            attempts = attempts,
+            lock = lock,
+            expired = expired,
+            locked = STATE_LOCKED,
+            active = STATE_ACTIVE,
+            columns = COLUMNS,
+        );
+
+        self.update_returning(account_id, &query, params![account_id, time_to_sql(Some(now)), max, time_to_sql(lock_until)])
+    }
+
+    fn record_login(&self, account_id: u32, now: u64, ip: Option<Ipv4Addr>, password_hash: Option<&str>) -> Result<Account, StoreError> {
+        let expired = format!("(state != {} AND state_until <= ?2)", STATE_ACTIVE);
+        let query = format!(
+            "UPDATE login SET logincount = logincount + 1, lastlogin = ?2, last_ip = ?3, failed_attempts = 0,
+                user_pass = COALESCE(?4, user_pass),
+                state = CASE WHEN {expired} THEN {active} ELSE state END,
+                state_until = CASE WHEN {expired} THEN NULL ELSE state_until END
+            WHERE account_id = ?1 RETURNING {columns}",
+            expired = expired,
+            active = STATE_ACTIVE,
+            columns = COLUMNS,
+        );
+
+        let ip = ip.map(|ip| ip.to_string());
+        self.update_returning(account_id, &query, params![account_id, time_to_sql(Some(now)), ip, password_hash])
+    }
+
+    fn set_state(&self, account_id: u32, state: AccountState) -> Result<Account, StoreError> {
+        let (state, until) = Self::state_to_sql(state);
+        let query = format!(
+            "UPDATE login SET state = ?2, state_until = ?3,
+                failed_attempts = CASE WHEN ?2 = {} THEN 0 ELSE failed_attempts END
+            WHERE account_id = ?1 RETURNING {}",
+            STATE_ACTIVE, COLUMNS,
+        );
+
+        self.update_returning(account_id, &query, params![account_id, state, until])
+    }
+
+    fn ip_bans(&self) -> Result<Vec<IpBan>, StoreError> {
+        let connection = self.connection();
+        let mut statement = connection.prepare("SELECT start_ip, end_ip, until, reason FROM ipbanlist")?;
+        let rows = statement.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, time_from_sql(row.get(2)?), row.get(3)?))
+        })?;
+
+        let mut bans = Vec::new();
+        for row in rows {
+            let (start, end, until, reason) = row?;
+            // Rows edited by hand with an invalid address are skipped.
+            if let (Ok(start), Ok(end)) = (start.parse(), end.parse()) {
+                bans.push(IpBan { start, end, until, reason });
+            }
+        }
+        Ok(bans)
+    }
+
+    fn add_ip_ban(&self, ban: IpBan) -> Result<(), StoreError> {
+        self.connection().execute(
+            "INSERT INTO ipbanlist (start_ip, end_ip, until, reason) VALUES (?1, ?2, ?3, ?4)",
+            params![ban.start.to_string(), ban.end.to_string(), time_to_sql(ban.until), ban.reason],
+        )?;
+        Ok(())
+    }
+
+    fn remove_ip_ban(&self, start: Ipv4Addr, end: Ipv4Addr) -> Result<usize, StoreError> {
+        Ok(self.connection().execute(
+            "DELETE FROM ipbanlist WHERE start_ip = ?1 AND end_ip = ?2",
+            [start.to_string(), end.to_string()],
+        )?)
+    }
+}
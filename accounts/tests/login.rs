@@ -0,0 +1,146 @@
+use std::sync::Barrier;
+use std::thread;
+
+use accounts::{authenticate, AccountState, AccountStore, Credentials, LoginError, LoginPolicy, MemoryStore, NewAccount, PasswordScheme, Sex};
+use md5::{Digest, Md5};
+
+const ATTEMPTS: u32 = 8;
+
+fn policy() -> LoginPolicy {
+    LoginPolicy {
+        allow_legacy_md5: false,
+        allow_plain_passwords: true,
+        max_failed_attempts: Some(ATTEMPTS),
+        lock_duration: None,
+    }
+}
+
+fn create(store: &dyn AccountStore) -> u32 {
+    store.create(NewAccount::with_plain_password("player", "secret", Sex::Male)).unwrap().account_id
+}
+
+fn md5(first: &[u8], second: &[u8]) -> [u8; 16] {
+    Md5::new().chain_update(first).chain_update(second).finalize().into()
+}
+
+/// Every bad password of a burst is counted, however they interleave.
+fn parallel_bad_logins_lock(store: &dyn AccountStore) {
+    let account_id = create(store);
+    let barrier = Barrier::new(ATTEMPTS as usize);
+
+    thread::scope(|scope| {
+        for _ in 0..ATTEMPTS {
+            scope.spawn(|| {
+                barrier.wait();
+                let result = authenticate(store, "player", Credentials::Password("wrong"), None, policy());
+                assert!(matches!(result, Err(LoginError::IncorrectPassword)));
+            });
+        }
+    });
+
+    let account = store.find_by_id(account_id).unwrap().unwrap();
+    assert_eq!(account.failed_attempts, ATTEMPTS);
+    assert_eq!(account.state, AccountState::Locked { until: None });
+
+    let result = authenticate(store, "player", Credentials::Password("secret"), None, policy());
+    assert!(matches!(result, Err(LoginError::Locked { until: None })));
+}
+
+/// A ban set while a login was being checked survives the login.
+fn ban_is_kept(store: &dyn AccountStore) {
+    let account_id = create(store);
+    let banned = AccountState::Banned { until: None };
+
+    store.set_state(account_id, banned).unwrap();
+    assert_eq!(store.record_failed_attempt(account_id, 0, Some(1), None).unwrap().state, banned);
+    assert_eq!(store.record_login(account_id, 0, None, None).unwrap().state, banned);
+}
+
+/// A lock that ran out is lifted, and the attempts counted from scratch.
+fn expired_lock_is_lifted(store: &dyn AccountStore) {
+    let account_id = create(store);
+    store.set_state(account_id, AccountState::Locked { until: Some(100) }).unwrap();
+
+    let account = store.record_failed_attempt(account_id, 50, Some(2), Some(200)).unwrap();
+    assert_eq!(account.state, AccountState::Locked { until: Some(100) });
+
+    let account = store.record_failed_attempt(account_id, 150, Some(2), Some(200)).unwrap();
+    assert_eq!((account.state, account.failed_attempts), (AccountState::Active, 1));
+
+    let account = store.record_failed_attempt(account_id, 160, Some(2), Some(260)).unwrap();
+    assert_eq!((account.state, account.failed_attempts), (AccountState::Locked { until: Some(260) }, 2));
+
+    let account = store.record_login(account_id, 300, Some([127, 0, 0, 1].into()), Some("hash")).unwrap();
+    assert_eq!((account.state, account.failed_attempts, account.login_count), (AccountState::Active, 0, 1));
+    assert_eq!((account.last_login, account.password_hash.as_str()), (Some(300), "hash"));
+}
+
+#[test]
+fn memory_parallel_bad_logins_lock() {
+    parallel_bad_logins_lock(&MemoryStore::new());
+}
+
+#[test]
+fn memory_ban_is_kept() {
+    ban_is_kept(&MemoryStore::new());
+}
+
+#[test]
+fn memory_expired_lock_is_lifted() {
+    expired_lock_is_lifted(&MemoryStore::new());
+}
+
+/// Clients put the challenge before or after the password, both are fine.
+#[test]
+fn digest_in_either_order() {
+    let store = MemoryStore::new();
+    create(&store);
+    let challenge = b"0123456789abcdef";
+
+    for digest in [md5(challenge, b"secret"), md5(b"secret", challenge)] {
+        let credentials = Credentials::Md5Digest { digest, challenge };
+        assert!(authenticate(&store, "player", credentials, None, policy()).is_ok());
+    }
+
+    let credentials = Credentials::Md5Digest { digest: md5(challenge, b"wrong"), challenge };
+    let result = authenticate(&store, "player", credentials, None, policy());
+    assert!(matches!(result, Err(LoginError::IncorrectPassword)));
+}
+
+/// Without a challenge the digest never changes, so seeing it once is enough
+/// to log in: it is refused, whether it is of the password or the stored hash.
+#[test]
+fn digest_without_challenge_is_refused() {
+    let store = MemoryStore::new();
+    create(&store);
+    let credentials = Credentials::Md5Digest { digest: md5(b"", b"secret"), challenge: b"" };
+    let result = authenticate(&store, "player", credentials, None, policy());
+    assert!(matches!(result, Err(LoginError::IncorrectPassword)));
+
+    let legacy = NewAccount::new("legacy", "secret", Sex::Female, PasswordScheme::LegacyMd5).unwrap();
+    store.create(legacy).unwrap();
+    let policy = LoginPolicy { allow_legacy_md5: true, ..policy() };
+    let credentials = Credentials::Md5Digest { digest: md5(b"", b"secret"), challenge: b"" };
+    let result = authenticate(&store, "legacy", credentials, None, policy);
+    assert!(matches!(result, Err(LoginError::IncorrectPassword)));
+}
+
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use accounts::SqliteStore;
+
+    #[test]
+    fn parallel_bad_logins_lock() {
+        super::parallel_bad_logins_lock(&SqliteStore::open_in_memory().unwrap());
+    }
+
+    #[test]
+    fn ban_is_kept() {
+        super::ban_is_kept(&SqliteStore::open_in_memory().unwrap());
+    }
+
+    #[test]
+    fn expired_lock_is_lifted() {
+        super::expired_lock_is_lifted(&SqliteStore::open_in_memory().unwrap());
+    }
+}
@@ -0,0 +1,153 @@
+use std::fmt::{self, Display};
+
+pub use accounts::Sex;
+
+mod memory;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+pub use memory::MemoryStore;
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteStore;
+
+/// First character id handed out.
+pub const START_CHAR_ID: u32 = 150000;
+
+/// Slots shown in the character select screen.
+pub const MAX_CHARS: u8 = 9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Stats {
+    pub str: u8,
+    pub agi: u8,
+    pub vit: u8,
+    pub int: u8,
+    pub dex: u8,
+    pub luk: u8,
+}
+
+/// Sprites worn by a character.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Look {
+    pub hair: u16,
+    pub hair_color: u16,
+    pub clothes_color: u16,
+    pub weapon: u16,
+    pub shield: u16,
+    pub head_top: u16,
+    pub head_mid: u16,
+    pub head_bottom: u16,
+    pub robe: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Position {
+    pub map: String,
+    pub x: u16,
+    pub y: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Character {
+    pub char_id: u32,
+    pub account_id: u32,
+    pub slot: u8,
+    pub name: String,
+    pub sex: Sex,
+    pub class: u16,
+    pub base_level: u16,
+    pub job_level: u16,
+    pub base_exp: u64,
+    pub job_exp: u64,
+    pub zeny: u32,
+    pub stats: Stats,
+    pub hp: u32,
+    pub max_hp: u32,
+    pub sp: u16,
+    pub max_sp: u16,
+    pub status_points: u16,
+    pub skill_points: u16,
+    pub look: Look,
+    pub position: Position,
+    pub save_point: Position,
+}
+
+/// Character about to be created, already checked by the char server.
+#[derive(Debug, Clone)]
+pub struct NewCharacter {
+    pub account_id: u32,
+    pub slot: u8,
+    pub name: String,
+    pub sex: Sex,
+    pub class: u16,
+    pub stats: Stats,
+    pub hp: u32,
+    pub sp: u16,
+    pub look: Look,
+    pub start: Position,
+}
+
+#[derive(Debug)]
+pub enum StoreError {
+    NameTaken(String),
+    SlotTaken(u8),
+    NotFound(u32),
+    Backend(String),
+}
+
+impl Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::NameTaken(name) => write!(f, "name '{}' is already taken", name),
+            StoreError::SlotTaken(slot) => write!(f, "slot {} is already used", slot),
+            StoreError::NotFound(char_id) => write!(f, "character {} doesn't exist", char_id),
+            StoreError::Backend(reason) => write!(f, "character storage error: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+pub trait CharacterStore: Send + Sync {
+    fn find(&self, char_id: u32) -> Result<Option<Character>, StoreError>;
+
+    /// Characters of an account, ordered by slot.
+    fn characters_of(&self, account_id: u32) -> Result<Vec<Character>, StoreError>;
+
+    fn create(&self, character: NewCharacter) -> Result<Character, StoreError>;
+
+    /// Saves every field of an existing character.
+    fn update(&self, character: &Character) -> Result<(), StoreError>;
+
+    /// Returns `false` if the character didn't exist.
+    fn delete(&self, char_id: u32) -> Result<bool, StoreError>;
+}
+
+impl NewCharacter {
+    /// The character as stored, at level 1 with every point spent.
+    pub fn into_character(self, char_id: u32) -> Character {
+        Character {
+            char_id,
+            account_id: self.account_id,
+            slot: self.slot,
+            name: self.name,
+            sex: self.sex,
+            class: self.class,
+            base_level: 1,
+            job_level: 1,
+            base_exp: 0,
+            job_exp: 0,
+            zeny: 0,
+            stats: self.stats,
+            hp: self.hp,
+            max_hp: self.hp,
+            sp: self.sp,
+            max_sp: self.sp,
+            status_points: 0,
+            skill_points: 0,
+            look: self.look,
+            position: self.start.clone(),
+            save_point: self.start,
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use std::collections::BTreeMap;
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::{Character, CharacterStore, NewCharacter, StoreError, START_CHAR_ID};
+
+/// Characters kept in memory, for tests and throwaway servers.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    characters: RwLock<BTreeMap<u32, Character>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, BTreeMap<u32, Character>> {
+        self.characters.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, BTreeMap<u32, Character>> {
+        self.characters.write().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl CharacterStore for MemoryStore {
+    fn find(&self, char_id: u32) -> Result<Option<Character>, StoreError> {
+        Ok(self.read().get(&char_id).cloned())
+    }
+
+    fn characters_of(&self, account_id: u32) -> Result<Vec<Character>, StoreError> {
+        let mut characters: Vec<Character> = self
+            .read()
+            .values()
+            .filter(|character| character.account_id == account_id)
+            .cloned()
+            .collect();
+        characters.sort_by_key(|character| character.slot);
+        Ok(characters)
+    }
+
+    fn create(&self, character: NewCharacter) -> Result<Character, StoreError> {
+        let mut characters = self.write();
+
+        if characters.values().any(|existing| existing.name == character.name) {
+            return Err(StoreError::NameTaken(character.name));
+        }
+        if characters
+            .values()
+            .any(|existing| existing.account_id == character.account_id && existing.slot == character.slot)
+        {
+            return Err(StoreError::SlotTaken(character.slot));
+        }
+
+        let char_id = characters.keys().next_back().map_or(START_CHAR_ID, |id| id + 1);
+        let character = character.into_character(char_id);
+
+        characters.insert(char_id, character.clone());
+        Ok(character)
+    }
+
+    fn update(&self, character: &Character) -> Result<(), StoreError> {
+        match self.write().get_mut(&character.char_id) {
+            Some(existing) => {
+                *existing = character.clone();
+                Ok(())
+            },
+            None => Err(StoreError::NotFound(character.char_id)),
+        }
+    }
+
+    fn delete(&self, char_id: u32) -> Result<bool, StoreError> {
+        Ok(self.write().remove(&char_id).is_some())
+    }
+}
@@ -0,0 +1,275 @@
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+use rusqlite::{params, Connection, OptionalExtension, Row, TransactionBehavior};
+
+use crate::{Character, CharacterStore, Look, NewCharacter, Position, Sex, Stats, StoreError, START_CHAR_ID};
+
+/// Statements bringing the schema from version `n` to `n + 1`, the version
+/// being kept in `PRAGMA user_version`.
+const MIGRATIONS: &[&str] = &["
+    CREATE TABLE char (
+        char_id         INTEGER PRIMARY KEY,
+        account_id      INTEGER NOT NULL,
+        char_num        INTEGER NOT NULL,
+        name            TEXT    NOT NULL UNIQUE,
+        sex             TEXT    NOT NULL,
+        class           INTEGER NOT NULL,
+        base_level      INTEGER NOT NULL,
+        job_level       INTEGER NOT NULL,
+        base_exp        INTEGER NOT NULL,
+        job_exp         INTEGER NOT NULL,
+        zeny            INTEGER NOT NULL,
+        str             INTEGER NOT NULL,
+        agi             INTEGER NOT NULL,
+        vit             INTEGER NOT NULL,
+        int             INTEGER NOT NULL,
+        dex             INTEGER NOT NULL,
+        luk             INTEGER NOT NULL,
+        hp              INTEGER NOT NULL,
+        max_hp          INTEGER NOT NULL,
+        sp              INTEGER NOT NULL,
+        max_sp          INTEGER NOT NULL,
+        status_point    INTEGER NOT NULL,
+        skill_point     INTEGER NOT NULL,
+        hair            INTEGER NOT NULL,
+        hair_color      INTEGER NOT NULL,
+        clothes_color   INTEGER NOT NULL,
+        weapon          INTEGER NOT NULL,
+        shield          INTEGER NOT NULL,
+        head_top        INTEGER NOT NULL,
+        head_mid        INTEGER NOT NULL,
+        head_bottom     INTEGER NOT NULL,
+        robe            INTEGER NOT NULL,
+        last_map        TEXT    NOT NULL,
+        last_x          INTEGER NOT NULL,
+        last_y          INTEGER NOT NULL,
+        save_map        TEXT    NOT NULL,
+        save_x          INTEGER NOT NULL,
+        save_y          INTEGER NOT NULL,
+        UNIQUE (account_id, char_num)
+    );
+"];
+
+/// How long to wait for the other server holding the database.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+const COLUMNS: &str = "char_id, account_id, char_num, name, sex, class, base_level, job_level, base_exp, job_exp, zeny,
+    str, agi, vit, int, dex, luk, hp, max_hp, sp, max_sp, status_point, skill_point,
+    hair, hair_color, clothes_color, weapon, shield, head_top, head_mid, head_bottom, robe,
+    last_map, last_x, last_y, save_map, save_x, save_y";
+
+impl From<rusqlite::Error> for StoreError {
+    fn from(error: rusqlite::Error) -> Self {
+        StoreError::Backend(error.to_string())
+    }
+}
+
+/// Characters stored in an SQLite database, in a `char` table.
+#[derive(Debug)]
+pub struct SqliteStore {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, StoreError> {
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self, StoreError> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(mut connection: Connection) -> Result<Self, StoreError> {
+        // The char and map servers share the database and may start together,
+        // so the version is read again under the write lock before each step.
+        connection.busy_timeout(BUSY_TIMEOUT)?;
+        loop {
+            let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            let version: u32 = transaction.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+            let Some(migration) = MIGRATIONS.get(version as usize) else {
+                if version as usize > MIGRATIONS.len() {
+                    return Err(StoreError::Backend(format!("unsupported schema version {}", version)));
+                }
+                break;
+            };
+
+            transaction.execute_batch(migration)?;
+            transaction.pragma_update(None, "user_version", version + 1)?;
+            transaction.commit()?;
+        }
+
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    fn connection(&self) -> MutexGuard<'_, Connection> {
+        self.connection.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn character_from_row(row: &Row) -> rusqlite::Result<Character> {
+        let sex: String = row.get(4)?;
+        let base_exp: i64 = row.get(8)?;
+        let job_exp: i64 = row.get(9)?;
+
+        Ok(Character {
+            char_id: row.get(0)?,
+            account_id: row.get(1)?,
+            slot: row.get(2)?,
+            name: row.get(3)?,
+            sex: sex.chars().next().and_then(Sex::from_char).unwrap_or(Sex::Male),
+            class: row.get(5)?,
+            base_level: row.get(6)?,
+            job_level: row.get(7)?,
+            base_exp: base_exp.max(0) as u64,
+            job_exp: job_exp.max(0) as u64,
+            zeny: row.get(10)?,
+            stats: Stats {
+                str: row.get(11)?,
+                agi: row.get(12)?,
+                vit: row.get(13)?,
+                int: row.get(14)?,
+                dex: row.get(15)?,
+                luk: row.get(16)?,
+            },
+            hp: row.get(17)?,
+            max_hp: row.get(18)?,
+            sp: row.get(19)?,
+            max_sp: row.get(20)?,
+            status_points: row.get(21)?,
+            skill_points: row.get(22)?,
+            look: Look {
+                hair: row.get(23)?,
+                hair_color: row.get(24)?,
+                clothes_color: row.get(25)?,
+                weapon: row.get(26)?,
+                shield: row.get(27)?,
+                head_top: row.get(28)?,
+                head_mid: row.get(29)?,
+                head_bottom: row.get(30)?,
+                robe: row.get(31)?,
+            },
+            position: Position {
+                map: row.get(32)?,
+                x: row.get(33)?,
+                y: row.get(34)?,
+            },
+            save_point: Position {
+                map: row.get(35)?,
+                x: row.get(36)?,
+                y: row.get(37)?,
+            },
+        })
+    }
+
+    fn write(connection: &Connection, statement: &str, character: &Character) -> rusqlite::Result<usize> {
+        connection.execute(
+            statement,
+            params![
+                character.char_id,
+                character.account_id,
+                character.slot,
+                character.name,
+                character.sex.as_char().to_string(),
+                character.class,
+                character.base_level,
+                character.job_level,
+                character.base_exp.min(i64::MAX as u64) as i64,
+                character.job_exp.min(i64::MAX as u64) as i64,
+                character.zeny,
+                character.stats.str,
+                character.stats.agi,
+                character.stats.vit,
+                character.stats.int,
+                character.stats.dex,
+                character.stats.luk,
+                character.hp,
+                character.max_hp,
+                character.sp,
+                character.max_sp,
+                character.status_points,
+                character.skill_points,
+                character.look.hair,
+                character.look.hair_color,
+                character.look.clothes_color,
+                character.look.weapon,
+                character.look.shield,
+                character.look.head_top,
+                character.look.head_mid,
+                character.look.head_bottom,
+                character.look.robe,
+                character.position.map,
+                character.position.x,
+                character.position.y,
+                character.save_point.map,
+                character.save_point.x,
+                character.save_point.y,
+            ],
+        )
+    }
+}
+
+impl CharacterStore for SqliteStore {
+    fn find(&self, char_id: u32) -> Result<Option<Character>, StoreError> {
+        let query = format!("SELECT {} FROM char WHERE char_id = ?1", COLUMNS);
+        Ok(self.connection().query_row(&query, [char_id], Self::character_from_row).optional()?)
+    }
+
+    fn characters_of(&self, account_id: u32) -> Result<Vec<Character>, StoreError> {
+        let connection = self.connection();
+        let mut statement = connection.prepare(&format!("SELECT {} FROM char WHERE account_id = ?1 ORDER BY char_num", COLUMNS))?;
+        let characters = statement.query_map([account_id], Self::character_from_row)?.collect::<rusqlite::Result<_>>()?;
+        Ok(characters)
+    }
+
+    fn create(&self, character: NewCharacter) -> Result<Character, StoreError> {
+        let mut connection = self.connection();
+        let transaction = connection.transaction()?;
+
+        let name_taken: bool =
+            transaction.query_row("SELECT EXISTS(SELECT 1 FROM char WHERE name = ?1)", [&character.name], |row| row.get(0))?;
+        if name_taken {
+            return Err(StoreError::NameTaken(character.name));
+        }
+        let slot_taken: bool = transaction.query_row(
+            "SELECT EXISTS(SELECT 1 FROM char WHERE account_id = ?1 AND char_num = ?2)",
+            params![character.account_id, character.slot],
+            |row| row.get(0),
+        )?;
+        if slot_taken {
+            return Err(StoreError::SlotTaken(character.slot));
+        }
+
+        let char_id: u32 =
+            transaction.query_row("SELECT COALESCE(MAX(char_id) + 1, ?1) FROM char", [START_CHAR_ID], |row| row.get(0))?;
+        let character = character.into_character(char_id);
+        let placeholders = (1..=38).map(|n| format!("?{}", n)).collect::<Vec<_>>().join(", ");
+        Self::write(&transaction, &format!("INSERT INTO char ({}) VALUES ({})", COLUMNS, placeholders), &character)?;
+        transaction.commit()?;
+
+        Ok(character)
+    }
+
+    fn update(&self, character: &Character) -> Result<(), StoreError> {
+        let assignments = COLUMNS
+            .split(',')
+            .map(str::trim)
+            .enumerate()
+            .skip(1)
+            .map(|(n, column)| format!("{} = ?{}", column, n + 1))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let updated = Self::write(&self.connection(), &format!("UPDATE char SET {} WHERE char_id = ?1", assignments), character)?;
+        if updated == 0 {
+            return Err(StoreError::NotFound(character.char_id));
+        }
+        Ok(())
+    }
+
+    fn delete(&self, char_id: u32) -> Result<bool, StoreError> {
+        Ok(self.connection().execute("DELETE FROM char WHERE char_id = ?1", [char_id])? > 0)
+    }
+}
@@ -0,0 +1,97 @@
+use std::net::SocketAddrV4;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{validate_public_address, CharServerType, InterConfig, LogConfig, NetworkConfig, ServerConfig};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CharConfig {
+    pub log: LogConfig,
+    pub network: NetworkConfig,
+    /// Link to the login server.
+    pub inter: InterConfig,
+    pub char: CharSection,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CharSection {
+    /// Shown in the server list.
+    pub name: String,
+    pub server_type: CharServerType,
+    /// Shows the server with a "new" mark.
+    pub new: bool,
+    /// Sent to the login server for players to connect to, `network.bind`
+    /// being of no use to them behind a NAT or on `0.0.0.0`.
+    pub public_address: SocketAddrV4,
+    pub characters_db: PathBuf,
+    /// Where map servers connect, apart from the players.
+    pub inter_bind: SocketAddrV4,
+    /// Where players are sent while no map server is connected.
+    pub map_server: SocketAddrV4,
+    pub start_map: String,
+    pub start_x: u16,
+    pub start_y: u16,
+    /// Players type it to delete a character, in place of an e-mail.
+    pub delete_key: String,
+}
+
+impl Default for CharConfig {
+    fn default() -> Self {
+        Self {
+            log: LogConfig::default(),
+            network: NetworkConfig::new(SocketAddrV4::new([127, 0, 0, 1].into(), 6121), "char_packets.txt"),
+            inter: InterConfig::new("127.0.0.1:6901"),
+            char: CharSection {
+                name: "Einbroch".to_string(),
+                server_type: CharServerType::Normal,
+                new: false,
+                public_address: SocketAddrV4::new([127, 0, 0, 1].into(), 6121),
+                characters_db: PathBuf::from("characters.db"),
+                inter_bind: SocketAddrV4::new([127, 0, 0, 1].into(), 6122),
+                map_server: SocketAddrV4::new([127, 0, 0, 1].into(), 5121),
+                start_map: "new_1-1".to_string(),
+                start_x: 53,
+                start_y: 111,
+                delete_key: "a@a.com".to_string(),
+            },
+        }
+    }
+}
+
+impl ServerConfig for CharConfig {
+    const ENV_PREFIX: &'static str = "EINBROCH_CHAR";
+
+    fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        self.log.validate(&mut problems);
+        self.network.validate(&mut problems);
+        self.inter.validate(&mut problems);
+        // Both sent in 20 byte fields, map names with their extension.
+        if self.char.name.is_empty() || self.char.name.len() > 20 {
+            problems.push("char.name must be 1 to 20 bytes long".to_string());
+        }
+        validate_public_address("char.public_address", self.char.public_address, &mut problems);
+        if self.char.start_map.is_empty() || self.char.start_map.len() > 11 {
+            problems.push("char.start_map must be 1 to 11 bytes long".to_string());
+        }
+        // Sent in a 40 byte field.
+        if self.char.delete_key.is_empty() || self.char.delete_key.len() > 40 {
+            problems.push("char.delete_key must be 1 to 40 bytes long".to_string());
+        }
+        problems
+    }
+
+    fn restart_needed(&self, new: &Self) -> Vec<&'static str> {
+        let mut changed = Vec::new();
+        self.log.restart_needed(&new.log, &mut changed);
+        self.network.restart_needed(&new.network, &mut changed);
+        self.inter.restart_needed(&new.inter, &mut changed);
+        if self.char != new.char {
+            changed.push("char");
+        }
+        changed
+    }
+}
@@ -0,0 +1,233 @@
+use std::fmt::{self, Display};
+use std::io;
+use std::net::SocketAddrV4;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use network::{LinkConfig, ReactorConfig};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+mod char;
+mod log;
+mod login;
+mod map;
+mod source;
+
+pub use char::{CharConfig, CharSection};
+pub use log::{LogConfig, LogFormat, LogHandle};
+pub use login::{LoginConfig, LoginSection, StaticCharServer};
+pub use map::{MapConfig, MapSection};
+pub use source::ConfigSource;
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io { path: PathBuf, error: io::Error },
+    Parse { path: PathBuf, reason: String },
+    /// A value set in the environment or on the command line.
+    Override { key: String, reason: String },
+    /// The layers merged into something that isn't a valid configuration.
+    Type(String),
+    Invalid(Vec<String>),
+    Usage(String),
+    Log(String),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, error } => write!(f, "couldn't read '{}': {}", path.display(), error),
+            ConfigError::Parse { path, reason } => write!(f, "couldn't parse '{}': {}", path.display(), reason),
+            ConfigError::Override { key, reason } => write!(f, "couldn't override '{}': {}", key, reason),
+            ConfigError::Type(reason) => write!(f, "invalid configuration: {}", reason),
+            ConfigError::Invalid(problems) => write!(f, "invalid configuration: {}", problems.join(", ")),
+            ConfigError::Usage(usage) => write!(f, "{}", usage),
+            ConfigError::Log(reason) => write!(f, "couldn't set up logging: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Configuration of one of the servers, every field being set by its
+/// `Default` until a layer overrides it.
+pub trait ServerConfig: Default + Serialize + DeserializeOwned + Send + 'static {
+    /// Environment variables starting with `<ENV_PREFIX>__` override the
+    /// files, `EINBROCH_LOGIN__NETWORK__THREADS` setting `network.threads`.
+    const ENV_PREFIX: &'static str;
+
+    /// Every problem found, empty when the configuration can be used.
+    fn validate(&self) -> Vec<String>;
+
+    /// Settings only read at startup which differ in `new`.
+    fn restart_needed(&self, new: &Self) -> Vec<&'static str>;
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NetworkConfig {
+    pub bind: SocketAddrV4,
+    /// Network threads sessions are spread over.
+    pub threads: usize,
+    /// Seconds without traffic before a session is dropped, 0 never drops it.
+    pub idle_timeout: u64,
+    /// Seconds a closing session has to flush what it was sent.
+    pub close_timeout: u64,
+    pub packet_table: PathBuf,
+    /// Client version to parse packets for, the newest when unset.
+    pub packetver: Option<u32>,
+}
+
+impl NetworkConfig {
+    pub fn new(bind: SocketAddrV4, packet_table: &str) -> Self {
+        let reactor = ReactorConfig::default();
+        Self {
+            bind,
+            threads: reactor.workers,
+            idle_timeout: reactor.idle_timeout.map_or(0, |timeout| timeout.as_secs()),
+            close_timeout: reactor.close_timeout.as_secs(),
+            packet_table: PathBuf::from(packet_table),
+            packetver: None,
+        }
+    }
+
+    pub fn reactor_config(&self) -> ReactorConfig {
+        ReactorConfig {
+            workers: self.threads,
+            idle_timeout: (self.idle_timeout > 0).then(|| Duration::from_secs(self.idle_timeout)),
+            close_timeout: Duration::from_secs(self.close_timeout),
+            ..ReactorConfig::default()
+        }
+    }
+
+    fn validate(&self, problems: &mut Vec<String>) {
+        if !(1..=256).contains(&self.threads) {
+            problems.push(format!("network.threads must be between 1 and 256, not {}", self.threads));
+        }
+        if self.close_timeout == 0 {
+            problems.push("network.close_timeout can't be 0".to_string());
+        }
+        if !self.packet_table.is_file() {
+            problems.push(format!("network.packet_table '{}' doesn't exist", self.packet_table.display()));
+        }
+    }
+
+    fn restart_needed(&self, new: &Self, changed: &mut Vec<&'static str>) {
+        if self != new {
+            changed.push("network");
+        }
+    }
+}
+
+/// Label shown next to a char server in the server list, sent as its
+/// `repr` value.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[repr(u16)]
+pub enum CharServerType {
+    #[default]
+    Normal = 0,
+    Maintenance = 1,
+    Over18 = 2,
+    Paying = 3,
+    FreeToPlay = 4,
+    /// Players can attack each other outside of the PvP maps.
+    Pk = 5,
+    Pvp = 6,
+}
+
+impl CharServerType {
+    /// Unknown values, from newer char servers, are shown as normal.
+    pub fn from_u16(value: u16) -> Self {
+        match value {
+            1 => CharServerType::Maintenance,
+            2 => CharServerType::Over18,
+            3 => CharServerType::Paying,
+            4 => CharServerType::FreeToPlay,
+            5 => CharServerType::Pk,
+            6 => CharServerType::Pvp,
+            _ => CharServerType::Normal,
+        }
+    }
+}
+
+/// Link from the char or map server to the server it registers with.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct InterConfig {
+    /// Shared by every server of the cluster.
+    pub secret: String,
+    /// Server to register with.
+    pub connect: String,
+    /// Seconds between two connection attempts.
+    pub reconnect_delay: u64,
+    pub keepalive_interval: u64,
+    /// Seconds without hearing from the other server before reconnecting,
+    /// 0 never gives up on it.
+    pub timeout: u64,
+    /// Seconds between two user counts sent to the other server.
+    pub user_count_interval: u64,
+}
+
+impl InterConfig {
+    pub fn new(connect: &str) -> Self {
+        let link = LinkConfig::default();
+        Self {
+            secret: String::new(),
+            connect: connect.to_string(),
+            reconnect_delay: link.reconnect_delay.as_secs(),
+            keepalive_interval: link.keepalive_interval.as_secs(),
+            timeout: link.timeout.map_or(0, |timeout| timeout.as_secs()),
+            user_count_interval: 10,
+        }
+    }
+
+    pub fn link_config(&self, keepalive: Vec<u8>) -> LinkConfig {
+        LinkConfig {
+            reconnect_delay: Duration::from_secs(self.reconnect_delay),
+            keepalive: Some(keepalive),
+            keepalive_interval: Duration::from_secs(self.keepalive_interval),
+            timeout: (self.timeout > 0).then(|| Duration::from_secs(self.timeout)),
+        }
+    }
+
+    pub fn user_count_interval(&self) -> Duration {
+        Duration::from_secs(self.user_count_interval)
+    }
+
+    fn validate(&self, problems: &mut Vec<String>) {
+        validate_secret("inter.secret", &self.secret, problems);
+        if self.secret.is_empty() {
+            problems.push("inter.secret must be set".to_string());
+        }
+        if self.connect.is_empty() {
+            problems.push("inter.connect must be set".to_string());
+        }
+        if self.keepalive_interval == 0 || self.user_count_interval == 0 {
+            problems.push("inter.keepalive_interval and inter.user_count_interval can't be 0".to_string());
+        }
+        if self.timeout > 0 && self.timeout <= self.keepalive_interval {
+            problems.push("inter.timeout must be longer than inter.keepalive_interval".to_string());
+        }
+    }
+
+    fn restart_needed(&self, new: &Self, changed: &mut Vec<&'static str>) {
+        if self != new {
+            changed.push("inter");
+        }
+    }
+}
+
+/// Addresses players are sent to, which can't be the unspecified one.
+fn validate_public_address(key: &str, addr: SocketAddrV4, problems: &mut Vec<String>) {
+    if addr.ip().is_unspecified() || addr.port() == 0 {
+        problems.push(format!("{} must be an address players can connect to, not {}", key, addr));
+    }
+}
+
+/// Secrets are sent in a 32 byte field.
+fn validate_secret(key: &str, secret: &str, problems: &mut Vec<String>) {
+    if secret.len() > 32 {
+        problems.push(format!("{} can't be longer than 32 bytes", key));
+    }
+}
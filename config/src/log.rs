@@ -0,0 +1,72 @@
+use std::io::IsTerminal;
+
+use serde::{Deserialize, Serialize};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, reload, EnvFilter, Registry};
+
+use crate::ConfigError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    /// One object per line, with the fields of the session and packet spans.
+    Json,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LogConfig {
+    /// `info`, or per crate like `info,network=debug,systems::map=trace`.
+    pub level: String,
+    pub format: LogFormat,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".to_string(),
+            format: LogFormat::Text,
+        }
+    }
+}
+
+/// Changes the level of the logger installed by `LogConfig::init`.
+#[derive(Debug, Clone)]
+pub struct LogHandle(reload::Handle<EnvFilter, Registry>);
+
+impl LogHandle {
+    pub fn set_level(&self, level: &str) -> Result<(), ConfigError> {
+        let filter = EnvFilter::try_new(level).map_err(|err| ConfigError::Log(err.to_string()))?;
+        self.0.reload(filter).map_err(|err| ConfigError::Log(err.to_string()))
+    }
+}
+
+impl LogConfig {
+    /// Installs the logger of the process, fails if there already is one.
+    pub fn init(&self) -> Result<LogHandle, ConfigError> {
+        let filter = EnvFilter::try_new(&self.level).map_err(|err| ConfigError::Log(err.to_string()))?;
+        let (filter, handle) = reload::Layer::new(filter);
+        let registry = tracing_subscriber::registry().with(filter);
+
+        let result = match self.format {
+            LogFormat::Text => registry.with(fmt::layer().with_ansi(std::io::stdout().is_terminal())).try_init(),
+            LogFormat::Json => registry.with(fmt::layer().json().with_current_span(true).with_span_list(true)).try_init(),
+        };
+        result.map_err(|err| ConfigError::Log(err.to_string()))?;
+        Ok(LogHandle(handle))
+    }
+
+    pub(crate) fn validate(&self, problems: &mut Vec<String>) {
+        if let Err(err) = EnvFilter::try_new(&self.level) {
+            problems.push(format!("log.level '{}' is invalid: {}", self.level, err));
+        }
+    }
+
+    pub(crate) fn restart_needed(&self, new: &Self, changed: &mut Vec<&'static str>) {
+        if self.format != new.format {
+            changed.push("log.format");
+        }
+    }
+}
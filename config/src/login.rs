@@ -0,0 +1,112 @@
+use std::net::SocketAddrV4;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{validate_secret, CharServerType, LogConfig, NetworkConfig, ServerConfig};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LoginConfig {
+    pub log: LogConfig,
+    pub network: NetworkConfig,
+    pub login: LoginSection,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LoginSection {
+    pub accounts_db: PathBuf,
+    /// Threads checking passwords, apart from the network threads.
+    pub login_threads: usize,
+    /// Where char servers connect, apart from the players.
+    pub inter_bind: SocketAddrV4,
+    /// Char servers register with it, none can while it is empty.
+    pub inter_secret: String,
+    pub allow_legacy_md5: bool,
+    /// Accepts passwords stored in clear, see `conf/login.toml`.
+    pub allow_plain_passwords: bool,
+    /// Incorrect passwords in a row that lock an account, 0 never locks.
+    pub max_failed_attempts: u32,
+    /// Seconds a locked account stays locked, 0 until an admin unlocks it.
+    pub lock_duration: u64,
+    /// Listed while no char server is connected.
+    pub char_servers: Vec<StaticCharServer>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StaticCharServer {
+    pub name: String,
+    pub address: SocketAddrV4,
+    #[serde(default)]
+    pub server_type: CharServerType,
+    /// Shows the server with a "new" mark.
+    #[serde(default)]
+    pub new: bool,
+}
+
+impl Default for LoginConfig {
+    fn default() -> Self {
+        Self {
+            log: LogConfig::default(),
+            network: NetworkConfig::new(SocketAddrV4::new([127, 0, 0, 1].into(), 6900), "auth_packets.txt"),
+            login: LoginSection {
+                accounts_db: PathBuf::from("accounts.db"),
+                login_threads: 2,
+                inter_bind: SocketAddrV4::new([127, 0, 0, 1].into(), 6901),
+                inter_secret: String::new(),
+                allow_legacy_md5: false,
+                allow_plain_passwords: false,
+                max_failed_attempts: 5,
+                lock_duration: 15 * 60,
+                char_servers: vec![StaticCharServer {
+                    name: "Einbroch".to_string(),
+                    address: SocketAddrV4::new([127, 0, 0, 1].into(), 6121),
+                    server_type: CharServerType::Normal,
+                    new: false,
+                }],
+            },
+        }
+    }
+}
+
+impl ServerConfig for LoginConfig {
+    const ENV_PREFIX: &'static str = "EINBROCH_LOGIN";
+
+    fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        self.log.validate(&mut problems);
+        self.network.validate(&mut problems);
+        if !(1..=64).contains(&self.login.login_threads) {
+            problems.push(format!("login.login_threads must be between 1 and 64, not {}", self.login.login_threads));
+        }
+        validate_secret("login.inter_secret", &self.login.inter_secret, &mut problems);
+        for server in &self.login.char_servers {
+            // Sent in a 20 byte field.
+            if server.name.is_empty() || server.name.len() > 20 {
+                problems.push(format!("login.char_servers name '{}' must be 1 to 20 bytes long", server.name));
+            }
+        }
+        problems
+    }
+
+    fn restart_needed(&self, new: &Self) -> Vec<&'static str> {
+        let mut changed = Vec::new();
+        self.log.restart_needed(&new.log, &mut changed);
+        self.network.restart_needed(&new.network, &mut changed);
+        if self.login.accounts_db != new.login.accounts_db {
+            changed.push("login.accounts_db");
+        }
+        if self.login.login_threads != new.login.login_threads {
+            changed.push("login.login_threads");
+        }
+        if self.login.inter_bind != new.login.inter_bind {
+            changed.push("login.inter_bind");
+        }
+        if self.login.inter_secret != new.login.inter_secret {
+            changed.push("login.inter_secret");
+        }
+        changed
+    }
+}
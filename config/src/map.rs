@@ -0,0 +1,88 @@
+use std::net::SocketAddrV4;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{validate_public_address, InterConfig, LogConfig, NetworkConfig, ServerConfig};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MapConfig {
+    pub log: LogConfig,
+    pub network: NetworkConfig,
+    /// Link to the char server.
+    pub inter: InterConfig,
+    pub map: MapSection,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MapSection {
+    /// Sent to the char server for players to connect to, `network.bind`
+    /// being of no use to them behind a NAT or on `0.0.0.0`.
+    pub public_address: SocketAddrV4,
+    pub characters_db: PathBuf,
+    /// An archive, or a client `data.ini` listing them by priority.
+    pub grf: PathBuf,
+    /// Directory with a loose `data` directory read before the archives.
+    pub overlay: Option<PathBuf>,
+    /// Maps served here, every map of the GRF when empty.
+    pub maps: Vec<String>,
+}
+
+impl Default for MapConfig {
+    fn default() -> Self {
+        Self {
+            log: LogConfig::default(),
+            network: NetworkConfig::new(SocketAddrV4::new([127, 0, 0, 1].into(), 5121), "map_packets.txt"),
+            inter: InterConfig::new("127.0.0.1:6122"),
+            map: MapSection {
+                public_address: SocketAddrV4::new([127, 0, 0, 1].into(), 5121),
+                characters_db: PathBuf::from("characters.db"),
+                grf: PathBuf::from("data.grf"),
+                overlay: None,
+                maps: Vec::new(),
+            },
+        }
+    }
+}
+
+impl MapSection {
+    /// `None` to load every map of the GRF.
+    pub fn map_names(&self) -> Option<&[String]> {
+        (!self.maps.is_empty()).then_some(self.maps.as_slice())
+    }
+}
+
+impl ServerConfig for MapConfig {
+    const ENV_PREFIX: &'static str = "EINBROCH_MAP";
+
+    fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        self.log.validate(&mut problems);
+        self.network.validate(&mut problems);
+        self.inter.validate(&mut problems);
+        validate_public_address("map.public_address", self.map.public_address, &mut problems);
+        if !self.map.grf.is_file() {
+            problems.push(format!("map.grf '{}' doesn't exist", self.map.grf.display()));
+        }
+        if let Some(overlay) = self.map.overlay.as_ref().filter(|overlay| !overlay.is_dir()) {
+            problems.push(format!("map.overlay '{}' isn't a directory", overlay.display()));
+        }
+        if self.map.maps.iter().any(|name| name.trim().is_empty()) {
+            problems.push("map.maps can't list empty names".to_string());
+        }
+        problems
+    }
+
+    fn restart_needed(&self, new: &Self) -> Vec<&'static str> {
+        let mut changed = Vec::new();
+        self.log.restart_needed(&new.log, &mut changed);
+        self.network.restart_needed(&new.network, &mut changed);
+        self.inter.restart_needed(&new.inter, &mut changed);
+        if self.map != new.map {
+            changed.push("map");
+        }
+        changed
+    }
+}
@@ -0,0 +1,389 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use toml::{Table, Value};
+use tracing::{info, warn};
+
+use crate::{ConfigError, ServerConfig};
+
+/// Files imported from imported files, deeper ones are most likely a loop.
+const MAX_IMPORT_DEPTH: usize = 8;
+
+const USAGE: &str = "options: --config <path> --set <key>=<value>";
+
+/// Where a configuration is loaded from, in order:
+/// - the defaults of the configuration type,
+/// - the config file, then the files listed in its `import` key,
+///   rAthena style, missing ones being skipped,
+/// - environment variables, `<ENV_PREFIX>__NETWORK__THREADS=4`,
+/// - `--set network.threads=4` on the command line.
+#[derive(Debug, Clone)]
+pub struct ConfigSource {
+    path: PathBuf,
+    overrides: Vec<(String, String)>,
+    /// Every file read by the last load, missing imports included, with
+    /// when they were modified.
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+impl ConfigSource {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            overrides: Vec::new(),
+            files: Vec::new(),
+        }
+    }
+
+    /// Takes `--config` and `--set` out of `args`, reading `default_path`
+    /// unless `--config` is given. Returns the other arguments.
+    pub fn from_args(default_path: &str, args: &[String]) -> Result<(Self, Vec<String>), ConfigError> {
+        let mut source = Self::new(default_path);
+        let mut rest = Vec::new();
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--config" => {
+                    let path = args.next().ok_or_else(|| ConfigError::Usage(USAGE.to_string()))?;
+                    source.path = PathBuf::from(path);
+                },
+                "--set" => {
+                    let assignment = args.next().ok_or_else(|| ConfigError::Usage(USAGE.to_string()))?;
+                    let (key, value) = assignment.split_once('=').ok_or_else(|| ConfigError::Usage(USAGE.to_string()))?;
+                    source.overrides.push((key.trim().to_string(), value.trim().to_string()));
+                },
+                _ => rest.push(arg.clone()),
+            }
+        }
+
+        Ok((source, rest))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn load<T: ServerConfig>(&mut self) -> Result<T, ConfigError> {
+        let mut files = Vec::new();
+        let mut table = Table::try_from(T::default()).map_err(|err| ConfigError::Type(err.to_string()))?;
+
+        let file = read_file(&self.path, &mut files)?.ok_or_else(|| ConfigError::Io {
+            path: self.path.clone(),
+            error: std::io::ErrorKind::NotFound.into(),
+        })?;
+        merge_file(&mut table, &self.path, file, 0, &mut files)?;
+
+        let prefix = format!("{}__", T::ENV_PREFIX);
+        let mut vars: Vec<(String, String)> = std::env::vars()
+            .filter_map(|(name, value)| {
+                let key = name.strip_prefix(&prefix)?.split("__").collect::<Vec<_>>().join(".").to_lowercase();
+                Some((key, value))
+            })
+            .collect();
+        vars.sort();
+        for (key, value) in vars.iter().chain(&self.overrides) {
+            set_key(&mut table, key, value)?;
+        }
+
+        let config: T = Value::Table(table).try_into().map_err(|err: toml::de::Error| ConfigError::Type(err.message().to_string()))?;
+        let problems = config.validate();
+        if !problems.is_empty() {
+            return Err(ConfigError::Invalid(problems));
+        }
+
+        self.files = files;
+        Ok(config)
+    }
+
+    fn changed(&self) -> bool {
+        self.files.iter().any(|(path, modified)| modified_at(path) != *modified)
+    }
+
+    /// Loads the configuration again whenever one of its files changes and
+    /// hands it to `on_reload`. Changes which can't be applied to `current`
+    /// without a restart are reported, and invalid ones ignored.
+    pub fn watch<T, F>(mut self, current: T, interval: Duration, on_reload: F)
+    where
+        T: ServerConfig,
+        F: Fn(&T) + Send + 'static,
+    {
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            if !self.changed() {
+                continue;
+            }
+
+            match self.load::<T>() {
+                Ok(config) => {
+                    info!(path = %self.path.display(), "Reloaded the configuration");
+                    let restart_needed = current.restart_needed(&config);
+                    if !restart_needed.is_empty() {
+                        warn!(settings = %restart_needed.join(", "), "Restart to apply the changes");
+                    }
+                    on_reload(&config);
+                },
+                Err(err) => {
+                    // Not reading the same broken files again until they change.
+                    self.files.iter_mut().for_each(|(path, modified)| *modified = modified_at(path));
+                    warn!(%err, "Keeping the current configuration");
+                },
+            }
+        });
+    }
+}
+
+fn modified_at(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// `None` if the file doesn't exist, which is still watched.
+fn read_file(path: &Path, files: &mut Vec<(PathBuf, Option<SystemTime>)>) -> Result<Option<Table>, ConfigError> {
+    files.push((path.to_path_buf(), modified_at(path)));
+
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(ConfigError::Io { path: path.to_path_buf(), error }),
+    };
+    text.parse::<Table>()
+        .map(Some)
+        .map_err(|err| ConfigError::Parse { path: path.to_path_buf(), reason: err.to_string() })
+}
+
+/// Merges `file` and then what it imports, relative to its directory.
+fn merge_file(table: &mut Table, path: &Path, mut file: Table, depth: usize, files: &mut Vec<(PathBuf, Option<SystemTime>)>) -> Result<(), ConfigError> {
+    let imports = match file.remove("import") {
+        None => Vec::new(),
+        Some(Value::String(import)) => vec![import],
+        Some(Value::Array(imports)) => imports
+            .into_iter()
+            .map(|import| match import {
+                Value::String(import) => Ok(import),
+                _ => Err(ConfigError::Parse { path: path.to_path_buf(), reason: "import must list file names".to_string() }),
+            })
+            .collect::<Result<_, _>>()?,
+        Some(_) => return Err(ConfigError::Parse { path: path.to_path_buf(), reason: "import must be a file name or a list of them".to_string() }),
+    };
+
+    merge(table, file);
+
+    if !imports.is_empty() && depth == MAX_IMPORT_DEPTH {
+        return Err(ConfigError::Parse { path: path.to_path_buf(), reason: "too many nested imports".to_string() });
+    }
+    let dir = path.parent().unwrap_or(Path::new(""));
+    for import in imports {
+        let import = dir.join(import);
+        if let Some(file) = read_file(&import, files)? {
+            merge_file(table, &import, file, depth + 1, files)?;
+        }
+    }
+    Ok(())
+}
+
+/// Tables are merged key by key, anything else is replaced.
+fn merge(table: &mut Table, other: Table) {
+    for (key, value) in other {
+        match (table.get_mut(&key), value) {
+            (Some(Value::Table(existing)), Value::Table(value)) => merge(existing, value),
+            (_, value) => {
+                table.insert(key, value);
+            },
+        }
+    }
+}
+
+/// Sets the value at a dotted `key`. Values replacing strings are kept as
+/// they are, anything else is read as TOML so numbers and lists work.
+fn set_key(table: &mut Table, key: &str, raw: &str) -> Result<(), ConfigError> {
+    let error = |reason: &str| ConfigError::Override { key: key.to_string(), reason: reason.to_string() };
+    let (parents, name) = match key.rsplit_once('.') {
+        Some((parents, name)) => (parents.split('.').collect(), name),
+        None => (Vec::new(), key),
+    };
+
+    let mut target = table;
+    for parent in parents {
+        target = match target.entry(parent).or_insert_with(|| Value::Table(Table::new())) {
+            Value::Table(table) => table,
+            _ => return Err(error(&format!("'{}' isn't a section", parent))),
+        };
+    }
+
+    let value = match target.get(name) {
+        Some(Value::String(_)) => Value::String(raw.to_string()),
+        _ => format!("value = {}", raw)
+            .parse::<Table>()
+            .ok()
+            .and_then(|mut parsed| parsed.remove("value"))
+            .unwrap_or_else(|| Value::String(raw.to_string())),
+    };
+    target.insert(name.to_string(), value);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::{CharConfig, LoginConfig};
+
+    /// Each layer sets its own key and the ones of the layers after it.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct TestConfig {
+        layers: Layers,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct Layers {
+        default: String,
+        file: String,
+        import: String,
+        env: String,
+        set: String,
+        inner: Inner,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct Inner {
+        depth: u32,
+        names: Vec<String>,
+    }
+
+    impl Default for TestConfig {
+        fn default() -> Self {
+            let default = "default".to_string();
+            Self {
+                layers: Layers {
+                    default: default.clone(),
+                    file: default.clone(),
+                    import: default.clone(),
+                    env: default.clone(),
+                    set: default,
+                    inner: Inner { depth: 0, names: Vec::new() },
+                },
+            }
+        }
+    }
+
+    impl ServerConfig for TestConfig {
+        const ENV_PREFIX: &'static str = "EINBROCH_SOURCE_TEST";
+
+        fn validate(&self) -> Vec<String> {
+            Vec::new()
+        }
+
+        fn restart_needed(&self, _new: &Self) -> Vec<&'static str> {
+            Vec::new()
+        }
+    }
+
+    /// A directory of its own for `name`, with `files` written in it.
+    fn write_files(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("einbroch-config-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for (file, text) in files {
+            fs::write(dir.join(file), text).unwrap();
+        }
+        dir
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn layers_are_applied_in_order() {
+        let dir = write_files("layers", &[
+            ("main.toml", "import = [\"import.toml\", \"missing.toml\"]\n[layers]\nfile = \"file\"\nimport = \"file\"\nenv = \"file\"\nset = \"file\"\n"),
+            ("import.toml", "[layers]\nimport = \"import\"\nenv = \"import\"\nset = \"import\"\n"),
+        ]);
+        // Only this test sets variables with this prefix.
+        std::env::set_var("EINBROCH_SOURCE_TEST__LAYERS__ENV", "env");
+        std::env::set_var("EINBROCH_SOURCE_TEST__LAYERS__SET", "env");
+        std::env::set_var("EINBROCH_SOURCE_TEST__LAYERS__INNER__DEPTH", "3");
+
+        let path = dir.join("main.toml");
+        let (mut source, rest) = ConfigSource::from_args("unused.toml", &args(&[
+            "--config", path.to_str().unwrap(), "serve", "--set", "layers.set = set", "--set", "layers.inner.names=[\"a\", \"b\"]",
+        ])).unwrap();
+        assert_eq!(rest, ["serve"]);
+        let config: TestConfig = source.load().unwrap();
+
+        let layers = &config.layers;
+        assert_eq!(
+            [&layers.default, &layers.file, &layers.import, &layers.env, &layers.set].map(String::as_str),
+            ["default", "file", "import", "env", "set"],
+        );
+        assert_eq!(layers.inner, Inner { depth: 3, names: vec!["a".to_string(), "b".to_string()] });
+        // The missing import is watched too, in case it gets created.
+        assert_eq!(source.files.iter().map(|(path, _)| path.file_name().unwrap()).collect::<Vec<_>>(), ["main.toml", "import.toml", "missing.toml"]);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn strings_set_on_the_command_line_stay_strings() {
+        let dir = write_files("strings", &[("main.toml", "")]);
+        let path = dir.join("main.toml");
+        let (mut source, _) = ConfigSource::from_args(path.to_str().unwrap(), &args(&["--set", "layers.file=42"])).unwrap();
+        let config: TestConfig = source.load().unwrap();
+        assert_eq!(config.layers.file, "42");
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn bad_overrides_are_refused() {
+        for bad in [&["--set"][..], &["--set", "layers.file"], &["--config"]] {
+            assert!(matches!(ConfigSource::from_args("main.toml", &args(bad)), Err(ConfigError::Usage(_))), "{:?}", bad);
+        }
+
+        let dir = write_files("overrides", &[("main.toml", "")]);
+        let path = dir.join("main.toml");
+        let load = |set: &str| {
+            let (mut source, _) = ConfigSource::from_args(path.to_str().unwrap(), &args(&["--set", set])).unwrap();
+            source.load::<TestConfig>()
+        };
+        assert!(matches!(load("layers.file.deeper=1"), Err(ConfigError::Override { .. })));
+        assert!(matches!(load("layers.inner.depth=deep"), Err(ConfigError::Type(_))));
+        assert!(matches!(load("layers.unknown=1"), Err(ConfigError::Type(_))));
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn broken_files_are_refused() {
+        let dir = write_files("broken", &[("main.toml", "import = \"broken.toml\"\n"), ("broken.toml", "[layers\n")]);
+        let mut source = ConfigSource::new(dir.join("main.toml"));
+        assert!(matches!(source.load::<TestConfig>(), Err(ConfigError::Parse { .. })));
+
+        let mut source = ConfigSource::new(dir.join("missing.toml"));
+        assert!(matches!(source.load::<TestConfig>(), Err(ConfigError::Io { .. })));
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn restart_needed_lists_startup_settings() {
+        let current = LoginConfig::default();
+        let mut new = current.clone();
+        new.log.level = "debug".to_string();
+        new.login.max_failed_attempts += 1;
+        assert!(current.restart_needed(&new).is_empty());
+
+        new.network.threads += 1;
+        new.login.login_threads += 1;
+        new.login.inter_bind.set_port(7000);
+        assert_eq!(current.restart_needed(&new), ["network", "login.login_threads", "login.inter_bind"]);
+
+        let current = CharConfig::default();
+        let mut new = current.clone();
+        new.inter.secret = "changed".to_string();
+        new.char.start_x += 1;
+        assert_eq!(current.restart_needed(&new), ["inter", "char"]);
+    }
+}
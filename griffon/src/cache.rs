@@ -0,0 +1,112 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Decompressed entries, the least recently read ones being dropped once
+/// they take more than `capacity` bytes.
+#[derive(Debug, Default)]
+pub(crate) struct EntryCache {
+    capacity: usize,
+    size: usize,
+    entries: HashMap<PathBuf, (Arc<Vec<u8>>, u64)>,
+    /// Paths by when they were last read.
+    recent: BTreeMap<u64, PathBuf>,
+    tick: u64,
+}
+
+impl EntryCache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            ..Self::default()
+        }
+    }
+
+    pub(crate) fn get(&mut self, path: &Path) -> Option<Arc<Vec<u8>>> {
+        self.tick += 1;
+        let (data, last_read) = self.entries.get_mut(path)?;
+
+        let path = self.recent.remove(last_read).expect("cached entries are in recent");
+        self.recent.insert(self.tick, path);
+        *last_read = self.tick;
+
+        Some(data.clone())
+    }
+
+    /// Entries larger than the whole cache aren't kept.
+    pub(crate) fn insert(&mut self, path: &Path, data: Arc<Vec<u8>>) {
+        if data.len() > self.capacity {
+            return;
+        }
+
+        self.tick += 1;
+        self.size += data.len();
+        if let Some((previous, last_read)) = self.entries.insert(path.to_path_buf(), (data, self.tick)) {
+            self.size -= previous.len();
+            self.recent.remove(&last_read);
+        }
+        self.recent.insert(self.tick, path.to_path_buf());
+
+        while self.size > self.capacity {
+            let Some((_, oldest)) = self.recent.pop_first() else {
+                break;
+            };
+            if let Some((data, _)) = self.entries.remove(&oldest) {
+                self.size -= data.len();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(len: usize) -> Arc<Vec<u8>> {
+        Arc::new(vec![0; len])
+    }
+
+    fn cached(cache: &mut EntryCache, path: &str) -> bool {
+        cache.get(Path::new(path)).is_some()
+    }
+
+    #[test]
+    fn oldest_entries_are_evicted_first() {
+        let mut cache = EntryCache::new(10);
+        cache.insert(Path::new("a"), data(4));
+        cache.insert(Path::new("b"), data(4));
+        cache.insert(Path::new("c"), data(4));
+
+        assert!(!cached(&mut cache, "a"));
+        assert!(cached(&mut cache, "b"));
+        assert!(cached(&mut cache, "c"));
+        assert_eq!(cache.size, 8);
+    }
+
+    #[test]
+    fn reads_keep_entries() {
+        let mut cache = EntryCache::new(10);
+        cache.insert(Path::new("a"), data(4));
+        cache.insert(Path::new("b"), data(4));
+        assert!(cached(&mut cache, "a"));
+        cache.insert(Path::new("c"), data(4));
+
+        assert!(cached(&mut cache, "a"));
+        assert!(!cached(&mut cache, "b"));
+        assert!(cached(&mut cache, "c"));
+    }
+
+    #[test]
+    fn replaced_and_oversized_entries() {
+        let mut cache = EntryCache::new(10);
+        cache.insert(Path::new("a"), data(4));
+        cache.insert(Path::new("a"), data(6));
+        assert_eq!((cache.size, cache.entries.len(), cache.recent.len()), (6, 1, 1));
+
+        cache.insert(Path::new("b"), data(11));
+        assert!(!cached(&mut cache, "b"));
+        assert!(cached(&mut cache, "a"));
+    }
+}
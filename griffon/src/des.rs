@@ -0,0 +1,343 @@
+//! The DES variant Gravity uses in GRF archives: a single round without a
+//! key, between the standard initial and final permutations. The round
+//! leaves the right half alone, so decrypting and encrypting are the same.
+
+const MASK: [u8; 8] = [0x80, 0x40, 0x20, 0x10, 0x08, 0x04, 0x02, 0x01];
+
+const IP_TABLE: [u8; 64] = [
+    58, 50, 42, 34, 26, 18, 10, 2,
+    60, 52, 44, 36, 28, 20, 12, 4,
+    62, 54, 46, 38, 30, 22, 14, 6,
+    64, 56, 48, 40, 32, 24, 16, 8,
+    57, 49, 41, 33, 25, 17, 9, 1,
+    59, 51, 43, 35, 27, 19, 11, 3,
+    61, 53, 45, 37, 29, 21, 13, 5,
+    63, 55, 47, 39, 31, 23, 15, 7,
+];
+
+const FP_TABLE: [u8; 64] = [
+    40, 8, 48, 16, 56, 24, 64, 32,
+    39, 7, 47, 15, 55, 23, 63, 31,
+    38, 6, 46, 14, 54, 22, 62, 30,
+    37, 5, 45, 13, 53, 21, 61, 29,
+    36, 4, 44, 12, 52, 20, 60, 28,
+    35, 3, 43, 11, 51, 19, 59, 27,
+    34, 2, 42, 10, 50, 18, 58, 26,
+    33, 1, 41, 9, 49, 17, 57, 25,
+];
+
+/// P-box applied to the output of the S-boxes.
+const TP_TABLE: [u8; 32] = [
+    16, 7, 20, 21, 29, 12, 28, 17,
+    1, 15, 23, 26, 5, 18, 31, 10,
+    2, 8, 24, 14, 32, 27, 3, 9,
+    19, 13, 30, 6, 22, 11, 4, 25,
+];
+
+/// The eight standard S-boxes, by row and column.
+const S_BOXES: [[[u8; 16]; 4]; 8] = [
+    [
+        [14, 4, 13, 1, 2, 15, 11, 8, 3, 10, 6, 12, 5, 9, 0, 7],
+        [0, 15, 7, 4, 14, 2, 13, 1, 10, 6, 12, 11, 9, 5, 3, 8],
+        [4, 1, 14, 8, 13, 6, 2, 11, 15, 12, 9, 7, 3, 10, 5, 0],
+        [15, 12, 8, 2, 4, 9, 1, 7, 5, 11, 3, 14, 10, 0, 6, 13],
+    ],
+    [
+        [15, 1, 8, 14, 6, 11, 3, 4, 9, 7, 2, 13, 12, 0, 5, 10],
+        [3, 13, 4, 7, 15, 2, 8, 14, 12, 0, 1, 10, 6, 9, 11, 5],
+        [0, 14, 7, 11, 10, 4, 13, 1, 5, 8, 12, 6, 9, 3, 2, 15],
+        [13, 8, 10, 1, 3, 15, 4, 2, 11, 6, 7, 12, 0, 5, 14, 9],
+    ],
+    [
+        [10, 0, 9, 14, 6, 3, 15, 5, 1, 13, 12, 7, 11, 4, 2, 8],
+        [13, 7, 0, 9, 3, 4, 6, 10, 2, 8, 5, 14, 12, 11, 15, 1],
+        [13, 6, 4, 9, 8, 15, 3, 0, 11, 1, 2, 12, 5, 10, 14, 7],
+        [1, 10, 13, 0, 6, 9, 8, 7, 4, 15, 14, 3, 11, 5, 2, 12],
+    ],
+    [
+        [7, 13, 14, 3, 0, 6, 9, 10, 1, 2, 8, 5, 11, 12, 4, 15],
+        [13, 8, 11, 5, 6, 15, 0, 3, 4, 7, 2, 12, 1, 10, 14, 9],
+        [10, 6, 9, 0, 12, 11, 7, 13, 15, 1, 3, 14, 5, 2, 8, 4],
+        [3, 15, 0, 6, 10, 1, 13, 8, 9, 4, 5, 11, 12, 7, 2, 14],
+    ],
+    [
+        [2, 12, 4, 1, 7, 10, 11, 6, 8, 5, 3, 15, 13, 0, 14, 9],
+        [14, 11, 2, 12, 4, 7, 13, 1, 5, 0, 15, 10, 3, 9, 8, 6],
+        [4, 2, 1, 11, 10, 13, 7, 8, 15, 9, 12, 5, 6, 3, 0, 14],
+        [11, 8, 12, 7, 1, 14, 2, 13, 6, 15, 0, 9, 10, 4, 5, 3],
+    ],
+    [
+        [12, 1, 10, 15, 9, 2, 6, 8, 0, 13, 3, 4, 14, 7, 5, 11],
+        [10, 15, 4, 2, 7, 12, 9, 5, 6, 1, 13, 14, 0, 11, 3, 8],
+        [9, 14, 15, 5, 2, 8, 12, 3, 7, 0, 4, 10, 1, 13, 11, 6],
+        [4, 3, 2, 12, 9, 5, 15, 10, 11, 14, 1, 7, 6, 0, 8, 13],
+    ],
+    [
+        [4, 11, 2, 14, 15, 0, 8, 13, 3, 12, 9, 7, 5, 10, 6, 1],
+        [13, 0, 11, 7, 4, 9, 1, 10, 14, 3, 5, 12, 2, 15, 8, 6],
+        [1, 4, 11, 13, 12, 3, 7, 14, 10, 15, 6, 8, 0, 5, 9, 2],
+        [6, 11, 13, 8, 1, 4, 10, 7, 9, 5, 0, 15, 14, 2, 3, 12],
+    ],
+    [
+        [13, 2, 8, 4, 6, 15, 11, 1, 10, 9, 3, 14, 5, 0, 12, 7],
+        [1, 15, 13, 8, 10, 3, 7, 4, 12, 5, 6, 11, 0, 14, 9, 2],
+        [7, 11, 4, 1, 9, 12, 14, 2, 0, 6, 10, 13, 15, 3, 5, 8],
+        [2, 1, 14, 7, 4, 10, 8, 13, 15, 12, 9, 0, 3, 5, 6, 11],
+    ],
+];
+
+fn permute(block: &[u8; 8], table: &[u8; 64]) -> [u8; 8] {
+    let mut out = [0u8; 8];
+    for (i, &bit) in table.iter().enumerate() {
+        let j = (bit - 1) as usize;
+        if block[j >> 3] & MASK[j & 7] != 0 {
+            out[i >> 3] |= MASK[i & 7];
+        }
+    }
+    out
+}
+
+fn s_box(n: usize, input: u8) -> u8 {
+    let row = ((input >> 4) & 0x02) | (input & 0x01);
+    let column = (input >> 1) & 0x0f;
+    S_BOXES[n][row as usize][column as usize]
+}
+
+/// Mixes the right half of the block into its left half.
+fn round(block: &mut [u8; 8]) {
+    let r = &block[4..];
+
+    // Expansion of the right half into eight groups of six bits.
+    let expanded = [
+        ((r[3] << 5) | (r[0] >> 3)) & 0x3f,
+        ((r[0] << 1) | (r[1] >> 7)) & 0x3f,
+        ((r[0] << 5) | (r[1] >> 3)) & 0x3f,
+        ((r[1] << 1) | (r[2] >> 7)) & 0x3f,
+        ((r[1] << 5) | (r[2] >> 3)) & 0x3f,
+        ((r[2] << 1) | (r[3] >> 7)) & 0x3f,
+        ((r[2] << 5) | (r[3] >> 3)) & 0x3f,
+        ((r[3] << 1) | (r[0] >> 7)) & 0x3f,
+    ];
+
+    let mut substituted = [0u8; 4];
+    for (i, byte) in substituted.iter_mut().enumerate() {
+        *byte = (s_box(i * 2, expanded[i * 2]) << 4) | s_box(i * 2 + 1, expanded[i * 2 + 1]);
+    }
+
+    let mut transposed = [0u8; 4];
+    for (i, &bit) in TP_TABLE.iter().enumerate() {
+        let j = (bit - 1) as usize;
+        if substituted[j >> 3] & MASK[j & 7] != 0 {
+            transposed[i >> 3] |= MASK[i & 7];
+        }
+    }
+
+    for (left, mixed) in block[..4].iter_mut().zip(transposed) {
+        *left ^= mixed;
+    }
+}
+
+pub fn decrypt_block(block: &mut [u8; 8]) {
+    let mut permuted = permute(block, &IP_TABLE);
+    round(&mut permuted);
+    *block = permute(&permuted, &FP_TABLE);
+}
+
+/// Names in the file table of 0x1xx archives have the nibbles of every byte
+/// swapped before being encrypted.
+pub fn decode_file_name(data: &mut [u8]) {
+    for chunk in data.chunks_exact_mut(8) {
+        for byte in chunk.iter_mut() {
+            *byte = byte.rotate_left(4);
+        }
+        let block: &mut [u8; 8] = chunk.try_into().expect("chunks of 8 bytes");
+        decrypt_block(block);
+    }
+}
+
+/// Blocks at the start of an entry which are always encrypted.
+const ENCRYPTED_HEADER_BLOCKS: usize = 20;
+
+/// Decrypts an entry one part after the other, the parts being whole
+/// blocks following each other.
+#[derive(Debug, Clone)]
+pub struct Decrypter {
+    /// `None` for entries only the start of which is encrypted.
+    cycle: Option<usize>,
+    /// Blocks decrypted so far.
+    block_count: usize,
+    shuffle_count: usize,
+}
+
+impl Decrypter {
+    pub fn header() -> Self {
+        Self {
+            cycle: None,
+            block_count: 0,
+            shuffle_count: 0,
+        }
+    }
+
+    /// Entries encrypted all along: past the header, one block in `cycle` is
+    /// encrypted and every seventh of the others shuffled, the first one
+    /// after eight. `compressed_size` is the size before alignment.
+    pub fn mixed(compressed_size: u32) -> Self {
+        let digits = compressed_size.max(1).ilog10() as usize + 1;
+        let cycle = match digits {
+            0..3 => 1,
+            3..5 => digits + 1,
+            5..7 => digits + 9,
+            _ => digits + 15,
+        };
+
+        Self {
+            cycle: Some(cycle),
+            block_count: 0,
+            shuffle_count: 0,
+        }
+    }
+
+    /// Decrypts every complete block of `data`.
+    pub fn decrypt(&mut self, data: &mut [u8]) {
+        for chunk in data.chunks_exact_mut(8) {
+            let block: &mut [u8; 8] = chunk.try_into().expect("chunks of 8 bytes");
+            let i = self.block_count;
+            self.block_count += 1;
+
+            if i < ENCRYPTED_HEADER_BLOCKS {
+                decrypt_block(block);
+                continue;
+            }
+
+            let Some(cycle) = self.cycle else {
+                continue;
+            };
+            if i.is_multiple_of(cycle) {
+                decrypt_block(block);
+                continue;
+            }
+
+            if self.shuffle_count == 7 {
+                unshuffle_block(block);
+                self.shuffle_count = 0;
+            }
+            self.shuffle_count += 1;
+        }
+    }
+}
+
+fn unshuffle_block(block: &mut [u8; 8]) {
+    let src = *block;
+    *block = [src[3], src[4], src[6], src[0], src[1], src[2], src[5], substitute(src[7])];
+}
+
+fn substitute(byte: u8) -> u8 {
+    match byte {
+        0x00 => 0x2b,
+        0x2b => 0x00,
+        0x01 => 0x68,
+        0x68 => 0x01,
+        0x48 => 0x77,
+        0x77 => 0x48,
+        0x60 => 0xff,
+        0xff => 0x60,
+        0x6c => 0x80,
+        0x80 => 0x6c,
+        0xb9 => 0xc0,
+        0xc0 => 0xb9,
+        0xeb => 0xfe,
+        0xfe => 0xeb,
+        other => other,
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Inverse of `unshuffle_block`.
+    fn shuffle_block(block: &mut [u8; 8]) {
+        let src = *block;
+        *block = [src[3], src[4], src[5], src[0], src[1], src[6], src[2], substitute(src[7])];
+    }
+
+    /// Encrypts `data` so that `decrypter` gives it back.
+    pub(crate) fn encrypt(data: &mut [u8], decrypter: &Decrypter) {
+        let mut shuffle_count = 0;
+        for (i, chunk) in data.chunks_exact_mut(8).enumerate() {
+            let block: &mut [u8; 8] = chunk.try_into().expect("chunks of 8 bytes");
+            if i < ENCRYPTED_HEADER_BLOCKS {
+                decrypt_block(block);
+                continue;
+            }
+
+            let Some(cycle) = decrypter.cycle else {
+                continue;
+            };
+            if i.is_multiple_of(cycle) {
+                decrypt_block(block);
+                continue;
+            }
+
+            if shuffle_count == 7 {
+                shuffle_block(block);
+                shuffle_count = 0;
+            }
+            shuffle_count += 1;
+        }
+    }
+
+    /// Swaps the nibbles after encrypting, see `decode_file_name`.
+    pub(crate) fn encode_file_name(data: &mut [u8]) {
+        for chunk in data.chunks_exact_mut(8) {
+            let block: &mut [u8; 8] = chunk.try_into().expect("chunks of 8 bytes");
+            decrypt_block(block);
+            for byte in block.iter_mut() {
+                *byte = byte.rotate_left(4);
+            }
+        }
+    }
+
+    #[test]
+    fn block_known_answers() {
+        // From a bit by bit implementation of the DES tables.
+        let vectors = [
+            ([0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], [0x04, 0x04, 0x01, 0x55, 0x55, 0x01, 0x54, 0x55]),
+            ([0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef], [0x51, 0x76, 0x05, 0x76, 0x98, 0xea, 0xd9, 0xeb]),
+        ];
+
+        for (plain, encrypted) in vectors {
+            let mut block = plain;
+            decrypt_block(&mut block);
+            assert_eq!(block, encrypted);
+            decrypt_block(&mut block);
+            assert_eq!(block, plain);
+        }
+    }
+
+    #[test]
+    fn file_name_round_trip() {
+        let mut name = *b"data\\prontera.gat\0\0\0\0\0\0\0";
+        encode_file_name(&mut name);
+        assert_ne!(&name[..17], b"data\\prontera.gat");
+        decode_file_name(&mut name);
+        assert_eq!(&name[..18], b"data\\prontera.gat\0");
+    }
+
+    #[test]
+    fn mixed_decryption_is_split_anywhere() {
+        let plain: Vec<u8> = (0..8 * 400).map(|i| (i * 7 % 251) as u8).collect();
+        let mut encrypted = plain.clone();
+        encrypt(&mut encrypted, &Decrypter::mixed(plain.len() as u32));
+        assert_ne!(encrypted, plain);
+
+        for part in [8, 24, 8 * 64, plain.len()] {
+            let mut decrypter = Decrypter::mixed(plain.len() as u32);
+            let mut data = encrypted.clone();
+            for chunk in data.chunks_mut(part) {
+                decrypter.decrypt(chunk);
+            }
+            assert_eq!(data, plain, "decrypted {} bytes at a time", part);
+        }
+    }
+}
@@ -3,12 +3,31 @@ use std::{
     collections::HashMap,
     fs::File,
     io::{self, Cursor, Read, Seek},
+    ops::BitOr,
     path::{Path, PathBuf},
+    sync::{Arc, Mutex},
 };
 
+use cache::EntryCache;
+
+mod cache;
+mod des;
+mod reader;
+mod set;
+mod thor;
+mod writer;
+
+pub use reader::EntryReader;
+pub use set::GrfSet;
+pub use thor::{ThorEntry, ThorPatch};
+pub use writer::GrfWriter;
+
 const HEADER_SIZE: usize = 46;
 const HEADER_MAGIC_STRING: &str = "Master of Magic\0";
-const SUPPORTED_VERSION: u32 = 0x200;
+const SUPPORTED_VERSIONS: [u32; 4] = [0x102, 0x103, 0x200, 0x300];
+
+/// Extensions of the files only the start of which is encrypted in 0x1xx archives.
+const HEADER_ENCRYPTED_EXTENSIONS: [&str; 4] = ["gnd", "gat", "act", "str"];
 
 #[derive(Debug)]
 pub enum GrfError {
@@ -33,7 +52,9 @@ impl From<yazi::Error> for GrfError {
 #[derive(Default, Debug)]
 pub struct GrfHeader {
     pub encription_key: String,
-    pub file_table_offset: u32,
+    /// From the end of the header. 0x300 archives use 64 bits for it and
+    /// don't have a seed.
+    pub file_table_offset: u64,
     pub seed: u32,
     pub files_count: u32,
     pub version: u32,
@@ -45,17 +66,69 @@ impl GrfHeader {
 
         fd.by_ref().take(14).read_to_string(&mut header.encription_key)?;
 
-        header.file_table_offset = fd.read_u32::<LittleEndian>()?;
-        header.seed = fd.read_u32::<LittleEndian>()?;
-        header.files_count = fd.read_u32::<LittleEndian>()?;
-        header.version = fd.read_u32::<LittleEndian>()?;
+        // The layout of the rest depends on the version, which comes last.
+        let mut rest = [0_u8; 16];
+        fd.read_exact(&mut rest)?;
+        let mut rdr = Cursor::new(&rest[..]);
+        header.version = u32::from_le_bytes([rest[12], rest[13], rest[14], rest[15]]);
 
-        if header.version != SUPPORTED_VERSION {
+        if !SUPPORTED_VERSIONS.contains(&header.version) {
             return Err(GrfError::UnsupportedVersion(header.version));
         }
 
+        if header.version >= 0x300 {
+            header.file_table_offset = rdr.read_u64::<LittleEndian>()?;
+        } else {
+            header.file_table_offset = rdr.read_u32::<LittleEndian>()? as u64;
+            header.seed = rdr.read_u32::<LittleEndian>()?;
+        }
+        header.files_count = rdr.read_u32::<LittleEndian>()?;
+
         Ok(header)
     }
+
+    /// Files in the archive, as the count is stored with an offset.
+    pub fn real_files_count(&self) -> u32 {
+        self.files_count.wrapping_sub(self.seed).wrapping_sub(7)
+    }
+}
+
+/// What an entry of the file table is and how it is encrypted. The
+/// encryption of 0x1xx archives is implied by file extensions.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GrfFileFlags(u8);
+
+impl GrfFileFlags {
+    /// Not set for directories.
+    pub const FILE: Self = Self(0x01);
+    /// Encrypted all along, see `des::decrypt_mixed`.
+    pub const ENCRYPT_MIXED: Self = Self(0x02);
+    /// Only the first blocks are encrypted.
+    pub const ENCRYPT_HEADER: Self = Self(0x04);
+
+    pub fn from_bits(bits: u8) -> Self {
+        Self(bits)
+    }
+
+    pub fn bits(self) -> u8 {
+        self.0
+    }
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn is_encrypted(self) -> bool {
+        self.0 & (Self::ENCRYPT_MIXED.0 | Self::ENCRYPT_HEADER.0) != 0
+    }
+}
+
+impl BitOr for GrfFileFlags {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
 }
 
 #[derive(Default, Debug)]
@@ -65,8 +138,21 @@ pub struct GrfFileEntry {
     pub compressed_size: u32,
     pub compressed_size_aligned: u32,
     pub uncompressed_size: u32,
-    pub flags: u8,
-    pub offset: u32,
+    pub flags: GrfFileFlags,
+    /// From the end of the header.
+    pub offset: u64,
+}
+
+impl GrfFileEntry {
+    pub(crate) fn decrypter(&self) -> Option<des::Decrypter> {
+        if self.flags.contains(GrfFileFlags::ENCRYPT_MIXED) {
+            Some(des::Decrypter::mixed(self.compressed_size))
+        } else if self.flags.contains(GrfFileFlags::ENCRYPT_HEADER) {
+            Some(des::Decrypter::header())
+        } else {
+            None
+        }
+    }
 }
 
 #[derive(Default, Debug)]
@@ -78,9 +164,22 @@ pub struct GrfFileTable {
 
 impl GrfFileTable {
     pub fn from_bytes(fd: &mut File, header: &GrfHeader) -> Result<Self, GrfError> {
+        fd.seek(io::SeekFrom::Start(HEADER_SIZE as u64 + header.file_table_offset))?;
+
+        match header.version {
+            0x102 | 0x103 => Self::from_legacy_bytes(fd),
+            _ => Self::from_compressed_bytes(fd, header),
+        }
+    }
+
+    /// 0x200 and 0x300 tables are compressed, 0x300 ones having 64 bit offsets.
+    fn from_compressed_bytes(fd: &mut File, header: &GrfHeader) -> Result<Self, GrfError> {
         let mut files_table = Self::default();
 
-        fd.seek(io::SeekFrom::Current(header.file_table_offset as i64))?;
+        if header.version >= 0x300 {
+            // Unused, always 0.
+            fd.read_u32::<LittleEndian>()?;
+        }
 
         files_table.compressed_size = fd.read_u32::<LittleEndian>()?;
         files_table.uncompressed_size = fd.read_u32::<LittleEndian>()?;
@@ -97,7 +196,7 @@ impl GrfFileTable {
         while rdr.get_ref().len() >= rdr.position() as usize {
             let file_name_opt = Self::read_file_name(&mut rdr);
 
-            if let None = file_name_opt {
+            if file_name_opt.is_none() {
                 break;
             }
 
@@ -110,34 +209,92 @@ impl GrfFileTable {
             file_entry.compressed_size = rdr.read_u32::<LittleEndian>()?;
             file_entry.compressed_size_aligned = rdr.read_u32::<LittleEndian>()?;
             file_entry.uncompressed_size = rdr.read_u32::<LittleEndian>()?;
-            file_entry.flags = rdr.read_u8()?;
-            file_entry.offset = rdr.read_u32::<LittleEndian>()?;
+            file_entry.flags = GrfFileFlags::from_bits(rdr.read_u8()?);
+            file_entry.offset = if header.version >= 0x300 {
+                rdr.read_u64::<LittleEndian>()?
+            } else {
+                rdr.read_u32::<LittleEndian>()? as u64
+            };
+
+            files_table.insert(file_entry);
+        }
+
+        Ok(files_table)
+    }
+
+    /// 0x1xx tables run to the end of the file, uncompressed. Each record is
+    /// the length of the name area, two unused bytes, the encrypted name and
+    /// the entry, its sizes stored with offsets.
+    fn from_legacy_bytes(fd: &mut File) -> Result<Self, GrfError> {
+        let mut files_table = Self::default();
+
+        let mut buf = Vec::new();
+        fd.read_to_end(&mut buf)?;
+        files_table.compressed_size = buf.len() as u32;
+        files_table.uncompressed_size = buf.len() as u32;
+
+        let mut rdr = Cursor::new(buf);
+        while (rdr.position() as usize) < rdr.get_ref().len() {
+            let name_len = rdr.read_u32::<LittleEndian>()? as usize;
+            let start = rdr.position() as usize;
+            let name_area = rdr.get_ref()
+                .get(start..start + name_len)
+                .ok_or_else(|| GrfError::InvalidData("File name runs past the end of the file table.".to_string()))?;
+
+            let mut file_name_bytes = name_area.get(2..).unwrap_or_default().to_vec();
+            des::decode_file_name(&mut file_name_bytes);
+            if let Some(end) = file_name_bytes.iter().position(|&byte| byte == 0) {
+                file_name_bytes.truncate(end);
+            }
+            rdr.set_position((start + name_len) as u64);
 
-            let file_path = file_entry.file_name.replace("\\", "/");
+            let compressed_size = rdr.read_u32::<LittleEndian>()?;
+            let compressed_size_aligned = rdr.read_u32::<LittleEndian>()?;
+            let uncompressed_size = rdr.read_u32::<LittleEndian>()?;
+            let flags = GrfFileFlags::from_bits(rdr.read_u8()?);
+            let offset = rdr.read_u32::<LittleEndian>()?;
 
-            files_table
-                .files
-                .insert(Path::new(&file_path).to_path_buf(), file_entry);
+            if !flags.contains(GrfFileFlags::FILE) {
+                continue;
+            }
+
+            let file_name: String = file_name_bytes.iter().map(|&c| c as char).collect();
+            let header_encrypted = file_name
+                .rsplit_once('.')
+                .is_some_and(|(_, extension)| HEADER_ENCRYPTED_EXTENSIONS.iter().any(|ext| ext.eq_ignore_ascii_case(extension)));
+
+            files_table.insert(GrfFileEntry {
+                file_name,
+                file_name_bytes,
+                compressed_size: compressed_size.wrapping_sub(uncompressed_size).wrapping_sub(715),
+                compressed_size_aligned: compressed_size_aligned.wrapping_sub(37579),
+                uncompressed_size,
+                flags: flags | if header_encrypted { GrfFileFlags::ENCRYPT_HEADER } else { GrfFileFlags::ENCRYPT_MIXED },
+                offset: offset as u64,
+            });
         }
 
         Ok(files_table)
     }
 
+    fn insert(&mut self, file_entry: GrfFileEntry) {
+        let file_path = file_entry.file_name.replace("\\", "/");
+
+        self.files
+            .insert(Path::new(&file_path).to_path_buf(), file_entry);
+    }
+
     fn read_file_name<R: Read>(reader: &mut R) -> Option<(String, Vec<u8>)> {
         let mut string_bytes = Vec::new();
 
-        reader
-            .by_ref()
-            .bytes()
-            .take_while(|byte| match byte {
-                Ok(byte) => *byte != 0x00_u8,
-                Err(_) => false,
-            })
-            .for_each(|byte| string_bytes.push(byte.unwrap()));
+        let mut byte = [0_u8; 1];
+        while reader.read_exact(&mut byte).is_ok() && byte[0] != 0x00_u8 {
+            string_bytes.push(byte[0]);
+        }
 
         let file_name: String = string_bytes.iter().map(|&c| c as char).collect();
 
-        if file_name == "" {
+        if file_name.is_empty() {
             return None;
         }
 
@@ -150,6 +307,7 @@ pub struct Grf {
     pub file_handle: File,
     pub header: GrfHeader,
     pub files_table: GrfFileTable,
+    cache: Option<Mutex<EntryCache>>,
 }
 
 impl Grf {
@@ -171,6 +329,7 @@ impl Grf {
             file_handle: fd,
             header,
             files_table,
+            cache: None,
         })
     }
 
@@ -187,25 +346,306 @@ impl Grf {
         Ok(())
     }
 
-    pub fn get_file_from_path(&mut self, path: &Path) -> Result<(&GrfFileEntry, Cursor<Vec<u8>>), GrfError> {
-        let file_entry = self.files_table
+    /// Keeps up to `capacity` bytes of the entries read by `read_file`, 0
+    /// not to keep any.
+    pub fn set_cache_capacity(&mut self, capacity: usize) {
+        self.cache = (capacity > 0).then(|| Mutex::new(EntryCache::new(capacity)));
+    }
+
+    pub fn get_file_entry(&self, path: &Path) -> Result<&GrfFileEntry, GrfError> {
+        self.files_table
             .files
-            .get(&path.to_path_buf())
-            .ok_or_else(|| GrfError::Other(io::Error::new(
-                io::ErrorKind::Other,
+            .get(path)
+            .ok_or_else(|| GrfError::Other(io::Error::other(
                 format!("File not found in the file table '{}'", path.display()),
-            )))?;
+            )))
+    }
 
-        self.file_handle.seek(io::SeekFrom::Start(file_entry.offset as u64 + HEADER_SIZE as u64))?;
-        let reader = {
-            let mut buf = vec![0_u8; file_entry.compressed_size as usize];
-            self.file_handle.read_exact(&mut buf)?;
+    pub fn get_file_from_path(&self, path: &Path) -> Result<(&GrfFileEntry, Cursor<Vec<u8>>), GrfError> {
+        let file_entry = self.get_file_entry(path)?;
 
-            let (buf, _) = yazi::decompress(&buf, yazi::Format::Zlib)?;
+        let data = match &self.cache {
+            Some(_) => self.read_file(path)?.as_ref().clone(),
+            None => read_entry(&self.file_handle, file_entry)?,
+        };
 
-            Cursor::new(buf)
+        Ok((file_entry, Cursor::new(data)))
+    }
+
+    /// Reads the whole entry, through the cache when there is one.
+    pub fn read_file(&self, path: &Path) -> Result<Arc<Vec<u8>>, GrfError> {
+        let Some(cache) = &self.cache else {
+            return Ok(Arc::new(read_entry(&self.file_handle, self.get_file_entry(path)?)?));
         };
 
-        Ok((&file_entry, reader))
+        if let Some(data) = cache.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).get(path) {
+            return Ok(data);
+        }
+
+        // Not holding the lock while reading, another thread may read the
+        // same entry meanwhile.
+        let data = Arc::new(read_entry(&self.file_handle, self.get_file_entry(path)?)?);
+        cache.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).insert(path, data.clone());
+        Ok(data)
+    }
+
+    /// Decompresses the entry as it is read, for large ones. Bypasses the cache.
+    pub fn open_entry(&self, path: &Path) -> Result<EntryReader<'_>, GrfError> {
+        Ok(EntryReader::new(&self.file_handle, self.get_file_entry(path)?))
+    }
+}
+
+/// Reads at `offset` without moving the cursor of `fd`, so threads can
+/// share it.
+pub(crate) fn read_exact_at(fd: &File, buf: &mut [u8], offset: u64) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::FileExt::read_exact_at(fd, buf, offset)
+    }
+
+    #[cfg(windows)]
+    {
+        let mut buf = buf;
+        let mut offset = offset;
+        while !buf.is_empty() {
+            match std::os::windows::fs::FileExt::seek_read(fd, buf, offset) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(read) => {
+                    buf = &mut buf[read..];
+                    offset += read as u64;
+                },
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {},
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads the data of an entry as stored in the archive, padding included.
+pub(crate) fn read_raw_entry(fd: &File, file_entry: &GrfFileEntry) -> Result<Vec<u8>, GrfError> {
+    // Encryption works on whole blocks, hence the alignment.
+    let size = if file_entry.flags.is_encrypted() { file_entry.compressed_size_aligned } else { file_entry.compressed_size };
+    let mut buf = vec![0_u8; size as usize];
+    read_exact_at(fd, &mut buf, file_entry.offset + HEADER_SIZE as u64)?;
+
+    Ok(buf)
+}
+
+pub(crate) fn read_entry(fd: &File, file_entry: &GrfFileEntry) -> Result<Vec<u8>, GrfError> {
+    let mut buf = read_raw_entry(fd, file_entry)?;
+
+    if let Some(mut decrypter) = file_entry.decrypter() {
+        decrypter.decrypt(&mut buf);
+    }
+
+    let compressed = buf.get(..file_entry.compressed_size as usize).ok_or_else(|| GrfError::InvalidData(
+        format!("Compressed size of '{}' is past its aligned size", file_entry.file_name),
+    ))?;
+    let (buf, _) = yazi::decompress(compressed, yazi::Format::Zlib)?;
+
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use byteorder::WriteBytesExt;
+    use std::fs;
+
+    /// An entry as stored in the data of an archive.
+    struct Stored {
+        name: &'static str,
+        /// Encryption, written to the table of 0x200 and 0x300 archives only.
+        flags: GrfFileFlags,
+        compressed_size: u32,
+        uncompressed_size: u32,
+        /// Aligned and encrypted.
+        data: Vec<u8>,
+    }
+
+    fn store(name: &'static str, data: &[u8], flags: GrfFileFlags) -> Stored {
+        let mut compressed = yazi::compress(data, yazi::Format::Zlib, yazi::CompressionLevel::Default).unwrap();
+        let compressed_size = compressed.len() as u32;
+        compressed.resize(compressed.len().next_multiple_of(8), 0);
+
+        let file_entry = GrfFileEntry { compressed_size, flags, ..GrfFileEntry::default() };
+        if let Some(decrypter) = file_entry.decrypter() {
+            des::tests::encrypt(&mut compressed, &decrypter);
+        }
+
+        Stored {
+            name,
+            flags,
+            compressed_size,
+            uncompressed_size: data.len() as u32,
+            data: compressed,
+        }
+    }
+
+    /// Bytes zlib can't shrink.
+    fn noise(len: usize) -> Vec<u8> {
+        let mut state = 0x2545_f491_u32;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("griffon-{}-{}.grf", std::process::id(), name))
+    }
+
+    /// Writes the header, the entries one after the other and `table`.
+    fn write_archive(name: &str, version: u32, entries: &[Stored], table: &[u8]) -> PathBuf {
+        let data: Vec<u8> = entries.iter().flat_map(|entry| entry.data.iter().copied()).collect();
+
+        let mut buf = HEADER_MAGIC_STRING.as_bytes().to_vec();
+        buf.extend_from_slice(&[0; 14]);
+        if version >= 0x300 {
+            buf.write_u64::<LittleEndian>(data.len() as u64).unwrap();
+        } else {
+            buf.write_u32::<LittleEndian>(data.len() as u32).unwrap();
+            buf.write_u32::<LittleEndian>(0).unwrap();
+        }
+        buf.write_u32::<LittleEndian>(entries.len() as u32 + 7).unwrap();
+        buf.write_u32::<LittleEndian>(version).unwrap();
+        buf.extend_from_slice(&data);
+        buf.extend_from_slice(table);
+
+        let path = temp_path(name);
+        fs::write(&path, buf).unwrap();
+        path
+    }
+
+    /// Table of a 0x200 or 0x300 archive, `offsets` overriding where the
+    /// entries are said to be.
+    fn compressed_table(version: u32, entries: &[Stored], offsets: &[u64]) -> Vec<u8> {
+        let mut records = Vec::new();
+        for (entry, &offset) in entries.iter().zip(offsets) {
+            records.extend_from_slice(entry.name.as_bytes());
+            records.write_u8(0).unwrap();
+            records.write_u32::<LittleEndian>(entry.compressed_size).unwrap();
+            records.write_u32::<LittleEndian>(entry.data.len() as u32).unwrap();
+            records.write_u32::<LittleEndian>(entry.uncompressed_size).unwrap();
+            records.write_u8((GrfFileFlags::FILE | entry.flags).bits()).unwrap();
+            if version >= 0x300 {
+                records.write_u64::<LittleEndian>(offset).unwrap();
+            } else {
+                records.write_u32::<LittleEndian>(offset as u32).unwrap();
+            }
+        }
+        let compressed = yazi::compress(&records, yazi::Format::Zlib, yazi::CompressionLevel::Default).unwrap();
+
+        let mut table = Vec::new();
+        if version >= 0x300 {
+            table.write_u32::<LittleEndian>(0).unwrap();
+        }
+        table.write_u32::<LittleEndian>(compressed.len() as u32).unwrap();
+        table.write_u32::<LittleEndian>(records.len() as u32).unwrap();
+        table.extend_from_slice(&compressed);
+        table
+    }
+
+    /// Table of a 0x1xx archive, with a directory record before the entries.
+    fn legacy_table(entries: &[Stored]) -> Vec<u8> {
+        let mut table = Vec::new();
+        let mut offset = 0;
+        let records = std::iter::once(("data", 0, 0, 0, GrfFileFlags::default()))
+            .chain(entries.iter().map(|entry| {
+                (entry.name, entry.compressed_size, entry.data.len() as u32, entry.uncompressed_size, GrfFileFlags::FILE)
+            }));
+
+        for (name, compressed_size, aligned_size, uncompressed_size, flags) in records {
+            let mut name = format!("{}\0", name).into_bytes();
+            name.resize(name.len().next_multiple_of(8), 0);
+            des::tests::encode_file_name(&mut name);
+
+            table.write_u32::<LittleEndian>(name.len() as u32 + 2).unwrap();
+            table.extend_from_slice(&[0; 2]);
+            table.extend_from_slice(&name);
+            table.write_u32::<LittleEndian>(compressed_size + uncompressed_size + 715).unwrap();
+            table.write_u32::<LittleEndian>(aligned_size + 37579).unwrap();
+            table.write_u32::<LittleEndian>(uncompressed_size).unwrap();
+            table.write_u8(flags.bits()).unwrap();
+            table.write_u32::<LittleEndian>(offset).unwrap();
+            offset += aligned_size;
+        }
+        table
+    }
+
+    fn read_both(grf: &Grf, path: &str) -> Vec<u8> {
+        let (_, reader) = grf.get_file_from_path(Path::new(path)).unwrap();
+        let mut streamed = Vec::new();
+        grf.open_entry(Path::new(path)).unwrap().read_to_end(&mut streamed).unwrap();
+        assert!(reader.get_ref() == &streamed, "'{}' reads differently when streamed", path);
+        streamed
+    }
+
+    #[test]
+    fn encrypted_entries_over_a_chunk() {
+        let big = noise(200 * 1024);
+        let entries = [
+            store("data\\mixed.bin", &big, GrfFileFlags::ENCRYPT_MIXED),
+            store("data\\header.gat", &big, GrfFileFlags::ENCRYPT_HEADER),
+        ];
+        assert!(entries.iter().all(|entry| entry.data.len() > reader::CHUNK_SIZE * 2));
+        let offsets = [0, entries[0].data.len() as u64];
+        let path = write_archive("chunks", 0x200, &entries, &compressed_table(0x200, &entries, &offsets));
+
+        let grf = Grf::from_path(&path).unwrap();
+        assert!(read_both(&grf, "data/mixed.bin") == big);
+        assert!(read_both(&grf, "data/header.gat") == big);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn legacy_names_and_entries_are_decrypted() {
+        let text = b"Prontera, capital of the Rune-Midgarts Kingdom.".repeat(40);
+        let gat = noise(4096);
+        // Which blocks are encrypted depends on the extension.
+        let entries = [
+            store("data\\readme.txt", &text, GrfFileFlags::ENCRYPT_MIXED),
+            store("data\\prontera.gat", &gat, GrfFileFlags::ENCRYPT_HEADER),
+        ];
+
+        for version in [0x102, 0x103] {
+            let path = write_archive(&format!("legacy-{:x}", version), version, &entries, &legacy_table(&entries));
+
+            let grf = Grf::from_path(&path).unwrap();
+            assert_eq!(grf.header.real_files_count(), 2);
+            assert_eq!(grf.files_table.files.len(), 2, "the directory is left out");
+            let file_entry = grf.get_file_entry(Path::new("data/readme.txt")).unwrap();
+            assert_eq!(file_entry.file_name, "data\\readme.txt");
+            assert_eq!(file_entry.flags, GrfFileFlags::FILE | GrfFileFlags::ENCRYPT_MIXED);
+            assert_eq!(file_entry.compressed_size, entries[0].compressed_size);
+            let file_entry = grf.get_file_entry(Path::new("data/prontera.gat")).unwrap();
+            assert_eq!(file_entry.flags, GrfFileFlags::FILE | GrfFileFlags::ENCRYPT_HEADER);
+
+            assert_eq!(read_both(&grf, "data/readme.txt"), text);
+            assert!(read_both(&grf, "data/prontera.gat") == gat);
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn offsets_of_0x300_are_64_bits() {
+        let entries = [
+            store("data\\near.txt", b"near", GrfFileFlags::default()),
+            store("data\\far.txt", b"far", GrfFileFlags::default()),
+        ];
+        let far = 5 << 30;
+        let path = write_archive("0x300", 0x300, &entries, &compressed_table(0x300, &entries, &[0, far]));
+
+        let grf = Grf::from_path(&path).unwrap();
+        assert_eq!(grf.header.file_table_offset, (entries[0].data.len() + entries[1].data.len()) as u64);
+        assert_eq!(grf.get_file_entry(Path::new("data/far.txt")).unwrap().offset, far);
+        assert_eq!(read_both(&grf, "data/near.txt"), b"near");
+        assert!(grf.get_file_from_path(Path::new("data/far.txt")).is_err());
+        fs::remove_file(path).unwrap();
     }
 }
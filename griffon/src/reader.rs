@@ -0,0 +1,97 @@
+use miniz_oxide::inflate::stream::{inflate, InflateState};
+use miniz_oxide::{DataFormat, MZError, MZFlush, MZStatus};
+use std::{
+    fs::File,
+    io::{self, Read},
+};
+
+use crate::{des::Decrypter, read_exact_at, GrfFileEntry, HEADER_SIZE};
+
+/// Compressed bytes read at once, whole blocks for the decryption.
+pub(crate) const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Reads an entry a chunk at a time, decrypting and decompressing it on
+/// the way. Returned by `Grf::open_entry`.
+pub struct EntryReader<'a> {
+    fd: &'a File,
+    /// Where the next chunk is in the archive.
+    offset: u64,
+    /// Compressed bytes not read yet.
+    remaining: u64,
+    /// Aligned bytes not read yet, the padding being decrypted too.
+    remaining_aligned: u64,
+    decrypter: Option<Decrypter>,
+    input: Vec<u8>,
+    input_pos: usize,
+    state: Box<InflateState>,
+    finished: bool,
+}
+
+impl<'a> EntryReader<'a> {
+    pub(crate) fn new(fd: &'a File, file_entry: &GrfFileEntry) -> Self {
+        let decrypter = file_entry.decrypter();
+        let remaining = file_entry.compressed_size as u64;
+        let remaining_aligned = if decrypter.is_some() { file_entry.compressed_size_aligned as u64 } else { remaining };
+
+        Self {
+            fd,
+            offset: file_entry.offset + HEADER_SIZE as u64,
+            remaining,
+            remaining_aligned,
+            decrypter,
+            input: Vec::new(),
+            input_pos: 0,
+            state: InflateState::new_boxed(DataFormat::Zlib),
+            finished: false,
+        }
+    }
+
+    fn read_chunk(&mut self) -> io::Result<()> {
+        let size = self.remaining_aligned.min(CHUNK_SIZE as u64) as usize;
+        self.input.resize(size, 0);
+        read_exact_at(self.fd, &mut self.input, self.offset)?;
+
+        if let Some(decrypter) = &mut self.decrypter {
+            decrypter.decrypt(&mut self.input);
+        }
+
+        self.offset += size as u64;
+        self.remaining_aligned -= size as u64;
+        // Dropping the padding.
+        self.input.truncate(self.remaining.min(size as u64) as usize);
+        self.remaining -= self.input.len() as u64;
+        self.input_pos = 0;
+        Ok(())
+    }
+}
+
+impl Read for EntryReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while !self.finished && !buf.is_empty() {
+            if self.input_pos == self.input.len() && self.remaining_aligned > 0 {
+                self.read_chunk()?;
+            }
+
+            let input = &self.input[self.input_pos..];
+            let result = inflate(&mut self.state, input, buf, MZFlush::None);
+            self.input_pos += result.bytes_consumed;
+
+            match result.status {
+                Ok(MZStatus::StreamEnd) => self.finished = true,
+                Ok(_) | Err(MZError::Buf) => {
+                    let exhausted = self.input_pos == self.input.len() && self.remaining_aligned == 0;
+                    if result.bytes_written == 0 && exhausted {
+                        return Err(io::ErrorKind::UnexpectedEof.into());
+                    }
+                },
+                Err(err) => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Failed decompression: {:?}", err))),
+            }
+
+            if result.bytes_written > 0 {
+                return Ok(result.bytes_written);
+            }
+        }
+
+        Ok(0)
+    }
+}
@@ -0,0 +1,216 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs::{self, File},
+    io::{self, Cursor, Read},
+    path::{Path, PathBuf},
+};
+
+use crate::{Grf, GrfError};
+
+const DATA_INI_SECTION: &str = "data";
+
+/// Archives layered the way the client does: files in the loose `data`
+/// directory of the overlay come first, then the archives in the order of
+/// their priority. Names are matched regardless of ASCII case in both.
+#[derive(Debug, Default)]
+pub struct GrfSet {
+    /// Directory containing the loose `data` directory.
+    overlay: Option<PathBuf>,
+    archives: Vec<Grf>,
+    /// Lowercased paths to the first archive having them and their path there.
+    index: HashMap<String, (usize, PathBuf)>,
+}
+
+impl GrfSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Opens an archive, or the archives of a `data.ini`.
+    pub fn open(path: &Path) -> Result<Self, GrfError> {
+        if path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("ini")) {
+            return Self::from_data_ini(path);
+        }
+
+        let mut set = Self::new();
+        set.push(Grf::from_path(path)?);
+        Ok(set)
+    }
+
+    /// Opens the archives of the `[Data]` section of a client `data.ini`,
+    /// relative to its directory, `0=` having the highest priority.
+    pub fn from_data_ini(path: &Path) -> Result<Self, GrfError> {
+        let text = fs::read_to_string(path)?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+
+        let mut archives = Vec::new();
+        let mut in_section = false;
+        for line in text.lines() {
+            let line = line.split(';').next().unwrap_or_default().trim();
+
+            if let Some(section) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+                in_section = section.trim().eq_ignore_ascii_case(DATA_INI_SECTION);
+                continue;
+            }
+
+            let Some((priority, file_name)) = line.split_once('=') else {
+                continue;
+            };
+            if !in_section || file_name.trim().is_empty() {
+                continue;
+            }
+
+            let priority = priority.trim().parse::<u32>().map_err(|_| GrfError::InvalidData(
+                format!("Invalid priority '{}' in '{}'", priority.trim(), path.display()),
+            ))?;
+            archives.push((priority, dir.join(file_name.trim())));
+        }
+        archives.sort_by_key(|(priority, _)| *priority);
+
+        let mut set = Self::new();
+        for (_, archive) in archives {
+            let grf = Grf::from_path(&archive).map_err(|err| GrfError::InvalidData(
+                format!("Couldn't open '{}': {:?}", archive.display(), err),
+            ))?;
+            set.push(grf);
+        }
+
+        Ok(set)
+    }
+
+    /// Adds an archive with a lower priority than the ones already there.
+    pub fn push(&mut self, grf: Grf) {
+        let archive = self.archives.len();
+        for path in grf.files_table.files.keys() {
+            self.index
+                .entry(Self::index_key(path))
+                .or_insert_with(|| (archive, path.clone()));
+        }
+        self.archives.push(grf);
+    }
+
+    /// Reads loose files from `dir/data` before the archives.
+    pub fn set_overlay(&mut self, dir: Option<PathBuf>) {
+        self.overlay = dir;
+    }
+
+    pub fn archives(&self) -> &[Grf] {
+        &self.archives
+    }
+
+    pub fn contains(&self, path: &Path) -> bool {
+        self.overlay_path(path).is_some() || self.index.contains_key(&Self::index_key(path))
+    }
+
+    /// Every file of the overlay and the archives.
+    pub fn paths(&self) -> Result<BTreeSet<PathBuf>, GrfError> {
+        let mut paths: BTreeSet<PathBuf> = self.index.values().map(|(_, path)| path.clone()).collect();
+
+        if let (Some(overlay), Some(data)) = (&self.overlay, self.find_loose(Path::new("data"))) {
+            let mut dirs = vec![data];
+            while let Some(dir) = dirs.pop() {
+                let entries = match fs::read_dir(&dir) {
+                    Ok(entries) => entries,
+                    Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                    Err(err) => return Err(err.into()),
+                };
+                for entry in entries {
+                    let path = entry?.path();
+                    if path.is_dir() {
+                        dirs.push(path);
+                    } else if let Ok(relative) = path.strip_prefix(overlay) {
+                        // Listed once when an archive has it too.
+                        if !self.index.contains_key(&Self::index_key(relative)) {
+                            paths.insert(relative.to_path_buf());
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(paths)
+    }
+
+    /// Keeps up to `capacity` bytes of decompressed entries for each archive.
+    pub fn set_cache_capacity(&mut self, capacity: usize) {
+        for grf in &mut self.archives {
+            grf.set_cache_capacity(capacity);
+        }
+    }
+
+    pub fn get_file_from_path(&self, path: &Path) -> Result<Cursor<Vec<u8>>, GrfError> {
+        if let Some(loose_path) = self.overlay_path(path) {
+            return Ok(Cursor::new(fs::read(loose_path)?));
+        }
+
+        let (grf, archive_path) = self.find(path)?;
+        let (_, reader) = grf.get_file_from_path(archive_path)?;
+        Ok(reader)
+    }
+
+    /// Reads the file as it is decompressed, see `Grf::open_entry`.
+    pub fn open_entry(&self, path: &Path) -> Result<Box<dyn Read + Send + '_>, GrfError> {
+        if let Some(loose_path) = self.overlay_path(path) {
+            return Ok(Box::new(File::open(loose_path)?));
+        }
+
+        let (grf, archive_path) = self.find(path)?;
+        Ok(Box::new(grf.open_entry(archive_path)?))
+    }
+
+    /// Size of the file once decompressed, without reading it.
+    pub fn file_size(&self, path: &Path) -> Result<u64, GrfError> {
+        if let Some(loose_path) = self.overlay_path(path) {
+            return Ok(fs::metadata(loose_path)?.len());
+        }
+
+        let (grf, archive_path) = self.find(path)?;
+        Ok(grf.get_file_entry(archive_path)?.uncompressed_size.into())
+    }
+
+    fn find(&self, path: &Path) -> Result<(&Grf, &Path), GrfError> {
+        let (archive, archive_path) = self.index
+            .get(&Self::index_key(path))
+            .ok_or_else(|| GrfError::Other(io::Error::other(
+                format!("File not found in any archive '{}'", path.display()),
+            )))?;
+
+        Ok((&self.archives[*archive], archive_path))
+    }
+
+    fn overlay_path(&self, path: &Path) -> Option<PathBuf> {
+        self.find_loose(path).filter(|loose_path| loose_path.is_file())
+    }
+
+    /// Looked up a component at a time, the ones not there as named being
+    /// searched for regardless of ASCII case.
+    fn find_loose(&self, path: &Path) -> Option<PathBuf> {
+        let mut loose_path = self.overlay.clone()?;
+
+        let path = path.to_string_lossy();
+        for component in path.split(['/', '\\']).filter(|component| !component.is_empty()) {
+            if component == "." || component == ".." {
+                return None;
+            }
+
+            let exact = loose_path.join(component);
+            if exact.exists() {
+                loose_path = exact;
+                continue;
+            }
+            let name = fs::read_dir(&loose_path)
+                .ok()?
+                .filter_map(Result::ok)
+                .map(|entry| entry.file_name())
+                .find(|name| name.to_string_lossy().eq_ignore_ascii_case(component))?;
+            loose_path.push(name);
+        }
+
+        Some(loose_path)
+    }
+
+    /// Only ASCII is folded, the other chars being bytes of EUC-KR names.
+    fn index_key(path: &Path) -> String {
+        path.to_string_lossy().replace('\\', "/").to_ascii_lowercase()
+    }
+}
@@ -0,0 +1,189 @@
+use byteorder::{LittleEndian, ReadBytesExt};
+use std::{
+    fs::File,
+    io::{self, Cursor, Read, Seek},
+    path::{Path, PathBuf},
+};
+
+use crate::{GrfError, GrfWriter};
+
+const THOR_MAGIC: &[u8; 24] = b"ASSF (C) 2007 Aeomin DEV";
+const MODE_SINGLE_FILE: i16 = 0x21;
+const MODE_MULTIPLE_FILES: i16 = 0x30;
+const ENTRY_REMOVED: u8 = 0x01;
+
+/// Listing of the patched files used by the patcher, not part of the archive.
+const INTEGRITY_FILE_NAME: &str = "data.integrity";
+
+#[derive(Debug)]
+pub struct ThorEntry {
+    pub path: PathBuf,
+    /// The entry is removed from the archive rather than added.
+    pub removed: bool,
+    /// From the start of the patch.
+    pub offset: u64,
+    pub compressed_size: u32,
+    pub uncompressed_size: u32,
+}
+
+/// A patch in the format of the Thor patcher, adding zlib entries to an
+/// archive or removing them.
+#[derive(Debug)]
+pub struct ThorPatch {
+    pub file_handle: File,
+    /// Whether the patch goes into an archive or to the client directory.
+    pub use_grf_merging: bool,
+    /// The archive patched, the main one of the client when empty.
+    pub target_grf_name: String,
+    pub entries: Vec<ThorEntry>,
+}
+
+impl ThorPatch {
+    pub fn from_path(path: &Path) -> Result<Self, GrfError> {
+        let mut fd = File::open(path)?;
+
+        let mut magic = [0_u8; THOR_MAGIC.len()];
+        fd.read_exact(&mut magic)?;
+        if &magic != THOR_MAGIC {
+            return Err(GrfError::InvalidData(
+                "Invalid Thor File. Magic Header not present.".to_string(),
+            ));
+        }
+
+        let use_grf_merging = fd.read_u8()? != 0;
+        let _files_count = fd.read_i32::<LittleEndian>()?;
+        let mode = fd.read_i16::<LittleEndian>()?;
+        let target_grf_name = Self::read_file_name(&mut fd)?;
+
+        let entries = match mode {
+            MODE_SINGLE_FILE => {
+                let compressed_size = fd.read_u32::<LittleEndian>()?;
+                let uncompressed_size = fd.read_u32::<LittleEndian>()?;
+                let path = Self::read_path(&mut fd)?;
+                vec![ThorEntry {
+                    path,
+                    removed: false,
+                    offset: fd.stream_position()?,
+                    compressed_size,
+                    uncompressed_size,
+                }]
+            },
+            MODE_MULTIPLE_FILES => Self::read_file_table(&mut fd)?,
+            mode => return Err(GrfError::InvalidData(format!("Unknown Thor mode {:#x}", mode))),
+        };
+
+        Ok(Self {
+            file_handle: fd,
+            use_grf_merging,
+            target_grf_name,
+            entries,
+        })
+    }
+
+    fn read_file_table(fd: &mut File) -> Result<Vec<ThorEntry>, GrfError> {
+        let compressed_size = fd.read_u32::<LittleEndian>()?;
+        let offset = fd.read_u32::<LittleEndian>()?;
+
+        fd.seek(io::SeekFrom::Start(offset as u64))?;
+        let mut rdr = {
+            let mut buf = vec![0_u8; compressed_size as usize];
+            fd.read_exact(&mut buf)?;
+
+            let (buf, _) = yazi::decompress(&buf, yazi::Format::Zlib)?;
+
+            Cursor::new(buf)
+        };
+
+        let mut entries = Vec::new();
+        while (rdr.position() as usize) < rdr.get_ref().len() {
+            let path = Self::read_path(&mut rdr)?;
+            let removed = rdr.read_u8()? & ENTRY_REMOVED != 0;
+
+            let mut entry = ThorEntry {
+                path,
+                removed,
+                offset: 0,
+                compressed_size: 0,
+                uncompressed_size: 0,
+            };
+            if !removed {
+                entry.offset = rdr.read_u32::<LittleEndian>()? as u64;
+                entry.compressed_size = rdr.read_u32::<LittleEndian>()?;
+                entry.uncompressed_size = rdr.read_u32::<LittleEndian>()?;
+            }
+            entries.push(entry);
+        }
+
+        Ok(entries)
+    }
+
+    /// Names are prefixed with their length, one byte per char like in GRFs.
+    fn read_file_name<R: Read>(reader: &mut R) -> Result<String, GrfError> {
+        let mut buf = vec![0_u8; reader.read_u8()? as usize];
+        reader.read_exact(&mut buf)?;
+
+        Ok(buf.iter().map(|&c| c as char).collect())
+    }
+
+    fn read_path<R: Read>(reader: &mut R) -> Result<PathBuf, GrfError> {
+        Ok(PathBuf::from(Self::read_file_name(reader)?.replace("\\", "/")))
+    }
+
+    /// The data of an entry as stored in the patch, zlib compressed.
+    pub fn read_raw_entry(&mut self, entry: &ThorEntry) -> Result<Vec<u8>, GrfError> {
+        self.file_handle.seek(io::SeekFrom::Start(entry.offset))?;
+
+        let mut buf = vec![0_u8; entry.compressed_size as usize];
+        self.file_handle.read_exact(&mut buf)?;
+
+        Ok(buf)
+    }
+
+    pub fn read_entry(&mut self, entry: &ThorEntry) -> Result<Vec<u8>, GrfError> {
+        let (buf, _) = yazi::decompress(&self.read_raw_entry(entry)?, yazi::Format::Zlib)?;
+
+        Ok(buf)
+    }
+
+    /// Fails for patches going to the client directory, or into another
+    /// archive than `archive`, the names being compared regardless of case.
+    pub fn check_target(&self, archive: &Path) -> Result<(), GrfError> {
+        if !self.use_grf_merging {
+            return Err(GrfError::InvalidData(
+                "The patch goes to the client directory, not into an archive".to_string(),
+            ));
+        }
+
+        let target = self.target_grf_name.rsplit(['/', '\\']).next().unwrap_or_default();
+        let archive_name = archive.file_name().unwrap_or_default().to_string_lossy();
+        if !target.is_empty() && !target.eq_ignore_ascii_case(&archive_name) {
+            return Err(GrfError::InvalidData(format!("The patch goes into '{}'", self.target_grf_name)));
+        }
+
+        Ok(())
+    }
+
+    /// Adds and removes the entries of the patch, in order, after checking
+    /// it targets the archive of `writer`. The compressed data is copied as is.
+    pub fn apply(&mut self, writer: &mut GrfWriter) -> Result<(), GrfError> {
+        self.check_target(writer.path())?;
+
+        let entries = std::mem::take(&mut self.entries);
+
+        let result = entries
+            .iter()
+            .filter(|entry| entry.path != Path::new(INTEGRITY_FILE_NAME))
+            .try_for_each(|entry| {
+                if entry.removed {
+                    writer.remove(&entry.path);
+                    return Ok(());
+                }
+
+                let compressed = self.read_raw_entry(entry)?;
+                writer.add_compressed(&entry.path, &compressed, entry.uncompressed_size)
+            });
+
+        self.entries = entries;
+        result
+    }
+}
@@ -0,0 +1,301 @@
+use byteorder::{LittleEndian, WriteBytesExt};
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    fs::{self, File, OpenOptions},
+    io::{self, Seek, Write},
+    path::{Path, PathBuf},
+};
+use yazi::CompressionLevel;
+
+use crate::{
+    read_entry, read_raw_entry, Grf, GrfError, GrfFileEntry, GrfFileFlags, HEADER_MAGIC_STRING, HEADER_SIZE,
+};
+
+const WRITTEN_VERSION: u32 = 0x200;
+const DEFAULT_COMPRESSION_LEVEL: u8 = 6;
+
+/// Creates and modifies 0x200 archives. New archives are written to
+/// `<archive>.tmp`, which replaces the archive on `finish`. Entries added
+/// to an existing archive go after its file table, which stays valid until
+/// `finish` writes the new one and then the header, so the archive can be
+/// read until then. The space of removed or replaced entries, and of the old
+/// file tables, is only reclaimed by `compact` or `recompress`, into a new
+/// file.
+#[derive(Debug)]
+pub struct GrfWriter {
+    path: PathBuf,
+    file_handle: File,
+    /// The file written, when it replaces `path` on `finish`.
+    temporary: Option<PathBuf>,
+    encription_key: String,
+    files: HashMap<PathBuf, GrfFileEntry>,
+    /// Where the next entry goes, from the end of the header.
+    data_end: u64,
+    compression_level: u8,
+}
+
+impl GrfWriter {
+    /// Creates an empty archive, replacing the file if there is one once
+    /// finished.
+    pub fn create(path: &Path) -> Result<Self, GrfError> {
+        let (temporary, file_handle) = Self::create_temporary(path)?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            file_handle,
+            temporary: Some(temporary),
+            encription_key: String::new(),
+            files: HashMap::new(),
+            data_end: 0,
+            compression_level: DEFAULT_COMPRESSION_LEVEL,
+        })
+    }
+
+    /// Opens an existing 0x200 archive to modify it.
+    pub fn open(path: &Path) -> Result<Self, GrfError> {
+        let grf = Grf::from_path(path)?;
+        if grf.header.version != WRITTEN_VERSION {
+            return Err(GrfError::UnsupportedVersion(grf.header.version));
+        }
+
+        let file_handle = OpenOptions::new().read(true).write(true).open(path)?;
+        // Not over the file table, which the header points to until `finish`.
+        let data_end = file_handle.metadata()?.len().saturating_sub(HEADER_SIZE as u64);
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            file_handle,
+            temporary: None,
+            encription_key: grf.header.encription_key,
+            files: grf.files_table.files,
+            data_end,
+            compression_level: DEFAULT_COMPRESSION_LEVEL,
+        })
+    }
+
+    /// zlib level used by the entries added from now on, 0 to store them
+    /// uncompressed up to 9 for the smallest ones.
+    pub fn set_compression_level(&mut self, level: u8) {
+        self.compression_level = level.min(9);
+    }
+
+    /// The archive written, replaced by `finish` when it is new or compacted.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn files(&self) -> &HashMap<PathBuf, GrfFileEntry> {
+        &self.files
+    }
+
+    /// Adds an entry at `path`, `data/sprite/foo.spr` stored as
+    /// `data\sprite\foo.spr`, replacing the one already there.
+    pub fn add(&mut self, path: &Path, data: &[u8]) -> Result<(), GrfError> {
+        let compressed = yazi::compress(data, yazi::Format::Zlib, self.yazi_level())?;
+
+        self.add_compressed(path, &compressed, Self::size(data.len())?)
+    }
+
+    /// Adds zlib data as is, from a patch or another archive.
+    pub fn add_compressed(&mut self, path: &Path, compressed: &[u8], uncompressed_size: u32) -> Result<(), GrfError> {
+        let (file_name, file_name_bytes) = Self::file_name(path)?;
+
+        let file_entry = GrfFileEntry {
+            file_name,
+            file_name_bytes,
+            compressed_size: Self::size(compressed.len())?,
+            compressed_size_aligned: Self::size(compressed.len().next_multiple_of(8))?,
+            uncompressed_size,
+            flags: GrfFileFlags::FILE,
+            offset: self.data_end,
+        };
+
+        self.write_entry(file_entry, compressed)
+    }
+
+    /// Returns whether there was an entry at `path`.
+    pub fn remove(&mut self, path: &Path) -> bool {
+        self.files.remove(path).is_some()
+    }
+
+    /// Bytes taken by entries which were removed or replaced.
+    pub fn wasted_space(&self) -> u64 {
+        let used: u64 = self.files.values().map(|file_entry| file_entry.compressed_size_aligned as u64).sum();
+        self.data_end.saturating_sub(used)
+    }
+
+    /// Moves the entries over the space left by removed ones, copying them
+    /// to `<archive>.tmp` unless the archive is being created.
+    pub fn compact(&mut self) -> Result<(), GrfError> {
+        let mut target = match self.temporary {
+            Some(_) => None,
+            None => Some(Self::create_temporary(&self.path)?),
+        };
+
+        let mut entries: Vec<&mut GrfFileEntry> = self.files.values_mut().collect();
+        entries.sort_by_key(|file_entry| file_entry.offset);
+
+        // In place, entries only ever move towards the start, so none is
+        // overwritten before being moved.
+        let mut data_end = 0;
+        for file_entry in entries {
+            if file_entry.offset != data_end || target.is_some() {
+                let mut data = read_raw_entry(&self.file_handle, file_entry)?;
+                data.resize(file_entry.compressed_size_aligned as usize, 0);
+                let mut file_handle = target.as_ref().map_or(&self.file_handle, |(_, file_handle)| file_handle);
+                file_handle.seek(io::SeekFrom::Start(HEADER_SIZE as u64 + data_end))?;
+                file_handle.write_all(&data)?;
+                file_entry.offset = data_end;
+            }
+            data_end += file_entry.compressed_size_aligned as u64;
+        }
+
+        if let Some((temporary, file_handle)) = target.take() {
+            self.file_handle = file_handle;
+            self.temporary = Some(temporary);
+        }
+        self.data_end = data_end;
+        Ok(())
+    }
+
+    /// Compresses every entry again at the current level, decrypting the
+    /// encrypted ones. An existing archive is read as its entries are written
+    /// to `<archive>.tmp`, which is compact as it only has them.
+    pub fn recompress(&mut self) -> Result<(), GrfError> {
+        let mut paths: Vec<PathBuf> = self.files.keys().cloned().collect();
+        paths.sort();
+
+        if self.temporary.is_some() {
+            for path in paths {
+                let data = read_entry(&self.file_handle, &self.files[&path])?;
+                self.add(&path, &data)?;
+            }
+            return self.compact();
+        }
+
+        let (temporary, file_handle) = Self::create_temporary(&self.path)?;
+        let source = std::mem::replace(&mut self.file_handle, file_handle);
+        self.temporary = Some(temporary);
+        let entries = std::mem::take(&mut self.files);
+        self.data_end = 0;
+        for path in paths {
+            let data = read_entry(&source, &entries[&path])?;
+            self.add(&path, &data)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the file table and the header, then replaces the archive with
+    /// `<archive>.tmp` if it was written there.
+    pub fn finish(mut self) -> Result<(), GrfError> {
+        let mut entries: Vec<&GrfFileEntry> = self.files.values().collect();
+        entries.sort_by_key(|file_entry| file_entry.offset);
+
+        let mut table = Vec::new();
+        for file_entry in &entries {
+            table.write_all(&file_entry.file_name_bytes)?;
+            table.write_u8(0)?;
+            table.write_u32::<LittleEndian>(file_entry.compressed_size)?;
+            table.write_u32::<LittleEndian>(file_entry.compressed_size_aligned)?;
+            table.write_u32::<LittleEndian>(file_entry.uncompressed_size)?;
+            table.write_u8(file_entry.flags.bits())?;
+            table.write_u32::<LittleEndian>(Self::offset(file_entry.offset)?)?;
+        }
+        let compressed_table = yazi::compress(&table, yazi::Format::Zlib, self.yazi_level())?;
+
+        self.file_handle.seek(io::SeekFrom::Start(HEADER_SIZE as u64 + self.data_end))?;
+        self.file_handle.write_u32::<LittleEndian>(Self::size(compressed_table.len())?)?;
+        self.file_handle.write_u32::<LittleEndian>(Self::size(table.len())?)?;
+        self.file_handle.write_all(&compressed_table)?;
+        let len = self.file_handle.stream_position()?;
+        self.file_handle.set_len(len)?;
+        // The table has to be there before the header points to it.
+        self.file_handle.sync_data()?;
+
+        let mut key = [0_u8; 14];
+        for (byte, &key_byte) in key.iter_mut().zip(self.encription_key.as_bytes()) {
+            *byte = key_byte;
+        }
+
+        self.file_handle.seek(io::SeekFrom::Start(0))?;
+        self.file_handle.write_all(HEADER_MAGIC_STRING.as_bytes())?;
+        self.file_handle.write_all(&key)?;
+        self.file_handle.write_u32::<LittleEndian>(Self::offset(self.data_end)?)?;
+        self.file_handle.write_u32::<LittleEndian>(0)?;
+        self.file_handle.write_u32::<LittleEndian>(Self::size(entries.len() + 7)?)?;
+        self.file_handle.write_u32::<LittleEndian>(WRITTEN_VERSION)?;
+        self.file_handle.flush()?;
+        self.file_handle.sync_data()?;
+
+        if let Some(temporary) = self.temporary.take() {
+            fs::rename(&temporary, &self.path)?;
+        }
+        Ok(())
+    }
+
+    fn create_temporary(path: &Path) -> Result<(PathBuf, File), GrfError> {
+        let mut temporary = OsString::from(path);
+        temporary.push(".tmp");
+        let temporary = PathBuf::from(temporary);
+
+        let file_handle = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&temporary)?;
+        Ok((temporary, file_handle))
+    }
+
+    fn write_entry(&mut self, file_entry: GrfFileEntry, data: &[u8]) -> Result<(), GrfError> {
+        let padding = file_entry.compressed_size_aligned as usize - data.len();
+
+        self.file_handle.seek(io::SeekFrom::Start(HEADER_SIZE as u64 + self.data_end))?;
+        self.file_handle.write_all(data)?;
+        self.file_handle.write_all(&vec![0_u8; padding])?;
+        self.data_end += file_entry.compressed_size_aligned as u64;
+
+        let file_path = file_entry.file_name.replace("\\", "/");
+        self.files.insert(PathBuf::from(file_path), file_entry);
+        Ok(())
+    }
+
+    fn yazi_level(&self) -> CompressionLevel {
+        match self.compression_level {
+            0 => CompressionLevel::None,
+            level => CompressionLevel::Specific(level),
+        }
+    }
+
+    /// Names are read one byte per char, see `GrfFileTable`.
+    fn file_name(path: &Path) -> Result<(String, Vec<u8>), GrfError> {
+        let file_name = path.to_string_lossy().replace("/", "\\");
+
+        let file_name_bytes = file_name
+            .chars()
+            .map(|c| u8::try_from(c).ok().filter(|&byte| byte != 0))
+            .collect::<Option<Vec<u8>>>()
+            .filter(|bytes| !bytes.is_empty())
+            .ok_or_else(|| GrfError::InvalidData(format!("Invalid file name '{}'", path.display())))?;
+
+        Ok((file_name, file_name_bytes))
+    }
+
+    fn size(size: usize) -> Result<u32, GrfError> {
+        u32::try_from(size).map_err(|_| GrfError::InvalidData("Entry over 4 GiB".to_string()))
+    }
+
+    fn offset(offset: u64) -> Result<u32, GrfError> {
+        u32::try_from(offset).map_err(|_| GrfError::InvalidData("Archive over 4 GiB".to_string()))
+    }
+}
+
+/// An archive not finished is left as it was.
+impl Drop for GrfWriter {
+    fn drop(&mut self) {
+        if let Some(temporary) = self.temporary.take() {
+            let _ = fs::remove_file(temporary);
+        }
+    }
+}
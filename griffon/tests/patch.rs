@@ -0,0 +1,94 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use griffon::{Grf, GrfSet, GrfWriter, ThorPatch};
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("griffon-patch-{}-{}", std::process::id(), name))
+}
+
+fn create_archive(path: &Path, entries: &[(&str, &[u8])]) {
+    let mut writer = GrfWriter::create(path).unwrap();
+    for (name, data) in entries {
+        writer.add(Path::new(name), data).unwrap();
+    }
+    writer.finish().unwrap();
+}
+
+/// A single file patch, in the layout of the Thor patcher.
+fn write_thor(path: &Path, use_grf_merging: bool, target: &str, name: &str, data: &[u8]) {
+    let compressed = yazi::compress(data, yazi::Format::Zlib, yazi::CompressionLevel::Default).unwrap();
+
+    let mut buf = b"ASSF (C) 2007 Aeomin DEV".to_vec();
+    buf.push(use_grf_merging.into());
+    buf.extend_from_slice(&1_i32.to_le_bytes());
+    buf.extend_from_slice(&0x21_i16.to_le_bytes());
+    buf.push(target.len() as u8);
+    buf.extend_from_slice(target.as_bytes());
+    buf.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+    buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    buf.push(name.len() as u8);
+    buf.extend_from_slice(name.as_bytes());
+    buf.extend_from_slice(&compressed);
+    fs::write(path, buf).unwrap();
+}
+
+fn read(grf: &Grf, name: &str) -> Option<Vec<u8>> {
+    grf.get_file_from_path(Path::new(name)).ok().map(|(_, reader)| reader.into_inner())
+}
+
+#[test]
+fn patches_only_go_into_their_target() {
+    let dir = temp_path("thor");
+    fs::create_dir_all(&dir).unwrap();
+    let archive = dir.join("data.grf");
+    create_archive(&archive, &[]);
+
+    let cases = [
+        ("merged.thor", true, "DATA.GRF", "data\\merged.txt", true),
+        ("main.thor", true, "", "data\\main.txt", true),
+        ("other.thor", true, "rdata.grf", "data\\other.txt", false),
+        ("client.thor", false, "", "data\\client.txt", false),
+    ];
+    for (patch, use_grf_merging, target, name, applies) in cases {
+        let patch_path = dir.join(patch);
+        write_thor(&patch_path, use_grf_merging, target, name, name.as_bytes());
+
+        let mut patch = ThorPatch::from_path(&patch_path).unwrap();
+        assert_eq!(patch.check_target(&archive).is_ok(), applies, "{}", name);
+        let mut writer = GrfWriter::open(&archive).unwrap();
+        assert_eq!(patch.apply(&mut writer).is_ok(), applies, "{}", name);
+        writer.finish().unwrap();
+    }
+
+    let grf = Grf::from_path(&archive).unwrap();
+    assert_eq!(read(&grf, "data/merged.txt").unwrap(), b"data\\merged.txt");
+    assert_eq!(read(&grf, "data/main.txt").unwrap(), b"data\\main.txt");
+    assert!(read(&grf, "data/other.txt").is_none());
+    assert!(read(&grf, "data/client.txt").is_none());
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn overlay_names_ignore_case() {
+    let dir = temp_path("overlay");
+    fs::create_dir_all(dir.join("Data/Texture")).unwrap();
+    fs::write(dir.join("Data/Texture/Loose.BMP"), b"loose").unwrap();
+    fs::write(dir.join("Data/Texture/Extra.bmp"), b"extra").unwrap();
+    let archive = dir.join("data.grf");
+    create_archive(&archive, &[("data/texture/loose.bmp", b"archived"), ("data/other.txt", b"other")]);
+
+    let mut set = GrfSet::open(&archive).unwrap();
+    set.set_overlay(Some(dir.clone()));
+
+    for name in ["data/texture/loose.bmp", "DATA\\TEXTURE\\LOOSE.BMP", "Data/Texture/Loose.BMP"] {
+        assert!(set.contains(Path::new(name)));
+        assert_eq!(set.get_file_from_path(Path::new(name)).unwrap().into_inner(), b"loose", "{}", name);
+    }
+    assert_eq!(set.get_file_from_path(Path::new("data/OTHER.txt")).unwrap().into_inner(), b"other");
+    assert!(!set.contains(Path::new("data/../data.grf")));
+    // Listed once, the loose file shadowing the archived one.
+    assert_eq!(set.paths().unwrap().len(), 3);
+    assert!(set.paths().unwrap().contains(Path::new("Data/Texture/Extra.bmp")));
+    fs::remove_dir_all(dir).unwrap();
+}
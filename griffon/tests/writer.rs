@@ -0,0 +1,146 @@
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use griffon::{Grf, GrfWriter};
+
+fn archive_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("griffon-writer-{}-{}.grf", std::process::id(), name));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+fn temporary_path(archive: &Path) -> PathBuf {
+    let mut path = archive.as_os_str().to_os_string();
+    path.push(".tmp");
+    PathBuf::from(path)
+}
+
+fn text(name: &str) -> Vec<u8> {
+    format!("Contents of {}. ", name).repeat(200).into_bytes()
+}
+
+fn create(archive: &Path, names: &[&str]) {
+    let mut writer = GrfWriter::create(archive).unwrap();
+    for name in names {
+        writer.add(Path::new(name), &text(name)).unwrap();
+    }
+    writer.finish().unwrap();
+}
+
+/// Reads an entry both whole and streamed.
+fn read(grf: &Grf, name: &str) -> Vec<u8> {
+    let (_, reader) = grf.get_file_from_path(Path::new(name)).unwrap();
+    let mut streamed = Vec::new();
+    grf.open_entry(Path::new(name)).unwrap().read_to_end(&mut streamed).unwrap();
+    assert_eq!(reader.into_inner(), streamed);
+    streamed
+}
+
+fn assert_entries(archive: &Path, names: &[&str]) {
+    let grf = Grf::from_path(archive).unwrap();
+    assert_eq!(grf.files_table.files.len(), names.len());
+    for name in names {
+        assert_eq!(read(&grf, name), text(name), "{}", name);
+    }
+}
+
+#[test]
+fn created_archives_read_back() {
+    let archive = archive_path("create");
+    create(&archive, &["data/a.txt", "data/sprite/b.spr"]);
+
+    assert!(!temporary_path(&archive).exists());
+    assert_entries(&archive, &["data/a.txt", "data/sprite/b.spr"]);
+    let grf = Grf::from_path(&archive).unwrap();
+    assert_eq!(grf.get_file_entry(Path::new("data/sprite/b.spr")).unwrap().file_name, "data\\sprite\\b.spr");
+    fs::remove_file(archive).unwrap();
+}
+
+#[test]
+fn archives_stay_readable_until_finished() {
+    let archive = archive_path("add");
+    create(&archive, &["data/a.txt"]);
+
+    let mut writer = GrfWriter::open(&archive).unwrap();
+    writer.add(Path::new("data/b.txt"), &text("data/b.txt")).unwrap();
+    writer.add(Path::new("data/a.txt"), &text("data/a.txt")).unwrap();
+    assert_entries(&archive, &["data/a.txt"]);
+
+    writer.finish().unwrap();
+    assert_entries(&archive, &["data/a.txt", "data/b.txt"]);
+    fs::remove_file(archive).unwrap();
+}
+
+#[test]
+fn removed_entries_are_compacted_away() {
+    let archive = archive_path("compact");
+    create(&archive, &["data/a.txt", "data/b.txt", "data/c.txt"]);
+
+    let mut writer = GrfWriter::open(&archive).unwrap();
+    assert!(writer.remove(Path::new("data/b.txt")));
+    assert!(!writer.remove(Path::new("data/d.txt")));
+    writer.finish().unwrap();
+    assert_entries(&archive, &["data/a.txt", "data/c.txt"]);
+    let len = fs::metadata(&archive).unwrap().len();
+
+    let mut writer = GrfWriter::open(&archive).unwrap();
+    assert!(writer.wasted_space() > 0);
+    writer.compact().unwrap();
+    assert_eq!(writer.wasted_space(), 0);
+    assert!(temporary_path(&archive).exists());
+    // Compacting writes another file.
+    assert_entries(&archive, &["data/a.txt", "data/c.txt"]);
+    writer.finish().unwrap();
+
+    assert!(!temporary_path(&archive).exists());
+    assert!(fs::metadata(&archive).unwrap().len() < len);
+    assert_entries(&archive, &["data/a.txt", "data/c.txt"]);
+    fs::remove_file(archive).unwrap();
+}
+
+#[test]
+fn repacked_archives_are_smaller() {
+    let archive = archive_path("repack");
+    let mut writer = GrfWriter::create(&archive).unwrap();
+    writer.set_compression_level(0);
+    writer.add(Path::new("data/a.txt"), &text("data/a.txt")).unwrap();
+    writer.finish().unwrap();
+    let len = fs::metadata(&archive).unwrap().len();
+
+    let mut writer = GrfWriter::open(&archive).unwrap();
+    writer.set_compression_level(9);
+    writer.recompress().unwrap();
+    assert_eq!(writer.wasted_space(), 0);
+    // Written to another file, the archive staying as it was.
+    assert_eq!(fs::metadata(&archive).unwrap().len(), len);
+    assert!(fs::metadata(temporary_path(&archive)).unwrap().len() < len);
+    writer.finish().unwrap();
+
+    assert!(fs::metadata(&archive).unwrap().len() < len);
+    assert_entries(&archive, &["data/a.txt"]);
+    fs::remove_file(archive).unwrap();
+}
+
+#[test]
+fn unfinished_writers_leave_the_archive() {
+    let archive = archive_path("drop");
+    create(&archive, &["data/a.txt"]);
+
+    let len = fs::metadata(&archive).unwrap().len();
+
+    let mut writer = GrfWriter::open(&archive).unwrap();
+    writer.remove(Path::new("data/a.txt"));
+    writer.compact().unwrap();
+    drop(writer);
+
+    let mut writer = GrfWriter::open(&archive).unwrap();
+    writer.set_compression_level(0);
+    writer.recompress().unwrap();
+    drop(writer);
+
+    assert!(!temporary_path(&archive).exists());
+    assert_eq!(fs::metadata(&archive).unwrap().len(), len);
+    assert_entries(&archive, &["data/a.txt"]);
+    fs::remove_file(archive).unwrap();
+}
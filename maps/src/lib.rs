@@ -0,0 +1,183 @@
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+use std::io::{self, BufReader, Read};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use griffon::{GrfError, GrfSet};
+
+mod path;
+
+pub use path::step_times;
+
+const GAT_MAGIC: &[u8; 4] = b"GRAT";
+/// Magic, version, width and height.
+const GAT_HEADER_SIZE: u64 = 14;
+/// Heights of the four corners and the type.
+const GAT_CELL_SIZE: u64 = 20;
+
+/// Cells a player can be sent through in a single `CZ_REQUEST_MOVE`.
+pub const MAX_WALK_PATH: usize = 32;
+
+#[derive(Debug)]
+pub enum MapError {
+    InvalidData(String),
+    Grf(GrfError),
+}
+
+impl From<GrfError> for MapError {
+    fn from(error: GrfError) -> Self {
+        MapError::Grf(error)
+    }
+}
+
+impl From<io::Error> for MapError {
+    fn from(error: io::Error) -> Self {
+        MapError::InvalidData(format!("truncated cell data: {}", error))
+    }
+}
+
+impl Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapError::InvalidData(reason) => write!(f, "invalid map data: {}", reason),
+            MapError::Grf(error) => write!(f, "couldn't read the GRF: {:?}", error),
+        }
+    }
+}
+
+impl std::error::Error for MapError {}
+
+/// Kind of a cell, as found in `.gat` files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CellType {
+    Walkable,
+    Blocked,
+    Water,
+    /// A gap arrows and spells fly over.
+    Cliff,
+    Unknown(u32),
+}
+
+impl CellType {
+    pub fn from_u32(value: u32) -> Self {
+        match value {
+            0 => CellType::Walkable,
+            1 => CellType::Blocked,
+            3 => CellType::Water,
+            5 => CellType::Cliff,
+            other => CellType::Unknown(other),
+        }
+    }
+
+    /// Unknown cells are walkable, as they are for the official servers.
+    pub fn is_walkable(self) -> bool {
+        !matches!(self, CellType::Blocked | CellType::Cliff)
+    }
+}
+
+/// The cells of a map, `(0, 0)` being its bottom left corner.
+#[derive(Debug, Clone)]
+pub struct MapData {
+    pub name: String,
+    pub width: u16,
+    pub height: u16,
+    cells: Vec<CellType>,
+}
+
+impl MapData {
+    /// Reads the cells of a `.gat` file of `size` bytes, refused when the
+    /// dimensions it claims don't fit in it.
+    pub fn from_gat<R: Read>(name: &str, mut reader: R, size: u64) -> Result<Self, MapError> {
+        let mut magic = [0_u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != GAT_MAGIC {
+            return Err(MapError::InvalidData(format!("'{}' isn't a .gat file", name)));
+        }
+
+        let _version = reader.read_u16::<LittleEndian>()?;
+        let width = reader.read_i32::<LittleEndian>()?;
+        let height = reader.read_i32::<LittleEndian>()?;
+        let (Ok(width), Ok(height)) = (u16::try_from(width), u16::try_from(height)) else {
+            return Err(MapError::InvalidData(format!("'{}' is {}x{} cells", name, width, height)));
+        };
+
+        let count = width as usize * height as usize;
+        if GAT_HEADER_SIZE + count as u64 * GAT_CELL_SIZE > size {
+            return Err(MapError::InvalidData(format!("'{}' is {}x{} cells but only {} bytes", name, width, height, size)));
+        }
+
+        let mut cells = Vec::with_capacity(count);
+        for _ in 0..count {
+            // Heights of the four corners.
+            let mut heights = [0_u8; 16];
+            reader.read_exact(&mut heights)?;
+            cells.push(CellType::from_u32(reader.read_u32::<LittleEndian>()?));
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            width,
+            height,
+            cells,
+        })
+    }
+
+    pub fn cell(&self, x: u16, y: u16) -> Option<CellType> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        self.cells.get(y as usize * self.width as usize + x as usize).copied()
+    }
+
+    pub fn is_walkable(&self, x: u16, y: u16) -> bool {
+        self.cell(x, y).is_some_and(CellType::is_walkable)
+    }
+}
+
+/// Loads `data\<name>.gat` for each of `names`, or every map in the GRFs
+/// when `names` is `None`, on as many threads as there are cores.
+pub fn load_from_grf(grfs: &GrfSet, names: Option<&[String]>) -> Result<BTreeMap<String, MapData>, MapError> {
+    let names: Vec<String> = match names {
+        Some(names) => names.to_vec(),
+        None => grfs
+            .paths()?
+            .iter()
+            // Named in any case, as the client looks them up.
+            .filter(|path| path.parent().and_then(Path::to_str).is_some_and(|parent| parent.eq_ignore_ascii_case("data")))
+            .filter(|path| path.extension().and_then(|extension| extension.to_str()).is_some_and(|extension| extension.eq_ignore_ascii_case("gat")))
+            .filter_map(|path| path.file_stem()?.to_str().map(str::to_string))
+            .collect(),
+    };
+
+    let threads = thread::available_parallelism().map_or(1, NonZeroUsize::get).min(names.len());
+    let next = AtomicUsize::new(0);
+    let load = || -> Result<Vec<(String, MapData)>, MapError> {
+        let mut maps = Vec::new();
+        while let Some(name) = names.get(next.fetch_add(1, Ordering::Relaxed)) {
+            let path = PathBuf::from(format!("data/{}.gat", name));
+            let size = grfs.file_size(&path)?;
+            let reader = BufReader::new(grfs.open_entry(&path)?);
+            maps.push((name.clone(), MapData::from_gat(name, reader, size)?));
+        }
+        Ok(maps)
+    };
+
+    let loaded: Vec<_> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads).map(|_| scope.spawn(load)).collect();
+        workers
+            .into_iter()
+            .map(|worker| worker.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)))
+            .collect()
+    });
+
+    let mut maps = BTreeMap::new();
+    for worker_maps in loaded {
+        maps.extend(worker_maps?);
+    }
+
+    Ok(maps)
+}
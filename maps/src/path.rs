@@ -0,0 +1,177 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::MapData;
+
+const MOVE_COST: u32 = 10;
+const MOVE_DIAGONAL_COST: u32 = 14;
+
+const DIRECTIONS: [(i32, i32); 8] = [(0, 1), (-1, 1), (-1, 0), (-1, -1), (0, -1), (1, -1), (1, 0), (1, 1)];
+
+struct Visit {
+    cost: u32,
+    steps: usize,
+    parent: Option<(u16, u16)>,
+}
+
+fn estimate(from: (u16, u16), to: (u16, u16)) -> u32 {
+    let dx = from.0.abs_diff(to.0) as u32;
+    let dy = from.1.abs_diff(to.1) as u32;
+    MOVE_COST * dx.max(dy) + (MOVE_DIAGONAL_COST - MOVE_COST) * dx.min(dy)
+}
+
+impl MapData {
+    /// Shortest walkable path from `from` to `to` of at most `max_steps`
+    /// cells, `from` excluded. Diagonal steps can't cut the corner of a wall.
+    pub fn find_path(&self, from: (u16, u16), to: (u16, u16), max_steps: usize) -> Option<Vec<(u16, u16)>> {
+        if !self.is_walkable(from.0, from.1) || !self.is_walkable(to.0, to.1) {
+            return None;
+        }
+        if from.0.abs_diff(to.0).max(from.1.abs_diff(to.1)) as usize > max_steps {
+            return None;
+        }
+
+        let mut visits = HashMap::from([(from, Visit { cost: 0, steps: 0, parent: None })]);
+        let mut open = BinaryHeap::from([Reverse((estimate(from, to), 0, from))]);
+
+        while let Some(Reverse((_, cost, cell))) = open.pop() {
+            if cell == to {
+                let mut path = vec![cell];
+                while let Some(parent) = visits[path.last()?].parent {
+                    path.push(parent);
+                }
+                path.pop();
+                path.reverse();
+                return Some(path);
+            }
+
+            let visit = &visits[&cell];
+            if cost > visit.cost || visit.steps == max_steps {
+                continue;
+            }
+            let steps = visit.steps + 1;
+
+            for (dx, dy) in DIRECTIONS {
+                let (x, y) = (cell.0 as i32 + dx, cell.1 as i32 + dy);
+                let (Ok(x), Ok(y)) = (u16::try_from(x), u16::try_from(y)) else {
+                    continue;
+                };
+                if !self.is_walkable(x, y) {
+                    continue;
+                }
+                let diagonal = dx != 0 && dy != 0;
+                if diagonal && (!self.is_walkable(x, cell.1) || !self.is_walkable(cell.0, y)) {
+                    continue;
+                }
+
+                let next_cost = cost + if diagonal { MOVE_DIAGONAL_COST } else { MOVE_COST };
+                if visits.get(&(x, y)).is_some_and(|visit| visit.cost <= next_cost) {
+                    continue;
+                }
+                visits.insert((x, y), Visit { cost: next_cost, steps, parent: Some(cell) });
+                open.push(Reverse((next_cost + estimate((x, y), to), next_cost, (x, y))));
+            }
+        }
+
+        None
+    }
+}
+
+/// How long each step of `path` takes in milliseconds, starting at `from`
+/// with a walk speed of `speed` milliseconds per straight step.
+pub fn step_times(from: (u16, u16), path: &[(u16, u16)], speed: u32) -> Vec<u32> {
+    let mut previous = from;
+    path.iter()
+        .map(|&cell| {
+            let diagonal = previous.0 != cell.0 && previous.1 != cell.1;
+            previous = cell;
+            speed * if diagonal { MOVE_DIAGONAL_COST } else { MOVE_COST } / MOVE_COST
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CellType, MapData, MapError};
+
+    /// A `.gat` file of `rows`, the first one being `y = 0`: `#` for a
+    /// blocked cell, `~` for water, anything else walkable.
+    fn gat(rows: &[&str]) -> Vec<u8> {
+        let mut data = b"GRAT".to_vec();
+        data.extend_from_slice(&0x0102_u16.to_le_bytes());
+        data.extend_from_slice(&(rows[0].len() as i32).to_le_bytes());
+        data.extend_from_slice(&(rows.len() as i32).to_le_bytes());
+        for cell in rows.iter().flat_map(|row| row.bytes()) {
+            data.extend_from_slice(&[0; 16]);
+            let cell_type: u32 = match cell {
+                b'#' => 1,
+                b'~' => 3,
+                _ => 0,
+            };
+            data.extend_from_slice(&cell_type.to_le_bytes());
+        }
+        data
+    }
+
+    fn map_of(rows: &[&str]) -> MapData {
+        let data = gat(rows);
+        MapData::from_gat("test", data.as_slice(), data.len() as u64).unwrap()
+    }
+
+    #[test]
+    fn gat_cells_are_read() {
+        let map = map_of(&["..#", "~.."]);
+        assert_eq!((map.width, map.height), (3, 2));
+        assert_eq!(map.cell(2, 0), Some(CellType::Blocked));
+        assert_eq!(map.cell(0, 1), Some(CellType::Water));
+        assert_eq!(map.cell(3, 0), None);
+        assert!(map.is_walkable(0, 1));
+        assert!(!map.is_walkable(2, 0));
+    }
+
+    #[test]
+    fn gat_larger_than_its_file_is_refused() {
+        let mut data = gat(&["...", "..."]);
+        data[6..10].copy_from_slice(&30000_i32.to_le_bytes());
+        data[10..14].copy_from_slice(&30000_i32.to_le_bytes());
+        let result = MapData::from_gat("test", data.as_slice(), data.len() as u64);
+        assert!(matches!(result, Err(MapError::InvalidData(_))));
+
+        let data = gat(&["...", "..."]);
+        let result = MapData::from_gat("test", &data[..data.len() - 1], data.len() as u64);
+        assert!(matches!(result, Err(MapError::InvalidData(_))));
+    }
+
+    #[test]
+    fn path_goes_diagonally() {
+        let map = map_of(&["...", "...", "..."]);
+        assert_eq!(map.find_path((0, 0), (2, 2), 8), Some(vec![(1, 1), (2, 2)]));
+        assert_eq!(map.find_path((1, 1), (1, 1), 8), Some(Vec::new()));
+    }
+
+    #[test]
+    fn path_does_not_cut_corners() {
+        let map = map_of(&[".#", ".."]);
+        assert_eq!(map.find_path((0, 0), (1, 1), 8), Some(vec![(0, 1), (1, 1)]));
+    }
+
+    #[test]
+    fn path_is_limited_to_max_steps() {
+        let map = map_of(&["....."]);
+        assert_eq!(map.find_path((0, 0), (4, 0), 4).map(|path| path.len()), Some(4));
+        assert_eq!(map.find_path((0, 0), (4, 0), 3), None);
+
+        // Close enough as the crow flies, but too far around the wall.
+        let map = map_of(&["...", ".#.", ".#.", ".#."]);
+        assert_eq!(map.find_path((0, 3), (2, 3), 7), None);
+        assert_eq!(map.find_path((0, 3), (2, 3), 8).map(|path| path.len()), Some(8));
+    }
+
+    #[test]
+    fn unreachable_targets_have_no_path() {
+        let map = map_of(&["..#..", "..#..", "..#.."]);
+        assert_eq!(map.find_path((0, 0), (4, 0), 32), None);
+        assert_eq!(map.find_path((0, 0), (2, 0), 32), None);
+        assert_eq!(map.find_path((0, 0), (9, 9), 32), None);
+    }
+}
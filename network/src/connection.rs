@@ -0,0 +1,333 @@
+use std::collections::VecDeque;
+use std::fmt::{self, Display};
+use std::io::{self, ErrorKind, Read, Write};
+use std::time::Instant;
+
+use packet::{PacketDecoder, PacketError, PacketParser};
+
+use crate::{PlayerSession, SessionState};
+
+const READ_CHUNK_SIZE: usize = 4096;
+
+#[derive(Debug)]
+pub enum ConnectionError {
+    Io(io::Error),
+    Packet(PacketError),
+    /// The peer stopped reading and its send queue grew past the hard limit.
+    SendQueueFull(usize),
+    /// The peer started a frame longer than the receive limit.
+    FrameTooLong(usize),
+}
+
+impl Display for ConnectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectionError::Io(err) => write!(f, "socket error: {}", err),
+            ConnectionError::Packet(err) => write!(f, "invalid packet: {}", err),
+            ConnectionError::SendQueueFull(bytes) => write!(f, "send queue full with {} bytes", bytes),
+            ConnectionError::FrameTooLong(bytes) => write!(f, "frame of {} bytes over the receive limit", bytes),
+        }
+    }
+}
+
+impl std::error::Error for ConnectionError {}
+
+impl From<io::Error> for ConnectionError {
+    fn from(error: io::Error) -> Self {
+        ConnectionError::Io(error)
+    }
+}
+
+impl From<PacketError> for ConnectionError {
+    fn from(error: PacketError) -> Self {
+        ConnectionError::Packet(error)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadStatus {
+    Open,
+    /// The peer closed its side of the connection.
+    Closed,
+    /// Reading stopped at the receive limit, the socket may hold more.
+    Full,
+}
+
+/// Send and receive queue limits, in bytes.
+#[derive(Debug, Clone, Copy)]
+pub struct Watermarks {
+    /// Reading from the peer pauses once this much data is waiting to be sent.
+    pub high: usize,
+    /// Reading resumes once the queue drains back below this.
+    pub low: usize,
+    /// The connection is dropped if the queue grows past this anyway.
+    pub max: usize,
+    /// Reading stops once this much received data waits to be processed,
+    /// and the connection is dropped on a frame longer than this.
+    pub recv_max: usize,
+}
+
+impl Default for Watermarks {
+    fn default() -> Self {
+        Self {
+            high: 64 * 1024,
+            low: 16 * 1024,
+            max: 1024 * 1024,
+            recv_max: 64 * 1024,
+        }
+    }
+}
+
+/// Receiving side of a connection, reassembling packets from the socket.
+#[derive(Debug, Default)]
+pub struct ReadHalf {
+    decoder: PacketDecoder,
+    received: u64,
+}
+
+impl ReadHalf {
+    /// Reads until the socket would block and queues every complete packet.
+    /// Stops early with `ReadStatus::Full` once `max` bytes wait in the
+    /// decoder and the session's receive queue.
+    pub fn read_from<R: Read>(
+        &mut self,
+        socket: &mut R,
+        parser: &PacketParser,
+        session: &mut PlayerSession,
+        max: usize,
+    ) -> Result<ReadStatus, ConnectionError> {
+        let mut buf = [0u8; READ_CHUNK_SIZE];
+        let mut queued: usize = session.recv_queue.iter().map(|packet| packet.length).sum();
+
+        loop {
+            if queued + self.decoder.pending() >= max {
+                return Ok(ReadStatus::Full);
+            }
+
+            match socket.read(&mut buf) {
+                Ok(0) => return Ok(ReadStatus::Closed),
+                Ok(bytes_read) => {
+                    self.received += bytes_read as u64;
+                    self.decoder.feed(&buf[..bytes_read]);
+                },
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => return Ok(ReadStatus::Open),
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err.into()),
+            }
+
+            while let Some(packet) = self.decoder.next_packet(parser)? {
+                queued += packet.length;
+                session.recv_queue.push_back(packet);
+            }
+
+            let frame_len = self.decoder.pending_frame_len(parser);
+            if frame_len > max {
+                self.decoder.clear();
+                return Err(ConnectionError::FrameTooLong(frame_len));
+            }
+        }
+    }
+
+    /// Total bytes read from the socket.
+    pub fn received(&self) -> u64 {
+        self.received
+    }
+}
+
+/// Sending side of a connection. Keeps track of partially written buffers
+/// so non-blocking writes can resume where they stopped.
+#[derive(Debug, Default)]
+pub struct WriteHalf {
+    queue: VecDeque<Vec<u8>>,
+    /// Bytes of the front buffer already written.
+    written: usize,
+    queued_bytes: usize,
+    sent: u64,
+}
+
+impl WriteHalf {
+    pub fn push(&mut self, buf: Vec<u8>) {
+        if buf.is_empty() {
+            return;
+        }
+        self.queued_bytes += buf.len();
+        self.queue.push_back(buf);
+    }
+
+    /// Bytes waiting to be written.
+    pub fn queued_bytes(&self) -> usize {
+        self.queued_bytes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Total bytes written to the socket.
+    pub fn sent(&self) -> u64 {
+        self.sent
+    }
+
+    /// Writes as much as the socket takes. Returns `true` once everything was sent.
+    pub fn write_to<W: Write>(&mut self, socket: &mut W) -> io::Result<bool> {
+        while let Some(front) = self.queue.front() {
+            match socket.write(&front[self.written..]) {
+                Ok(0) => return Err(io::Error::from(ErrorKind::WriteZero)),
+                Ok(bytes_written) => {
+                    self.written += bytes_written;
+                    self.queued_bytes -= bytes_written;
+                    self.sent += bytes_written as u64;
+                    if self.written == front.len() {
+                        self.queue.pop_front();
+                        self.written = 0;
+                    }
+                },
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => return Ok(false),
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+/// A socket with its read and write halves and the session state seen by systems.
+#[derive(Debug)]
+pub struct Connection<S> {
+    socket: S,
+    read: ReadHalf,
+    write: WriteHalf,
+    watermarks: Watermarks,
+    paused: bool,
+    last_activity: Instant,
+    pub session: PlayerSession,
+}
+
+impl<S: Read + Write> Connection<S> {
+    pub fn new(socket: S, session: PlayerSession, watermarks: Watermarks) -> Self {
+        Self {
+            socket,
+            read: ReadHalf::default(),
+            write: WriteHalf::default(),
+            watermarks,
+            paused: false,
+            last_activity: Instant::now(),
+            session,
+        }
+    }
+
+    pub fn socket(&self) -> &S {
+        &self.socket
+    }
+
+    pub fn socket_mut(&mut self) -> &mut S {
+        &mut self.socket
+    }
+
+    /// Whether reading is paused because the peer isn't keeping up with
+    /// what is sent to it.
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn has_pending_writes(&self) -> bool {
+        !self.write.is_empty() || !self.session.send_queue.is_empty()
+    }
+
+    /// Last time data was read from or written to the socket.
+    pub fn last_activity(&self) -> Instant {
+        self.last_activity
+    }
+
+    /// Whether a session being closed has sent everything it had queued.
+    pub fn is_flushed_for_close(&self) -> bool {
+        self.session.state() == SessionState::Closing && !self.has_pending_writes()
+    }
+
+    /// Reads and decodes everything available. Does nothing while paused or
+    /// once the session started closing.
+    pub fn receive(&mut self, parser: &PacketParser) -> Result<ReadStatus, ConnectionError> {
+        if self.paused || !self.session.is_open() {
+            return Ok(ReadStatus::Open);
+        }
+
+        let received = self.read.received();
+        let status = self.read.read_from(&mut self.socket, parser, &mut self.session, self.watermarks.recv_max)?;
+        if self.read.received() != received {
+            self.last_activity = Instant::now();
+        }
+        Ok(status)
+    }
+
+    /// Moves the packets queued by systems to the write half and sends as
+    /// much as possible, pausing or resuming reads around the watermarks.
+    /// Returns `true` when reading was just resumed, since the socket may
+    /// already hold data that won't be signalled again.
+    pub fn flush(&mut self) -> Result<bool, ConnectionError> {
+        while let Some(buf) = self.session.send_queue.pop_front() {
+            self.write.push(buf);
+        }
+
+        let sent = self.write.sent();
+        self.write.write_to(&mut self.socket)?;
+        if self.write.sent() != sent {
+            self.last_activity = Instant::now();
+        }
+
+        let queued = self.write.queued_bytes();
+        if queued > self.watermarks.max {
+            return Err(ConnectionError::SendQueueFull(queued));
+        }
+
+        if !self.paused && queued >= self.watermarks.high {
+            self.paused = true;
+        } else if self.paused && queued <= self.watermarks.low {
+            self.paused = false;
+            return Ok(true);
+        }
+
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const TABLE: &str = "0x0064 6 FIXED recv\n0x0065 -1 VARIABLE recv\n";
+
+    fn session() -> PlayerSession {
+        PlayerSession::new("127.0.0.1:6900".parse().unwrap())
+    }
+
+    #[test]
+    fn reading_stops_at_the_receive_limit() {
+        let parser: PacketParser = TABLE.parse().unwrap();
+        let mut socket = Cursor::new([0x64, 0x00, 1, 2, 3, 4].repeat(2000));
+        let mut read = ReadHalf::default();
+        let mut session = session();
+
+        let status = read.read_from(&mut socket, &parser, &mut session, 1024).unwrap();
+        assert_eq!(status, ReadStatus::Full);
+        let queued: usize = session.recv_queue.iter().map(|packet| packet.length).sum();
+        assert!((1024..1024 + READ_CHUNK_SIZE).contains(&(queued + read.decoder.pending())));
+
+        session.recv_queue.clear();
+        let status = read.read_from(&mut socket, &parser, &mut session, 1024).unwrap();
+        assert_eq!(status, ReadStatus::Full);
+        assert!(read.received() < 6 * 2000);
+    }
+
+    #[test]
+    fn oversized_frames_are_refused() {
+        let parser: PacketParser = TABLE.parse().unwrap();
+        let mut socket = Cursor::new(vec![0x65, 0x00, 0x00, 0x10, 0, 0]);
+        let mut read = ReadHalf::default();
+
+        let result = read.read_from(&mut socket, &parser, &mut session(), 1024);
+        assert!(matches!(result, Err(ConnectionError::FrameTooLong(0x1000))));
+        assert_eq!(read.decoder.pending(), 0);
+    }
+}
@@ -1,59 +1,173 @@
 use packet::RawPacket;
 use std::collections::VecDeque;
-use std::io::{Read, Write};
-use std::net::TcpStream;
+use std::net::SocketAddr;
+use tracing::Span;
 
-#[derive(Debug)]
-pub enum UpdateResponse {
-    Disconnected,
+mod connection;
+mod link;
+mod reactor;
+mod registry;
+
+pub use connection::{Connection, ConnectionError, ReadHalf, ReadStatus, Watermarks, WriteHalf};
+pub use link::{InterServerLink, LinkConfig};
+pub use reactor::{PacketHandler, Reactor, ReactorConfig};
+pub use registry::{SessionId, SessionInfo, SessionRegistry, SessionTask};
+
+use registry::Route;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionState {
+    Connecting,
+    Authenticated,
+    /// Waiting for `send_queue` to be flushed before closing the socket.
+    Closing,
+    Closed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisconnectReason {
+    /// The client closed the connection.
+    PeerClosed,
+    /// A system closed the session.
+    Kicked,
+    IdleTimeout,
+    /// The session was closed but didn't send what it had queued in time.
+    CloseTimeout,
+    /// A socket or decoding error, or a system panicked.
     Error,
-    NoContent,
-    Ok(Vec<u8>),
 }
 
+/// Called once when a session goes away, before it is marked as `Closed`.
+pub type DisconnectHook = fn(session: &mut PlayerSession, reason: DisconnectReason);
+
+/// Per connection state handed to the systems. Packets pushed to
+/// `send_queue` are written out once the systems are done with the
+/// packet being processed.
 #[derive(Debug)]
 pub struct PlayerSession {
-    pub socket: TcpStream,
+    pub peer_addr: SocketAddr,
     pub recv_queue: VecDeque<RawPacket>,
-    pub send_queue: VecDeque<Vec<u8>>
+    pub send_queue: VecDeque<Vec<u8>>,
+    id: SessionId,
+    registry: SessionRegistry,
+    account_id: Option<u32>,
+    char_id: Option<u32>,
+    state: SessionState,
+    disconnect_hooks: Vec<DisconnectHook>,
+    span: Span,
 }
 
 impl PlayerSession {
-    pub fn new(socket: TcpStream) -> Self {
-        socket
-            .set_nonblocking(true)
-            .expect("Failed to set player session in non-blocking mode");
+    /// A session outside of any reactor, registered in a registry of its own.
+    pub fn new(peer_addr: SocketAddr) -> Self {
+        Self::register(peer_addr, SessionRegistry::new(), None)
+    }
+
+    pub(crate) fn register(peer_addr: SocketAddr, registry: SessionRegistry, route: Option<Route>) -> Self {
+        let id = registry.insert(peer_addr, route);
         Self {
-            socket,
+            peer_addr,
             recv_queue: VecDeque::new(),
             send_queue: VecDeque::new(),
+            id,
+            registry,
+            account_id: None,
+            char_id: None,
+            state: SessionState::Connecting,
+            disconnect_hooks: Vec::new(),
+            span: tracing::info_span!("session", session_id = id.get(), peer = %peer_addr, account_id = tracing::field::Empty, char_id = tracing::field::Empty),
+        }
+    }
+
+    pub fn id(&self) -> SessionId {
+        self.id
+    }
+
+    /// Span everything happening to the session is logged in, carrying its
+    /// account and character once they are known.
+    pub fn span(&self) -> &Span {
+        &self.span
+    }
+
+    /// Registry holding every session of the server this one belongs to.
+    pub fn registry(&self) -> &SessionRegistry {
+        &self.registry
+    }
+
+    pub fn account_id(&self) -> Option<u32> {
+        self.account_id
+    }
+
+    pub fn set_account_id(&mut self, account_id: Option<u32>) {
+        self.account_id = account_id;
+        if let Some(account_id) = account_id {
+            self.span.record("account_id", account_id);
         }
+        self.registry.bind_account(self.id, account_id);
     }
 
-    pub fn update_sockets(&mut self) -> UpdateResponse {
-        let mut buf = [0_u8; 1024];
-        use std::io::ErrorKind::*;
-        match self.socket.read(&mut buf) {
-            Ok(bytes_read) => {
-                if bytes_read == 0 {
-                    println!("PlayerSession disconnected {:?}", self.socket);
-                    return UpdateResponse::Disconnected;
-                }
+    pub fn char_id(&self) -> Option<u32> {
+        self.char_id
+    }
+
+    pub fn set_char_id(&mut self, char_id: Option<u32>) {
+        self.char_id = char_id;
+        if let Some(char_id) = char_id {
+            self.span.record("char_id", char_id);
+        }
+        self.registry.bind_char(self.id, char_id);
+    }
+
+    /// Queues an encoded packet on this or any other session of the server.
+    /// Returns `false` if the session is gone.
+    pub fn send_to(&mut self, id: SessionId, packet: Vec<u8>) -> bool {
+        if id == self.id {
+            self.send_queue.push_back(packet);
+            return true;
+        }
+        self.registry.send_to(id, packet)
+    }
+
+    pub fn state(&self) -> SessionState {
+        self.state
+    }
+
+    pub fn is_open(&self) -> bool {
+        matches!(self.state, SessionState::Connecting | SessionState::Authenticated)
+    }
 
-                UpdateResponse::Ok(buf[..bytes_read].to_vec())
-            },
-            Err(ref e) if e.kind() == WouldBlock =>  UpdateResponse::NoContent,
-            Err(err) => {
-                eprintln!("Failed to read packets from player: {}", err);
-                UpdateResponse::Error
-            },
+    pub fn authenticate(&mut self) {
+        if self.state == SessionState::Connecting {
+            self.state = SessionState::Authenticated;
         }
     }
 
-    pub fn transmit(&mut self) {
-        while let Some(packet) = self.send_queue.pop_front() {
-            println!("sending packet with len: {}", packet.len());
-            self.socket.write_all(packet.as_slice()).unwrap();
+    /// Stops reading from the client and closes the connection once
+    /// everything in `send_queue` was sent.
+    pub fn close(&mut self) {
+        if self.is_open() {
+            self.state = SessionState::Closing;
+        }
+    }
+
+    pub fn on_disconnect(&mut self, hook: DisconnectHook) {
+        self.disconnect_hooks.push(hook);
+    }
+
+    /// Runs the session's hooks and then `hooks`, only the first time it is called.
+    pub(crate) fn disconnect(&mut self, reason: DisconnectReason, hooks: &[DisconnectHook]) {
+        if self.state == SessionState::Closed {
+            return;
+        }
+
+        let session_hooks = std::mem::take(&mut self.disconnect_hooks);
+        for hook in session_hooks.iter().chain(hooks) {
+            hook(self, reason);
         }
+
+        self.state = SessionState::Closed;
+        self.recv_queue.clear();
+        self.send_queue.clear();
+        self.registry.remove(self.id);
     }
-}
\ No newline at end of file
+}
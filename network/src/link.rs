@@ -0,0 +1,218 @@
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use packet::{PacketDecoder, PacketParser, RawPacket};
+use tracing::{info, warn};
+
+/// How often the reader wakes up to send keepalives and check the timeout.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Longest a sender waits for the other server to make room, past which the
+/// link is dropped as if it had died. Senders include the network threads.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone)]
+pub struct LinkConfig {
+    /// Wait before connecting again once the connection failed or was lost.
+    pub reconnect_delay: Duration,
+    /// Encoded packet sent every `keepalive_interval`, which the other server
+    /// answers so `timeout` can tell a dead connection apart from a quiet one.
+    pub keepalive: Option<Vec<u8>>,
+    pub keepalive_interval: Duration,
+    /// Drops the connection when nothing was received for this long.
+    pub timeout: Option<Duration>,
+}
+
+impl Default for LinkConfig {
+    fn default() -> Self {
+        Self {
+            reconnect_delay: Duration::from_secs(5),
+            keepalive: None,
+            keepalive_interval: Duration::from_secs(10),
+            timeout: Some(Duration::from_secs(30)),
+        }
+    }
+}
+
+/// Connection from one server to another, made again whenever it is lost.
+/// Packets are read on a thread of its own and handed to `handler`, and sent
+/// from any thread.
+#[derive(Debug)]
+pub struct InterServerLink {
+    addr: String,
+    stream: Mutex<Option<TcpStream>>,
+    connected: AtomicBool,
+}
+
+impl InterServerLink {
+    /// Starts connecting to `addr` in the background. `on_connect` runs first
+    /// on every new connection, to register with the other server.
+    pub fn connect<C, H>(addr: &str, parser: Arc<PacketParser>, config: LinkConfig, on_connect: C, handler: H) -> io::Result<Arc<Self>>
+    where
+        C: Fn(&InterServerLink) -> io::Result<()> + Send + 'static,
+        H: Fn(&RawPacket) + Send + 'static,
+    {
+        let link = Arc::new(Self {
+            addr: addr.to_string(),
+            stream: Mutex::new(None),
+            connected: AtomicBool::new(false),
+        });
+
+        let weak = Arc::downgrade(&link);
+        let link_addr = addr.to_string();
+        thread::Builder::new()
+            .name(format!("Link to {}", addr))
+            .spawn(move || {
+                let _entered = tracing::info_span!("link", addr = %link_addr).entered();
+                Self::run(weak, &parser, &config, on_connect, handler)
+            })?;
+
+        Ok(link)
+    }
+
+    /// Stops once every handle to the link was dropped.
+    fn run<C, H>(link: Weak<Self>, parser: &PacketParser, config: &LinkConfig, on_connect: C, handler: H)
+    where
+        C: Fn(&InterServerLink) -> io::Result<()>,
+        H: Fn(&RawPacket),
+    {
+        while let Some(link) = link.upgrade() {
+            match link.attach() {
+                Ok(mut reader) => {
+                    info!("Link is up");
+                    let result = on_connect(&link).and_then(|_| link.read_loop(&mut reader, parser, config, &handler));
+                    link.detach();
+                    match result {
+                        Ok(()) => info!("Link was closed"),
+                        Err(err) => warn!(%err, "Link failed"),
+                    }
+                },
+                Err(err) => warn!(%err, "Couldn't connect"),
+            }
+
+            drop(link);
+            thread::sleep(config.reconnect_delay);
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Option<TcpStream>> {
+        self.stream.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn attach(&self) -> io::Result<TcpStream> {
+        let stream = TcpStream::connect(&self.addr)?;
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(POLL_INTERVAL))?;
+        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+        let reader = stream.try_clone()?;
+
+        *self.lock() = Some(stream);
+        self.connected.store(true, Ordering::Release);
+        Ok(reader)
+    }
+
+    fn detach(&self) {
+        self.connected.store(false, Ordering::Release);
+        if let Some(stream) = self.lock().take() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+    }
+
+    fn read_loop<H: Fn(&RawPacket)>(&self, reader: &mut TcpStream, parser: &PacketParser, config: &LinkConfig, handler: H) -> io::Result<()> {
+        let mut decoder = PacketDecoder::new();
+        let mut buf = [0u8; 4096];
+        let mut last_received = Instant::now();
+        let mut last_keepalive = Instant::now();
+
+        loop {
+            if let Some(keepalive) = &config.keepalive {
+                if last_keepalive.elapsed() >= config.keepalive_interval {
+                    self.send(keepalive)?;
+                    last_keepalive = Instant::now();
+                }
+            }
+
+            let read = match reader.read(&mut buf) {
+                Ok(0) => return Ok(()),
+                Ok(read) => read,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) if matches!(err.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {
+                    if config.timeout.is_some_and(|timeout| last_received.elapsed() > timeout) {
+                        return Err(io::Error::new(io::ErrorKind::TimedOut, "the other server stopped answering"));
+                    }
+                    continue;
+                },
+                Err(err) => return Err(err),
+            };
+
+            last_received = Instant::now();
+            decoder.feed(&buf[..read]);
+            while let Some(packet) = decoder.next_packet(parser).map_err(io::Error::other)? {
+                handler(&packet);
+            }
+        }
+    }
+
+    pub fn addr(&self) -> &str {
+        &self.addr
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Acquire)
+    }
+
+    /// Fails with `NotConnected` while the link is down. A packet that
+    /// can't be written within `WRITE_TIMEOUT` brings the link down, part
+    /// of it may have been sent.
+    pub fn send(&self, packet: &[u8]) -> io::Result<()> {
+        let mut stream = self.lock();
+        let Some(connection) = stream.as_mut() else {
+            return Err(io::Error::new(io::ErrorKind::NotConnected, format!("the link to {} is down", self.addr)));
+        };
+
+        let result = connection.write_all(packet);
+        if let Err(err) = &result {
+            warn!(%err, addr = %self.addr, "Dropping the link: couldn't send");
+            // Wakes the reader up so it reconnects.
+            let _ = connection.shutdown(Shutdown::Both);
+            *stream = None;
+            self.connected.store(false, Ordering::Release);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    /// A server that stops reading only holds a sender up for `WRITE_TIMEOUT`.
+    #[test]
+    fn stalled_peer_drops_the_link() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let config = LinkConfig { reconnect_delay: Duration::from_secs(60), ..LinkConfig::default() };
+        let link = InterServerLink::connect(&addr, Arc::new("0x0064 6 FIXED recv\n".parse().unwrap()), config, |_| Ok(()), |_| {}).unwrap();
+        let (_peer, _) = listener.accept().unwrap();
+        while !link.is_connected() {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let started = Instant::now();
+        let chunk = vec![0u8; 64 * 1024];
+        let err = loop {
+            if let Err(err) = link.send(&chunk) {
+                break err;
+            }
+            assert!(started.elapsed() < WRITE_TIMEOUT * 10, "the send never timed out");
+        };
+        assert!(matches!(err.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut), "{}", err);
+        assert!(!link.is_connected());
+        assert_eq!(link.send(&chunk).unwrap_err().kind(), io::ErrorKind::NotConnected);
+    }
+}
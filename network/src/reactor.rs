@@ -0,0 +1,400 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::TcpListener;
+use std::sync::mpsc::{self, Receiver};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use mio::net::TcpStream;
+use mio::{Events, Interest, Poll, Token, Waker};
+use packet::{PacketParser, RawPacket};
+use tracing::{debug, error, info, warn};
+
+use crate::connection::{Connection, ReadStatus, Watermarks};
+use crate::registry::{Command, Route};
+use crate::{ConnectionError, DisconnectHook, DisconnectReason, PlayerSession, SessionId, SessionRegistry};
+
+const WAKE_TOKEN: Token = Token(usize::MAX);
+const EVENTS_CAPACITY: usize = 1024;
+/// How often idle and closing sessions are looked for.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Called for every packet received, on the network thread owning the session.
+pub type PacketHandler = dyn Fn(&mut PlayerSession, &RawPacket) + Send + Sync;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ReactorConfig {
+    pub workers: usize,
+    pub watermarks: Watermarks,
+    /// Sessions without any traffic for this long are dropped.
+    pub idle_timeout: Option<Duration>,
+    /// How long a closing session may take to flush its send queue.
+    pub close_timeout: Duration,
+}
+
+impl Default for ReactorConfig {
+    fn default() -> Self {
+        Self {
+            workers: 2,
+            watermarks: Watermarks::default(),
+            idle_timeout: Some(Duration::from_secs(60)),
+            close_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Event driven server loop. Accepted sockets are spread over a pool of
+/// network threads, each waiting on its own `Poll` and running the packet
+/// handler for the sessions it owns. Idle sessions only cost a timeout check
+/// once a second.
+pub struct Reactor {
+    config: ReactorConfig,
+    parser: Arc<PacketParser>,
+    registry: SessionRegistry,
+    disconnect_hooks: Vec<DisconnectHook>,
+}
+
+struct WorkerHandle {
+    route: Route,
+    thread: JoinHandle<()>,
+}
+
+impl Reactor {
+    pub fn new(parser: Arc<PacketParser>, config: ReactorConfig) -> Self {
+        Self {
+            config,
+            parser,
+            registry: SessionRegistry::new(),
+            disconnect_hooks: Vec::new(),
+        }
+    }
+
+    /// Registry of the sessions accepted by this reactor, for handing to
+    /// code running outside of the network threads.
+    pub fn registry(&self) -> &SessionRegistry {
+        &self.registry
+    }
+
+    /// Registers a hook called for every session that goes away, after the
+    /// hooks the session registered itself.
+    pub fn on_disconnect(mut self, hook: DisconnectHook) -> Self {
+        self.disconnect_hooks.push(hook);
+        self
+    }
+
+    /// Accepts connections on the current thread until the listener fails.
+    pub fn run<H>(self, listener: TcpListener, handler: H) -> io::Result<()>
+    where
+        H: Fn(&mut PlayerSession, &RawPacket) + Send + Sync + 'static,
+    {
+        let handler: Arc<PacketHandler> = Arc::new(handler);
+        let disconnect_hooks: Arc<[DisconnectHook]> = self.disconnect_hooks.clone().into();
+        let mut workers = Vec::new();
+
+        for n in 0..self.config.workers.max(1) {
+            workers.push(self.spawn_worker(n, handler.clone(), disconnect_hooks.clone())?);
+        }
+        info!(threads = workers.len(), "Running the network threads");
+
+        let mut next_worker = 0usize;
+        loop {
+            let (socket, peer_addr) = match listener.accept() {
+                Ok(accepted) => accepted,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => {
+                    // Usually out of file descriptors; give sessions a chance to close.
+                    error!(%err, "Failed to accept connection");
+                    thread::sleep(Duration::from_millis(10));
+                    continue;
+                },
+            };
+
+            if let Err(err) = socket.set_nonblocking(true).and_then(|_| socket.set_nodelay(true)) {
+                warn!(peer = %peer_addr, %err, "Failed to configure socket");
+                continue;
+            }
+
+            workers.retain(|worker| !worker.thread.is_finished());
+            if workers.is_empty() {
+                return Err(io::Error::other("every network thread has stopped"));
+            }
+
+            let worker = &workers[next_worker % workers.len()];
+            next_worker = next_worker.wrapping_add(1);
+
+            if !worker.route.send(Command::Accept(TcpStream::from_std(socket), peer_addr)) {
+                error!(peer = %peer_addr, "Network thread is gone, dropping connection");
+            }
+        }
+    }
+
+    fn spawn_worker(
+        &self,
+        n: usize,
+        handler: Arc<PacketHandler>,
+        disconnect_hooks: Arc<[DisconnectHook]>,
+    ) -> io::Result<WorkerHandle> {
+        let poll = Poll::new()?;
+        let waker = Arc::new(Waker::new(poll.registry(), WAKE_TOKEN)?);
+        let (commands, receiver) = mpsc::channel();
+        let route = Route { commands, waker };
+
+        let mut worker = Worker {
+            poll,
+            commands: receiver,
+            route: route.clone(),
+            connections: HashMap::new(),
+            tokens: HashMap::new(),
+            next_token: 0,
+            parser: self.parser.clone(),
+            registry: self.registry.clone(),
+            config: self.config,
+            handler,
+            disconnect_hooks,
+        };
+
+        let thread = thread::Builder::new()
+            .name(format!("Network Thread {}", n))
+            .spawn(move || {
+                if let Err(err) = worker.run() {
+                    error!(%err, "Network thread stopped");
+                }
+            })?;
+
+        Ok(WorkerHandle { route, thread })
+    }
+}
+
+/// What to do with a connection after it was processed.
+enum Outcome {
+    Open,
+    Disconnect(DisconnectReason),
+}
+
+struct Worker {
+    poll: Poll,
+    commands: Receiver<Command>,
+    /// Handed to the registry so other threads can reach this one.
+    route: Route,
+    connections: HashMap<Token, Connection<TcpStream>>,
+    tokens: HashMap<SessionId, Token>,
+    next_token: usize,
+    parser: Arc<PacketParser>,
+    registry: SessionRegistry,
+    config: ReactorConfig,
+    handler: Arc<PacketHandler>,
+    disconnect_hooks: Arc<[DisconnectHook]>,
+}
+
+impl Worker {
+    fn run(&mut self) -> io::Result<()> {
+        let mut events = Events::with_capacity(EVENTS_CAPACITY);
+        let mut last_sweep = Instant::now();
+
+        loop {
+            // Only wake up for timeouts while there are sessions to check.
+            let timeout = if self.connections.is_empty() {
+                None
+            } else {
+                Some(SWEEP_INTERVAL.saturating_sub(last_sweep.elapsed()))
+            };
+
+            if let Err(err) = self.poll.poll(&mut events, timeout) {
+                if err.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(err);
+            }
+
+            for event in events.iter() {
+                if event.token() == WAKE_TOKEN {
+                    self.run_commands();
+                    continue;
+                }
+
+                // Errors and hang ups show up as readable, and the read reports them.
+                let readable = event.is_readable() || event.is_error() || event.is_read_closed();
+                self.update(event.token(), readable);
+            }
+
+            if last_sweep.elapsed() >= SWEEP_INTERVAL {
+                self.sweep();
+                last_sweep = Instant::now();
+            }
+        }
+    }
+
+    fn run_commands(&mut self) {
+        while let Ok(command) = self.commands.try_recv() {
+            match command {
+                Command::Accept(socket, peer_addr) => self.accept(socket, peer_addr),
+                Command::Send(id, packet) => {
+                    if let Some(token) = self.tokens.get(&id).copied() {
+                        if let Some(connection) = self.connections.get_mut(&token) {
+                            connection.session.send_queue.push_back(packet);
+                        }
+                        self.update(token, false);
+                    }
+                },
+                Command::Run(id, task) => {
+                    if let Some(token) = self.tokens.get(&id).copied() {
+                        if let Some(connection) = self.connections.get_mut(&token) {
+                            let session = &mut connection.session;
+                            let span = session.span().clone();
+                            let _entered = span.enter();
+                            if panic::catch_unwind(AssertUnwindSafe(|| task(session))).is_err() {
+                                error!("Session task panicked, dropping session");
+                                self.close(token, DisconnectReason::Error);
+                                continue;
+                            }
+                        }
+                        self.update(token, false);
+                    }
+                },
+                Command::Close(id) => {
+                    if let Some(token) = self.tokens.get(&id).copied() {
+                        if let Some(connection) = self.connections.get_mut(&token) {
+                            connection.session.close();
+                        }
+                        self.update(token, false);
+                    }
+                },
+            }
+        }
+    }
+
+    fn accept(&mut self, mut socket: TcpStream, peer_addr: std::net::SocketAddr) {
+        let token = Token(self.next_token);
+        self.next_token = (self.next_token + 1) % WAKE_TOKEN.0;
+
+        if let Err(err) = self.poll.registry().register(&mut socket, token, Interest::READABLE) {
+            warn!(peer = %peer_addr, %err, "Failed to register connection");
+            return;
+        }
+
+        let session = PlayerSession::register(peer_addr, self.registry.clone(), Some(self.route.clone()));
+        info!(parent: session.span(), "Player connected");
+        self.tokens.insert(session.id(), token);
+        self.connections.insert(token, Connection::new(socket, session, self.config.watermarks));
+    }
+
+    fn update(&mut self, token: Token, readable: bool) {
+        let Some(connection) = self.connections.get_mut(&token) else {
+            return;
+        };
+
+        let span = connection.session.span().clone();
+        let _entered = span.enter();
+        let outcome = match Self::process(connection, &self.parser, self.handler.as_ref(), readable) {
+            Ok(outcome) => outcome,
+            Err(err) => {
+                warn!(%err, "Dropping session");
+                Outcome::Disconnect(DisconnectReason::Error)
+            },
+        };
+
+        if let Outcome::Disconnect(reason) = outcome {
+            self.close(token, reason);
+            return;
+        }
+
+        let interest = if connection.has_pending_writes() {
+            Interest::READABLE | Interest::WRITABLE
+        } else {
+            Interest::READABLE
+        };
+
+        if let Err(err) = self.poll.registry().reregister(connection.socket_mut(), token, interest) {
+            warn!(%err, "Dropping session");
+            self.close(token, DisconnectReason::Error);
+        }
+    }
+
+    /// Reads, dispatches and flushes until the connection has nothing left to do.
+    fn process(
+        connection: &mut Connection<TcpStream>,
+        parser: &PacketParser,
+        handler: &PacketHandler,
+        mut readable: bool,
+    ) -> Result<Outcome, ConnectionError> {
+        loop {
+            let status = if readable { connection.receive(parser)? } else { ReadStatus::Open };
+
+            while connection.session.is_open() {
+                let Some(packet) = connection.session.recv_queue.pop_front() else {
+                    break;
+                };
+
+                let name = parser.info(packet.packet_id).map_or("UNKNOWN", |info| info.name.as_str());
+                let _entered = tracing::info_span!("packet", packet_id = %format_args!("{:#06X}", packet.packet_id), packet = %name).entered();
+                debug!(length = packet.length, "Received packet");
+
+                // A panicking system only takes its own session down.
+                let session = &mut connection.session;
+                if panic::catch_unwind(AssertUnwindSafe(|| handler(session, &packet))).is_err() {
+                    error!("Packet handler panicked, dropping session");
+                    return Ok(Outcome::Disconnect(DisconnectReason::Error));
+                }
+            }
+
+            let resumed = connection.flush()?;
+
+            if connection.is_flushed_for_close() {
+                return Ok(Outcome::Disconnect(DisconnectReason::Kicked));
+            }
+            if status == ReadStatus::Closed {
+                return Ok(Outcome::Disconnect(DisconnectReason::PeerClosed));
+            }
+            if !resumed && status != ReadStatus::Full {
+                return Ok(Outcome::Open);
+            }
+
+            // Data that arrived while paused, or wasn't read because the
+            // receive buffer was full, won't raise another event.
+            readable = true;
+        }
+    }
+
+    /// Drops sessions that went idle or are taking too long to close.
+    fn sweep(&mut self) {
+        let now = Instant::now();
+        let expired: Vec<(Token, DisconnectReason)> = self
+            .connections
+            .iter()
+            .filter_map(|(token, connection)| {
+                let idle = now.duration_since(connection.last_activity());
+                if connection.session.is_open() {
+                    let expired = self.config.idle_timeout.is_some_and(|timeout| idle >= timeout);
+                    expired.then_some((*token, DisconnectReason::IdleTimeout))
+                } else {
+                    (idle >= self.config.close_timeout).then_some((*token, DisconnectReason::CloseTimeout))
+                }
+            })
+            .collect();
+
+        for (token, reason) in expired {
+            self.close(token, reason);
+        }
+    }
+
+    fn close(&mut self, token: Token, reason: DisconnectReason) {
+        let Some(mut connection) = self.connections.remove(&token) else {
+            return;
+        };
+        self.tokens.remove(&connection.session.id());
+
+        let _ = self.poll.registry().deregister(connection.socket_mut());
+        let _ = connection.socket().shutdown(std::net::Shutdown::Both);
+
+        let session = &mut connection.session;
+        let span = session.span().clone();
+        let _entered = span.enter();
+        info!(?reason, "Player disconnected");
+        if panic::catch_unwind(AssertUnwindSafe(|| session.disconnect(reason, &self.disconnect_hooks))).is_err() {
+            error!("Disconnect hook panicked");
+            self.registry.remove(session.id());
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use mio::Waker;
+
+use crate::PlayerSession;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SessionId(u64);
+
+impl SessionId {
+    pub fn get(self) -> u64 {
+        self.0
+    }
+}
+
+impl Display for SessionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// Public view of a registered session.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionInfo {
+    pub id: SessionId,
+    pub peer_addr: SocketAddr,
+    pub account_id: Option<u32>,
+    pub char_id: Option<u32>,
+}
+
+/// Work run on a session by the network thread owning it, see
+/// `SessionRegistry::run`.
+pub type SessionTask = Box<dyn FnOnce(&mut PlayerSession) + Send>;
+
+/// Work handed to the network thread owning a session.
+pub(crate) enum Command {
+    Accept(mio::net::TcpStream, SocketAddr),
+    Send(SessionId, Vec<u8>),
+    Run(SessionId, SessionTask),
+    Close(SessionId),
+}
+
+/// How to reach the network thread owning a session.
+#[derive(Debug, Clone)]
+pub(crate) struct Route {
+    pub commands: Sender<Command>,
+    pub waker: Arc<Waker>,
+}
+
+impl Route {
+    pub fn send(&self, command: Command) -> bool {
+        self.commands.send(command).is_ok() && self.waker.wake().is_ok()
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    info: SessionInfo,
+    route: Option<Route>,
+}
+
+#[derive(Debug, Default)]
+struct Sessions {
+    entries: HashMap<SessionId, Entry>,
+    by_account: HashMap<u32, SessionId>,
+    by_char: HashMap<u32, SessionId>,
+}
+
+/// Every live session, shared by the network threads and the systems.
+/// Cloning is cheap and every clone refers to the same sessions.
+#[derive(Debug, Clone, Default)]
+pub struct SessionRegistry {
+    sessions: Arc<RwLock<Sessions>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl SessionRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // A panicking system can't leave the maps half updated, so a poisoned
+    // lock is still safe to use.
+    fn read(&self) -> RwLockReadGuard<'_, Sessions> {
+        self.sessions.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, Sessions> {
+        self.sessions.write().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub(crate) fn insert(&self, peer_addr: SocketAddr, route: Option<Route>) -> SessionId {
+        let id = SessionId(self.next_id.fetch_add(1, Ordering::Relaxed) + 1);
+        let info = SessionInfo {
+            id,
+            peer_addr,
+            account_id: None,
+            char_id: None,
+        };
+
+        self.write().entries.insert(id, Entry { info, route });
+        id
+    }
+
+    pub(crate) fn remove(&self, id: SessionId) {
+        let mut sessions = self.write();
+        let Some(entry) = sessions.entries.remove(&id) else {
+            return;
+        };
+
+        if let Some(account_id) = entry.info.account_id {
+            if sessions.by_account.get(&account_id) == Some(&id) {
+                sessions.by_account.remove(&account_id);
+            }
+        }
+        if let Some(char_id) = entry.info.char_id {
+            if sessions.by_char.get(&char_id) == Some(&id) {
+                sessions.by_char.remove(&char_id);
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.read().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, id: SessionId) -> Option<SessionInfo> {
+        self.read().entries.get(&id).map(|entry| entry.info.clone())
+    }
+
+    pub fn find_by_account(&self, account_id: u32) -> Option<SessionId> {
+        self.read().by_account.get(&account_id).copied()
+    }
+
+    pub fn find_by_char(&self, char_id: u32) -> Option<SessionId> {
+        self.read().by_char.get(&char_id).copied()
+    }
+
+    pub fn sessions(&self) -> Vec<SessionInfo> {
+        self.read().entries.values().map(|entry| entry.info.clone()).collect()
+    }
+
+    /// Associates an account with a session, replacing any previous one.
+    /// Returns `false` if the session is gone.
+    pub(crate) fn bind_account(&self, id: SessionId, account_id: Option<u32>) -> bool {
+        let mut sessions = self.write();
+        let Some(entry) = sessions.entries.get_mut(&id) else {
+            return false;
+        };
+
+        let previous = std::mem::replace(&mut entry.info.account_id, account_id);
+        if let Some(previous) = previous {
+            if sessions.by_account.get(&previous) == Some(&id) {
+                sessions.by_account.remove(&previous);
+            }
+        }
+        if let Some(account_id) = account_id {
+            sessions.by_account.insert(account_id, id);
+        }
+        true
+    }
+
+    pub(crate) fn bind_char(&self, id: SessionId, char_id: Option<u32>) -> bool {
+        let mut sessions = self.write();
+        let Some(entry) = sessions.entries.get_mut(&id) else {
+            return false;
+        };
+
+        let previous = std::mem::replace(&mut entry.info.char_id, char_id);
+        if let Some(previous) = previous {
+            if sessions.by_char.get(&previous) == Some(&id) {
+                sessions.by_char.remove(&previous);
+            }
+        }
+        if let Some(char_id) = char_id {
+            sessions.by_char.insert(char_id, id);
+        }
+        true
+    }
+
+    fn route(&self, id: SessionId) -> Option<Route> {
+        self.read().entries.get(&id).and_then(|entry| entry.route.clone())
+    }
+
+    /// Queues an encoded packet on any session. It is sent by the network
+    /// thread owning the session, after the packet being processed there.
+    /// Returns `false` if the session is gone.
+    pub fn send_to(&self, id: SessionId, packet: Vec<u8>) -> bool {
+        self.route(id).is_some_and(|route| route.send(Command::Send(id, packet)))
+    }
+
+    /// Runs `task` on a session, on the network thread owning it. Used to
+    /// give a session the replies other servers sent about it, which never
+    /// go through the packet handler.
+    pub fn run(&self, id: SessionId, task: SessionTask) -> bool {
+        self.route(id).is_some_and(|route| route.send(Command::Run(id, task)))
+    }
+
+    /// Closes a session once its queued packets were sent.
+    pub fn close(&self, id: SessionId) -> bool {
+        self.route(id).is_some_and(|route| route.send(Command::Close(id)))
+    }
+}
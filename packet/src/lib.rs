@@ -1,166 +1,567 @@
 
-use std::io::{Cursor, Read, BufReader};
-use std::fmt::Debug;
+use std::io::{Cursor, Read};
+use std::fmt::{self, Debug, Display};
 use byteorder::{LittleEndian, ReadBytesExt};
-use std::fs::File;
 use std::collections::BTreeMap;
 use std::path::Path;
+use std::str::FromStr;
+
+mod registry;
+mod string;
+mod table;
+
+pub use registry::{DeclaredPacket, PacketDescriptor, PacketMismatch, PacketRegistry};
+pub use string::{decode_fixed_str, decode_fixed_string, encode_fixed_string, StringEncoding};
+pub use table::{PacketDirection, PacketInfo, PacketTable, PacketTableError};
+
+// Used by `#[derive(Packet)]` to declare every packet struct.
+#[doc(hidden)]
+pub use inventory;
 
 const MIN_PACKET_SIZE: usize = 2;
 
-#[derive(Debug)]
+/// Client version used when none is given, enabling every `since` field.
+pub const PACKETVER_LATEST: u32 = u32::MAX;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PacketLen {
 	Fixed(i16),
 	Variable,
 }
 
+#[derive(Debug)]
+pub enum PacketError {
+	UnknownId(u16),
+	Truncated { expected: usize, actual: usize },
+	LengthMismatch { packet_id: u16, length: usize },
+	InvalidVariableLength { packet_id: u16, length: i16 },
+	FieldDecode(&'static str),
+	FieldEncode(&'static str),
+}
+
+impl Display for PacketError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			PacketError::UnknownId(packet_id) => write!(f, "unknown packet id {:#06X}", packet_id),
+			PacketError::Truncated { expected, actual } => write!(f, "truncated data: expected at least {} bytes, got {}", expected, actual),
+			PacketError::LengthMismatch { packet_id, length } => write!(f, "packet {:#06X} can't have a length of {} bytes", packet_id, length),
+			PacketError::InvalidVariableLength { packet_id, length } => write!(f, "variable packet {:#06X} declares an invalid length of {}", packet_id, length),
+			PacketError::FieldDecode(field) => write!(f, "failed to decode field '{}'", field),
+			PacketError::FieldEncode(field) => write!(f, "failed to encode field '{}'", field),
+		}
+	}
+}
+
+impl std::error::Error for PacketError {}
+
 #[derive(Debug)]
 pub struct RawPacket {
 	pub packet_id: u16,
 	pub length: usize,
+	packetver: u32,
 	buffer: Vec<u8>,
 }
 
 impl RawPacket {
-	pub fn parse<P: Packet>(&self) -> Option<P> {
-		<P>::deserialize(&self.buffer[..self.length])
+	/// Decodes the packet with the layout of the client version its parser was built for.
+	pub fn parse<P: Packet>(&self) -> Result<P, PacketError> {
+		<P>::deserialize_for(&self.buffer[..self.length], self.packetver)
+	}
+
+	/// Client version the packet was received from, `PACKETVER_LATEST` when unknown.
+	pub fn packetver(&self) -> u32 {
+		self.packetver
+	}
+
+	pub fn as_packet_ref(&self) -> RawPacketRef<'_> {
+		RawPacketRef {
+			packet_id: self.packet_id,
+			length: self.length,
+			packetver: self.packetver,
+			buffer: &self.buffer[..self.length],
+		}
+	}
+}
+
+/// A frame borrowed from a receive buffer, for decoding without copying it.
+#[derive(Debug, Clone, Copy)]
+pub struct RawPacketRef<'a> {
+	pub packet_id: u16,
+	pub length: usize,
+	packetver: u32,
+	buffer: &'a [u8],
+}
+
+impl<'a> RawPacketRef<'a> {
+	fn new(frame: &'a [u8], packetver: Option<u32>) -> Self {
+		Self {
+			packet_id: u16::from_le_bytes([frame[0], frame[1]]),
+			length: frame.len(),
+			packetver: packetver.unwrap_or(PACKETVER_LATEST),
+			buffer: frame,
+		}
+	}
+
+	pub fn packetver(&self) -> u32 {
+		self.packetver
+	}
+
+	pub fn as_bytes(&self) -> &'a [u8] {
+		self.buffer
+	}
+
+	/// Borrowed view of the packet, reading its fields only when accessed.
+	pub fn view<P: Packet>(&self) -> Result<P::Ref<'a>, PacketError> {
+		<P::Ref<'a> as PacketView<'a>>::view_for(self.buffer, self.packetver)
+	}
+
+	pub fn parse<P: Packet>(&self) -> Result<P, PacketError> {
+		<P>::deserialize_for(self.buffer, self.packetver)
+	}
+
+	/// Copies the frame, for keeping it past the lifetime of the receive buffer.
+	pub fn to_raw_packet(&self) -> RawPacket {
+		RawPacket {
+			packet_id: self.packet_id,
+			length: self.length,
+			packetver: self.packetver,
+			buffer: self.buffer.to_vec(),
+		}
+	}
+}
+
+/// Iterator over the complete frames at the start of a buffer, see `PacketParser::frames`.
+#[derive(Debug)]
+pub struct Frames<'a> {
+	parser: &'a PacketParser,
+	buf: &'a [u8],
+	start: usize,
+	failed: bool,
+}
+
+impl<'a> Frames<'a> {
+	/// Bytes taken by the frames returned so far. Whatever comes after them
+	/// is an incomplete frame.
+	pub fn consumed(&self) -> usize {
+		self.start
+	}
+}
+
+impl<'a> Iterator for Frames<'a> {
+	type Item = Result<RawPacketRef<'a>, PacketError>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		if self.failed {
+			return None;
+		}
+
+		match self.parser.frame_len(&self.buf[self.start..]) {
+			Ok(Some(length)) => {
+				let frame = &self.buf[self.start..(self.start + length)];
+				self.start += length;
+				Some(Ok(RawPacketRef::new(frame, self.parser.packetver)))
+			},
+			Ok(None) => None,
+			Err(err) => {
+				self.failed = true;
+				Some(Err(err))
+			},
+		}
 	}
 }
 
 #[derive(Debug)]
 pub struct PacketParser {
-	length_table: BTreeMap<u16, (String, PacketLen)>,
+	packetver: Option<u32>,
+	length_table: BTreeMap<u16, PacketInfo>,
 }
 
 impl PacketParser {
-	pub fn new(path: &str) -> Self {
-		let mut length_table = BTreeMap::new();
-		
-		let file = File::open(Path::new(path)).unwrap();
-		let mut reader = BufReader::new(file);
-
-		let mut buf = String::new();
-		reader.read_to_string(&mut buf).unwrap();
-
-		let rows = buf
-			.split("\n")
-			.filter(|line| line.trim().len() > 0 && !line.starts_with("#"));
-
-		for line in rows {
-			let sanitized = line
-				.replace("\r", "");
-
-			let row: Vec<&str> = sanitized
-				.split("\t")
-				.filter(|line| line.trim().len() > 0)
-				.collect();
-
-			if row.len() != 3 {
-				continue;
-			} else if row[0].len() < 3 {
-				continue;
-			}
+	pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, PacketTableError> {
+		Ok(Self::from_table(&PacketTable::from_path(path)?, None))
+	}
 
-			let id = u16::from_str_radix(&row[0][2..], 16).ok();
-			let len = match i16::from_str_radix(&row[1], 10).ok() {
-				Some(x) if x >= 2 => Some(PacketLen::Fixed(x)),
-				Some(x) if x < 0 => Some(PacketLen::Variable),
-				Some(_) => None,
-				None => None
-			};
-			let name = row[2];
-
-			if id.is_none() || len.is_none() {
-				println!("Could not parse row of packet length file '{}': ({})", path, row.join(" | "));
-				continue;
-			}
+	pub fn from_reader<R: Read>(reader: R) -> Result<Self, PacketTableError> {
+		Ok(Self::from_table(&PacketTable::from_reader(reader)?, None))
+	}
+
+	/// Builds a parser with the packet lengths of the given client version,
+	/// or of the newest section of the table when `packetver` is `None`.
+	/// Rows marked as transmitted are left out, the server never receiving
+	/// them.
+	pub fn from_table(table: &PacketTable, packetver: Option<u32>) -> Self {
+		Self::with_direction(table, packetver, PacketDirection::Transmitted)
+	}
+
+	/// Builds a parser for the packets the server transmits, to read its
+	/// replies from the client side.
+	pub fn from_table_transmitted(table: &PacketTable, packetver: Option<u32>) -> Self {
+		Self::with_direction(table, packetver, PacketDirection::Received)
+	}
 
+	fn with_direction(table: &PacketTable, packetver: Option<u32>, excluded: PacketDirection) -> Self {
+		let length_table = table
+			.resolve(packetver)
+			.into_iter()
+			.filter(|(_, info)| info.direction != Some(excluded))
+			.collect();
 
-			length_table.insert(id.unwrap(), (name.to_string(), len.unwrap()));
+		Self {
+			packetver,
+			length_table,
 		}
+	}
+
+	/// Builds a parser straight from the packet structs, without a table file.
+	pub fn from_registry(registry: &PacketRegistry) -> Self {
+		let length_table = registry
+			.descriptors()
+			.map(|descriptor| (descriptor.id, PacketInfo {
+				name: descriptor.name.to_string(),
+				len: descriptor.len,
+				direction: None,
+				aliases: Vec::new(),
+			}))
+			.collect();
 
-		println!("Finished loading packet lengths from '{}'. Found {} valid packets.", path, length_table.len());
-		
 		Self {
-			length_table
+			packetver: None,
+			length_table,
 		}
 	}
 
-	pub fn extract_packets(&self, buf: &[u8]) -> Vec<RawPacket> {
-		let mut cur = Cursor::new(buf);
+	pub fn packetver(&self) -> Option<u32> {
+		self.packetver
+	}
+
+	pub fn len(&self) -> usize {
+		self.length_table.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.length_table.is_empty()
+	}
+
+	pub fn info(&self, packet_id: u16) -> Option<&PacketInfo> {
+		self.length_table.get(&packet_id)
+	}
+
+	/// Finds a packet id by its name or one of its aliases.
+	pub fn id_of(&self, name: &str) -> Option<u16> {
+		self.length_table
+			.iter()
+			.find(|(_, info)| info.matches_name(name))
+			.map(|(id, _)| *id)
+	}
+
+	pub fn extract_packets(&self, buf: &[u8]) -> Result<Vec<RawPacket>, PacketError> {
 		let mut packets = Vec::new();
+		let mut start = 0usize;
 
+		while start < buf.len() {
+			match self.frame_len(&buf[start..])? {
+				Some(length) => {
+					packets.push(RawPacketRef::new(&buf[start..(start + length)], self.packetver).to_raw_packet());
+					start += length;
+				},
+				None => return Err(PacketError::Truncated {
+					expected: self.frame_len_hint(&buf[start..]),
+					actual: buf.len() - start,
+				}),
+			}
+		}
+
+		Ok(packets)
+	}
+
+	/// Borrows every complete frame in `buf` without copying it. Stops at the
+	/// first incomplete frame, see `Frames::consumed`.
+	pub fn frames<'a>(&'a self, buf: &'a [u8]) -> Frames<'a> {
+		Frames {
+			parser: self,
+			buf,
+			start: 0,
+			failed: false,
+		}
+	}
+
+	/// Returns the length of the frame at the start of `buf`, or `None` if
+	/// more bytes are needed to complete it.
+	fn frame_len(&self, buf: &[u8]) -> Result<Option<usize>, PacketError> {
 		if buf.len() < MIN_PACKET_SIZE {
-			println!("Buffer is smaller than the minimum packet size.");
-			return packets;
+			return Ok(None);
 		}
 
-		while buf.len() > cur.position() as usize && MIN_PACKET_SIZE <= (buf.len() - cur.position() as usize) {
-			let packet_id: u16;
-			let start = cur.position() as usize;
-			if let Some(pid) = cur.read_u16::<LittleEndian>().ok() {
-				packet_id = pid;
-			} else {
-				break;
-			}
+		let mut cur = Cursor::new(buf);
+		let packet_id = cur.read_u16::<LittleEndian>().map_err(|_| PacketError::FieldDecode("packet_id"))?;
+
+		let length = match self.length_table.get(&packet_id) {
+			Some(PacketInfo { len: PacketLen::Fixed(len), .. }) => *len as usize,
+			Some(PacketInfo { len: PacketLen::Variable, .. }) => match cur.read_i16::<LittleEndian>() {
+				Ok(len) if len >= 4 => len as usize,
+				Ok(length) => return Err(PacketError::InvalidVariableLength { packet_id, length }),
+				Err(_) => return Ok(None),
+			},
+			None => return Err(PacketError::UnknownId(packet_id)),
+		};
+
+		if buf.len() < length {
+			return Ok(None);
+		}
 
-			let length = match self.length_table.get(&packet_id) {
-				Some((_, PacketLen::Fixed(len))) => {
-					cur.set_position((start + *len as usize) as u64);
-					*len
-				},
-				Some((_, PacketLen::Variable)) => {
-					let len = cur.read_i16::<LittleEndian>().unwrap();
-					cur.set_position((start + len as usize) as u64);
-					len
-				},
-				None => {
-					println!("Couldn't prepare unknown packet with id {:#06X}", packet_id);
-					break;
-				}
-			} as usize;
-
-			if length == 0 {
-				println!("Received packet {:#06X} with zero bytes. Discarding received bytes...", packet_id);
-				break;
-			}
-			
-			if buf.len() < start + length {
-				println!("Packet length is bigger than buffer! Packet id {:#06X} (Length: {}) | Buffer length: {}", packet_id, length, buf.len() - start);
-				break;
-			}
+		Ok(Some(length))
+	}
+
+	fn frame_len_hint(&self, buf: &[u8]) -> usize {
+		if buf.len() < MIN_PACKET_SIZE {
+			return MIN_PACKET_SIZE;
+		}
+
+		let packet_id = u16::from_le_bytes([buf[0], buf[1]]);
+		match self.length_table.get(&packet_id) {
+			Some(PacketInfo { len: PacketLen::Fixed(len), .. }) => *len as usize,
+			Some(PacketInfo { len: PacketLen::Variable, .. }) if buf.len() >= 4 => i16::from_le_bytes([buf[2], buf[3]]) as usize,
+			_ => 4,
+		}
+	}
+}
+
+/// Reassembles packets from a byte stream, keeping incomplete frames
+/// between reads.
+#[derive(Debug, Default)]
+pub struct PacketDecoder {
+	buffer: Vec<u8>,
+	start: usize,
+}
+
+impl PacketDecoder {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn feed(&mut self, data: &[u8]) {
+		if self.start > 0 {
+			self.buffer.drain(..self.start);
+			self.start = 0;
+		}
+		self.buffer.extend_from_slice(data);
+	}
 
-			println!("Adding packet to queue");
-			packets.push(RawPacket {
-				packet_id,
-				length,
-				buffer: buf[start..(start + length)].to_vec(),
-			});
+	/// Returns the next complete packet, if any. Framing errors can't be
+	/// recovered from, so the buffered data is discarded when one happens.
+	pub fn next_packet(&mut self, parser: &PacketParser) -> Result<Option<RawPacket>, PacketError> {
+		Ok(self.next_packet_ref(parser)?.map(|packet| packet.to_raw_packet()))
+	}
+
+	/// Like `next_packet`, but borrows the frame from the decoder's buffer
+	/// instead of copying it.
+	pub fn next_packet_ref(&mut self, parser: &PacketParser) -> Result<Option<RawPacketRef<'_>>, PacketError> {
+		match parser.frame_len(&self.buffer[self.start..]) {
+			Ok(Some(length)) => {
+				let frame = &self.buffer[self.start..(self.start + length)];
+				self.start += length;
+				Ok(Some(RawPacketRef::new(frame, parser.packetver)))
+			},
+			Ok(None) => Ok(None),
+			Err(err) => {
+				self.clear();
+				Err(err)
+			},
 		}
+	}
 
-		packets
+	/// Number of buffered bytes still waiting for the rest of their frame.
+	pub fn pending(&self) -> usize {
+		self.buffer.len() - self.start
+	}
+
+	/// Length the incomplete frame will have, as far as its first bytes
+	/// tell, 0 when nothing is pending.
+	pub fn pending_frame_len(&self, parser: &PacketParser) -> usize {
+		match self.pending() {
+			0 => 0,
+			_ => parser.frame_len_hint(&self.buffer[self.start..]),
+		}
+	}
+
+	pub fn clear(&mut self) {
+		self.buffer.clear();
+		self.start = 0;
+	}
+}
+
+impl FromStr for PacketParser {
+	type Err = PacketTableError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		Ok(Self::from_table(&s.parse()?, None))
 	}
 }
 
 pub trait Packet: Default + Debug + Sized {
+	/// Borrowed view generated next to the packet struct, e.g. `PacketCaLoginRef`.
+	type Ref<'a>: PacketView<'a, Owned = Self>;
+
+	const ID: u16;
+
+	const NAME: &'static str;
+
+	const LENGTH: PacketLen;
+
 	fn new() -> Self;
 
-	fn serialize(&self) -> Option<Vec<u8>>;
+	fn serialize(&self) -> Result<Vec<u8>, PacketError> {
+		self.serialize_for(PACKETVER_LATEST)
+	}
+
+	fn serialize_for(&self, packetver: u32) -> Result<Vec<u8>, PacketError>;
 
-	fn deserialize(cursor: &[u8]) -> Option<Self>;
+	fn deserialize(cursor: &[u8]) -> Result<Self, PacketError> {
+		Self::deserialize_for(cursor, PACKETVER_LATEST)
+	}
 
+	fn deserialize_for(cursor: &[u8], packetver: u32) -> Result<Self, PacketError>;
+
+	/// Whether a buffer of `length` bytes can hold this packet. Never panics,
+	/// even for buffers shorter than the fixed part.
 	fn has_valid_length(&self, length: usize) -> bool;
 
-	fn len(&self) -> usize;
+	/// Encoded size of the packet, computed without serializing it.
+	fn len(&self) -> usize {
+		self.len_for(PACKETVER_LATEST)
+	}
+
+	fn len_for(&self, packetver: u32) -> usize;
+
+	fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
 }
 
 pub trait PacketFragment: Default + Debug + Sized {
-	fn serialize(&self) -> Option<Vec<u8>>;
+	/// Encoded size of the fixed part of the fragment.
+	const BASE_LEN: usize;
+
+	/// Whether the fragment ends with a length-prefixed list.
+	const VARIABLE: bool;
+
+	fn serialize(&self) -> Result<Vec<u8>, PacketError> {
+		self.serialize_for(PACKETVER_LATEST)
+	}
 
-	fn deserialize(cursor: &mut std::io::Cursor<&[u8]>) -> Option<Self>;
+	fn serialize_for(&self, packetver: u32) -> Result<Vec<u8>, PacketError>;
+
+	fn deserialize(cursor: &mut std::io::Cursor<&[u8]>) -> Result<Self, PacketError> {
+		Self::deserialize_for(cursor, PACKETVER_LATEST)
+	}
+
+	fn deserialize_for(cursor: &mut std::io::Cursor<&[u8]>, packetver: u32) -> Result<Self, PacketError>;
+
+	fn len(&self) -> usize {
+		self.len_for(PACKETVER_LATEST)
+	}
 
-	fn get_base_len() -> usize;
+	fn len_for(&self, packetver: u32) -> usize;
+
+	fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	fn get_base_len() -> usize {
+		Self::BASE_LEN
+	}
+
+	/// Size of the fixed part of the fragment for a client version.
+	fn base_len_for(packetver: u32) -> usize;
+
+	/// Moves `cursor` past an encoded fragment without decoding it.
+	fn skip_for(cursor: &mut std::io::Cursor<&[u8]>, packetver: u32) -> Result<(), PacketError>;
+}
+
+/// A packet read in place from a borrowed buffer. Construction walks the
+/// frame once to validate it, the accessors then decode single fields.
+pub trait PacketView<'a>: Copy + Debug {
+	type Owned: Packet;
+
+	fn view(buffer: &'a [u8]) -> Result<Self, PacketError> {
+		Self::view_for(buffer, PACKETVER_LATEST)
+	}
+
+	fn view_for(buffer: &'a [u8], packetver: u32) -> Result<Self, PacketError>;
+
+	fn as_bytes(&self) -> &'a [u8];
+
+	fn to_packet(&self) -> Result<Self::Owned, PacketError>;
 }
 
 extern crate packet_derive;
-pub use packet_derive::{Packet, PacketFragment};
\ No newline at end of file
+pub use packet_derive::{Packet, PacketFragment};
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const TABLE: &str = "0x0064 6 FIXED recv\n0x0065 -1 VARIABLE recv\n";
+
+	fn fixed() -> Vec<u8> {
+		vec![0x64, 0x00, 1, 2, 3, 4]
+	}
+
+	fn variable() -> Vec<u8> {
+		vec![0x65, 0x00, 7, 0, 5, 6, 7]
+	}
+
+	#[test]
+	fn frame_fed_byte_by_byte() {
+		let parser: PacketParser = TABLE.parse().unwrap();
+		let mut decoder = PacketDecoder::new();
+		let frame = variable();
+
+		for (i, byte) in frame.iter().enumerate() {
+			assert!(decoder.next_packet(&parser).unwrap().is_none());
+			assert_eq!(decoder.pending(), i);
+			decoder.feed(&[*byte]);
+		}
+
+		let packet = decoder.next_packet(&parser).unwrap().unwrap();
+		assert_eq!(packet.packet_id, 0x0065);
+		assert_eq!(packet.as_packet_ref().as_bytes(), frame);
+		assert!(decoder.next_packet(&parser).unwrap().is_none());
+		assert_eq!(decoder.pending(), 0);
+	}
+
+	#[test]
+	fn two_frames_in_one_chunk() {
+		let parser: PacketParser = TABLE.parse().unwrap();
+		let mut decoder = PacketDecoder::new();
+		let mut chunk = fixed();
+		chunk.extend(variable());
+		// The start of a third frame, completed by the next read.
+		chunk.extend(&fixed()[..3]);
+		decoder.feed(&chunk);
+
+		assert_eq!(decoder.next_packet(&parser).unwrap().unwrap().as_packet_ref().as_bytes(), fixed());
+		assert_eq!(decoder.next_packet(&parser).unwrap().unwrap().as_packet_ref().as_bytes(), variable());
+		assert!(decoder.next_packet(&parser).unwrap().is_none());
+		assert_eq!(decoder.pending(), 3);
+
+		decoder.feed(&fixed()[3..]);
+		assert_eq!(decoder.next_packet(&parser).unwrap().unwrap().as_packet_ref().as_bytes(), fixed());
+		assert_eq!(decoder.pending(), 0);
+	}
+
+	#[test]
+	fn transmitted_rows_are_not_received() {
+		let table: PacketTable = "0x0064 6 FIXED recv\n0x0069 -1 ACCEPT send\n0x0081 3 UNMARKED\n".parse().unwrap();
+
+		let parser = PacketParser::from_table(&table, None);
+		assert!(parser.info(0x0064).is_some());
+		assert!(parser.info(0x0069).is_none());
+		assert!(parser.info(0x0081).is_some());
+		assert!(parser.extract_packets(&[0x69, 0x00, 4, 0]).is_err());
+
+		let parser = PacketParser::from_table_transmitted(&table, None);
+		assert!(parser.info(0x0064).is_none());
+		assert!(parser.info(0x0069).is_some());
+		assert!(parser.info(0x0081).is_some());
+	}
+}
@@ -0,0 +1,118 @@
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+
+use crate::{Packet, PacketLen, PacketTable};
+
+/// Id, name and length of a packet as declared by its `#[derive(Packet)]` struct.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PacketDescriptor {
+	pub id: u16,
+	pub name: &'static str,
+	pub len: PacketLen,
+}
+
+impl PacketDescriptor {
+	pub fn of<P: Packet>() -> Self {
+		Self {
+			id: P::ID,
+			name: P::NAME,
+			len: P::LENGTH,
+		}
+	}
+}
+
+/// A `#[derive(Packet)]` struct and the module declaring it, collected from
+/// every crate of the program.
+#[derive(Debug)]
+pub struct DeclaredPacket {
+	pub module: &'static str,
+	pub descriptor: PacketDescriptor,
+}
+
+inventory::collect!(DeclaredPacket);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PacketMismatch {
+	Missing(PacketDescriptor),
+	Length { descriptor: PacketDescriptor, table_len: PacketLen },
+	Name { descriptor: PacketDescriptor, table_name: String },
+}
+
+impl Display for PacketMismatch {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			PacketMismatch::Missing(descriptor) => write!(f, "{} ({:#06X}) is not in the packet table", descriptor.name, descriptor.id),
+			PacketMismatch::Length { descriptor, table_len } => write!(
+				f, "{} ({:#06X}) is {:?} but the packet table says {:?}",
+				descriptor.name, descriptor.id, descriptor.len, table_len,
+			),
+			PacketMismatch::Name { descriptor, table_name } => write!(
+				f, "{:#06X} is {} but the packet table calls it {}",
+				descriptor.id, descriptor.name, table_name,
+			),
+		}
+	}
+}
+
+/// Packets known at compile time, collected from their `Packet` impls.
+/// Each `#[derive(Packet)]` declares its struct, see `register_module`.
+#[derive(Debug, Default, Clone)]
+pub struct PacketRegistry {
+	packets: BTreeMap<u16, PacketDescriptor>,
+}
+
+impl PacketRegistry {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn register<P: Packet>(&mut self) -> &mut Self {
+		self.packets.insert(P::ID, PacketDescriptor::of::<P>());
+		self
+	}
+
+	/// Registers the declared packets accepted by `filter`.
+	pub fn register_declared<F: Fn(&DeclaredPacket) -> bool>(&mut self, filter: F) -> &mut Self {
+		for declared in inventory::iter::<DeclaredPacket>() {
+			if filter(declared) {
+				self.packets.insert(declared.descriptor.id, declared.descriptor);
+			}
+		}
+		self
+	}
+
+	/// Registers every packet declared in `module`, e.g. `packets::auth`.
+	pub fn register_module(&mut self, module: &str) -> &mut Self {
+		self.register_declared(|declared| declared.module == module)
+	}
+
+	pub fn get(&self, packet_id: u16) -> Option<&PacketDescriptor> {
+		self.packets.get(&packet_id)
+	}
+
+	pub fn descriptors(&self) -> impl Iterator<Item = &PacketDescriptor> {
+		self.packets.values()
+	}
+
+	/// Compares the registered packets with an external length table, so the
+	/// structs and the table can't silently drift apart. Rows of both
+	/// directions are compared.
+	pub fn check_against(&self, table: &PacketTable, packetver: Option<u32>) -> Vec<PacketMismatch> {
+		let rows = table.resolve(packetver);
+		self.packets
+			.values()
+			.filter_map(|descriptor| match rows.get(&descriptor.id) {
+				None => Some(PacketMismatch::Missing(*descriptor)),
+				Some(info) if info.len != descriptor.len => Some(PacketMismatch::Length {
+					descriptor: *descriptor,
+					table_len: info.len,
+				}),
+				Some(info) if !info.matches_name(descriptor.name) => Some(PacketMismatch::Name {
+					descriptor: *descriptor,
+					table_name: info.name.clone(),
+				}),
+				Some(_) => None,
+			})
+			.collect()
+	}
+}
@@ -0,0 +1,50 @@
+use std::borrow::Cow;
+
+use encoding_rs::{Encoding, EUC_KR, UTF_8, WINDOWS_1252};
+
+/// Encodings supported by `#[packet(string(...))]` fields.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StringEncoding {
+	Cp949,
+	Utf8,
+	Latin1,
+}
+
+impl StringEncoding {
+	fn encoding(self) -> &'static Encoding {
+		match self {
+			// encoding_rs' EUC-KR decoder is the Windows code page 949.
+			StringEncoding::Cp949 => EUC_KR,
+			StringEncoding::Utf8 => UTF_8,
+			StringEncoding::Latin1 => WINDOWS_1252,
+		}
+	}
+}
+
+/// Encodes `value` into a zero padded buffer of exactly `len` bytes.
+/// Returns `None` if it doesn't fit or can't be represented.
+pub fn encode_fixed_string(value: &str, len: usize, encoding: StringEncoding) -> Option<Vec<u8>> {
+	let (bytes, _, had_errors) = encoding.encoding().encode(value);
+
+	if had_errors || bytes.len() > len {
+		return None;
+	}
+
+	let mut buf = bytes.into_owned();
+	buf.resize(len, 0);
+	Some(buf)
+}
+
+/// Decodes a zero padded string, ignoring everything after the first NUL.
+pub fn decode_fixed_string(bytes: &[u8], encoding: StringEncoding) -> Option<String> {
+	decode_fixed_str(bytes, encoding).map(Cow::into_owned)
+}
+
+/// Like `decode_fixed_string`, but borrows from `bytes` when no conversion is needed.
+pub fn decode_fixed_str(bytes: &[u8], encoding: StringEncoding) -> Option<Cow<'_, str>> {
+	let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+
+	encoding
+		.encoding()
+		.decode_without_bom_handling_and_without_replacement(&bytes[..end])
+}
@@ -0,0 +1,193 @@
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::PacketLen;
+
+const SECTION_PREFIX: &str = "packet_ver:";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PacketDirection {
+	Received,
+	Transmitted,
+}
+
+impl FromStr for PacketDirection {
+	type Err = ();
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s.to_ascii_lowercase().as_str() {
+			"r" | "recv" | "received" => Ok(PacketDirection::Received),
+			"t" | "send" | "transmitted" => Ok(PacketDirection::Transmitted),
+			_ => Err(()),
+		}
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct PacketInfo {
+	pub name: String,
+	pub len: PacketLen,
+	pub direction: Option<PacketDirection>,
+	pub aliases: Vec<String>,
+}
+
+impl PacketInfo {
+	pub fn matches_name(&self, name: &str) -> bool {
+		self.name == name || self.aliases.iter().any(|alias| alias == name)
+	}
+}
+
+#[derive(Debug)]
+pub enum PacketTableError {
+	Io(io::Error),
+	InvalidRow { line: usize, reason: String },
+	InvalidSection { line: usize, reason: String },
+}
+
+impl Display for PacketTableError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			PacketTableError::Io(err) => write!(f, "failed to read packet table: {}", err),
+			PacketTableError::InvalidRow { line, reason } => write!(f, "invalid packet row at line {}: {}", line, reason),
+			PacketTableError::InvalidSection { line, reason } => write!(f, "invalid packet_ver section at line {}: {}", line, reason),
+		}
+	}
+}
+
+impl std::error::Error for PacketTableError {}
+
+impl From<io::Error> for PacketTableError {
+	fn from(error: io::Error) -> Self {
+		PacketTableError::Io(error)
+	}
+}
+
+/// A packet length database split in `packet_ver: <date>` sections.
+///
+/// Rows are `<id> <length> <name> [direction] [alias,alias...]`, separated
+/// by tabs or spaces. Rows before the first section apply to every client
+/// version, and each section overrides the entries of the sections before it.
+#[derive(Debug, Default)]
+pub struct PacketTable {
+	sections: BTreeMap<u32, BTreeMap<u16, PacketInfo>>,
+}
+
+impl PacketTable {
+	pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, PacketTableError> {
+		Self::from_reader(BufReader::new(File::open(path)?))
+	}
+
+	pub fn from_reader<R: Read>(mut reader: R) -> Result<Self, PacketTableError> {
+		let mut buf = String::new();
+		reader.read_to_string(&mut buf)?;
+		buf.parse()
+	}
+
+	/// Client versions that have their own section, in ascending order.
+	pub fn versions(&self) -> impl Iterator<Item = u32> + '_ {
+		self.sections.keys().copied().filter(|packetver| *packetver > 0)
+	}
+
+	/// Merges every section up to `packetver` (or all of them when `None`).
+	pub fn resolve(&self, packetver: Option<u32>) -> BTreeMap<u16, PacketInfo> {
+		let packetver = packetver.unwrap_or(u32::MAX);
+
+		let mut resolved = BTreeMap::new();
+		for (_, section) in self.sections.range(..=packetver) {
+			for (id, info) in section {
+				resolved.insert(*id, info.clone());
+			}
+		}
+
+		resolved
+	}
+
+	fn parse_row(line: usize, row: &str) -> Result<(u16, PacketInfo), PacketTableError> {
+		let invalid = |reason: String| PacketTableError::InvalidRow { line, reason };
+		let columns: Vec<&str> = row.split_whitespace().collect();
+
+		if !(3..=5).contains(&columns.len()) {
+			return Err(invalid(format!("expected 3 to 5 columns, found {}", columns.len())));
+		}
+
+		let id = columns[0]
+			.strip_prefix("0x")
+			.or_else(|| columns[0].strip_prefix("0X"))
+			.and_then(|hex| u16::from_str_radix(hex, 16).ok())
+			.ok_or_else(|| invalid(format!("'{}' is not a hexadecimal packet id", columns[0])))?;
+
+		let len = match columns[1].parse::<i16>().ok() {
+			Some(x) if x >= 2 => PacketLen::Fixed(x),
+			Some(x) if x < 0 => PacketLen::Variable,
+			_ => return Err(invalid(format!("'{}' is not a valid packet length", columns[1]))),
+		};
+
+		let direction = match columns.get(3) {
+			Some(direction) => Some(direction
+				.parse::<PacketDirection>()
+				.map_err(|_| invalid(format!("'{}' is not a packet direction", direction)))?),
+			None => None,
+		};
+
+		let aliases = columns
+			.get(4)
+			.map(|aliases| aliases.split(',').filter(|a| !a.is_empty()).map(str::to_string).collect())
+			.unwrap_or_default();
+
+		Ok((id, PacketInfo {
+			name: columns[2].to_string(),
+			len,
+			direction,
+			aliases,
+		}))
+	}
+}
+
+impl FromStr for PacketTable {
+	type Err = PacketTableError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let mut table = Self::default();
+		let mut packetver = 0u32;
+		table.sections.insert(packetver, BTreeMap::new());
+
+		for (index, line) in s.lines().enumerate() {
+			let line_number = index + 1;
+			let row = line.trim();
+
+			if row.is_empty() || row.starts_with('#') {
+				continue;
+			}
+
+			if let Some(version) = row.strip_prefix(SECTION_PREFIX) {
+				let version = version.trim().parse::<u32>().map_err(|_| PacketTableError::InvalidSection {
+					line: line_number,
+					reason: format!("'{}' is not a client date", version.trim()),
+				})?;
+
+				if version <= packetver {
+					return Err(PacketTableError::InvalidSection {
+						line: line_number,
+						reason: format!("section {} must come after section {}", version, packetver),
+					});
+				}
+
+				packetver = version;
+				table.sections.insert(packetver, BTreeMap::new());
+				continue;
+			}
+
+			let (id, info) = Self::parse_row(line_number, row)?;
+			table.sections
+				.get_mut(&packetver)
+				.expect("current section is always present")
+				.insert(id, info);
+		}
+
+		Ok(table)
+	}
+}
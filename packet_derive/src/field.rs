@@ -0,0 +1,676 @@
+use proc_macro2::TokenStream;
+use quote::{quote, format_ident};
+use syn::{Field, GenericArgument, Ident, LitInt, LitStr, PathArguments, Type};
+
+const PRIMITIVES: &[&str] = &["u8", "i8", "u16", "i16", "u32", "i32", "u64", "i64", "f32", "f64"];
+const INTEGERS: &[&str] = &["u8", "i8", "u16", "i16", "u32", "i32", "u64", "i64"];
+
+/// Wire layout of a field type.
+pub enum FieldKind {
+	Primitive(Ident),
+	Array { elem: Box<FieldKind>, len: syn::Expr },
+	Vec(Box<FieldKind>),
+	Fragment(Type),
+	String { len: syn::Expr, encoding: Ident },
+}
+
+impl FieldKind {
+	pub fn from_type(ty: &Type) -> syn::Result<Self> {
+		match ty {
+			Type::Array(syn::TypeArray { elem, len, .. }) => Ok(FieldKind::Array {
+				elem: Box::new(Self::from_type(elem)?),
+				len: len.clone(),
+			}),
+			Type::Path(syn::TypePath { path, .. }) => {
+				let segment = path.segments.last().unwrap();
+				match segment.ident.to_string().as_str() {
+					x if PRIMITIVES.contains(&x) => Ok(FieldKind::Primitive(segment.ident.clone())),
+					"String" => Err(syn::Error::new_spanned(ty, "String fields need #[packet(string(len = N))]")),
+					"Vec" => match &segment.arguments {
+						PathArguments::AngleBracketed(gargs) => match gargs.args.first() {
+							Some(GenericArgument::Type(elem)) => match Self::from_type(elem)? {
+								FieldKind::Vec(_) => Err(syn::Error::new_spanned(ty, "nested Vec fields are not supported")),
+								elem => Ok(FieldKind::Vec(Box::new(elem))),
+							},
+							_ => Err(syn::Error::new_spanned(ty, "expected Vec<T>")),
+						},
+						_ => Err(syn::Error::new_spanned(ty, "expected Vec<T>")),
+					},
+					_ => Ok(FieldKind::Fragment(ty.clone())),
+				}
+			},
+			_ => Err(syn::Error::new_spanned(ty, "unsupported packet field type")),
+		}
+	}
+
+	/// Whether a `Vec` is nested in this kind, which only the last field of
+	/// a packet can hold.
+	fn has_nested_vec(&self) -> bool {
+		match self {
+			FieldKind::Array { elem, .. } => matches!(elem.as_ref(), FieldKind::Vec(_)) || elem.has_nested_vec(),
+			FieldKind::Vec(elem) => matches!(elem.as_ref(), FieldKind::Vec(_)) || elem.has_nested_vec(),
+			_ => false,
+		}
+	}
+
+	fn is_byte(&self) -> bool {
+		matches!(self, FieldKind::Primitive(ident) if ident == "u8")
+	}
+
+	fn is_integer(&self) -> bool {
+		matches!(self, FieldKind::Primitive(ident) if INTEGERS.contains(&ident.to_string().as_str()))
+	}
+
+	/// Expression with the encoded size, not counting `Vec` items. It is a
+	/// constant for the latest layout when `packetver` is `None`.
+	pub fn size(&self, packetver: Option<&TokenStream>) -> TokenStream {
+		match self {
+			FieldKind::Primitive(ident) => quote! { ::std::mem::size_of::<#ident>() },
+			FieldKind::Array { elem, len } => {
+				let elem_size = elem.size(packetver);
+				quote! { ((#len) * (#elem_size)) }
+			},
+			FieldKind::Vec(_) => quote! { 0usize },
+			FieldKind::Fragment(ty) => match packetver {
+				Some(packetver) => quote! { <#ty as ::packet::PacketFragment>::base_len_for(#packetver) },
+				None => quote! { <#ty as ::packet::PacketFragment>::BASE_LEN },
+			},
+			FieldKind::String { len, .. } => quote! { (#len) },
+		}
+	}
+
+	/// Constant expression telling whether the encoded size depends on the value.
+	pub fn is_variable(&self) -> TokenStream {
+		match self {
+			FieldKind::Primitive(_) | FieldKind::String { .. } => quote! { false },
+			FieldKind::Array { elem, .. } => elem.is_variable(),
+			FieldKind::Vec(_) => quote! { true },
+			FieldKind::Fragment(ty) => quote! { <#ty as ::packet::PacketFragment>::VARIABLE },
+		}
+	}
+
+	/// Expression with the encoded size of `value`, computed without serializing it.
+	pub fn encoded_len(&self, value: TokenStream) -> TokenStream {
+		match self {
+			FieldKind::Primitive(_) | FieldKind::String { .. } => self.size(None),
+			FieldKind::Array { elem, .. } | FieldKind::Vec(elem) => match elem.as_ref() {
+				FieldKind::Primitive(_) | FieldKind::String { .. } => {
+					let elem_size = elem.size(None);
+					quote! { ((#value).len() * #elem_size) }
+				},
+				_ => {
+					let item_len = elem.encoded_len(quote! { *item });
+					quote! { (#value).iter().map(|item| #item_len).sum::<usize>() }
+				},
+			},
+			FieldKind::Fragment(_) => quote! { ::packet::PacketFragment::len_for(&#value, packetver) },
+		}
+	}
+
+	/// Statements appending `value` to `buf`.
+	pub fn write(&self, value: TokenStream, label: &str) -> TokenStream {
+		match self {
+			FieldKind::Primitive(ident) if ident == "u8" || ident == "i8" => {
+				let method_name = format_ident!("write_{}", ident);
+				quote! { buf.#method_name(#value).map_err(|_| ::packet::PacketError::FieldEncode(#label))?; }
+			},
+			FieldKind::Primitive(ident) => {
+				let method_name = format_ident!("write_{}", ident);
+				quote! { buf.#method_name::<LittleEndian>(#value).map_err(|_| ::packet::PacketError::FieldEncode(#label))?; }
+			},
+			FieldKind::Array { elem, .. } if elem.is_byte() => {
+				quote! { buf.write_all(&#value).map_err(|_| ::packet::PacketError::FieldEncode(#label))?; }
+			},
+			FieldKind::Array { elem, .. } | FieldKind::Vec(elem) => {
+				let write_item = elem.write(quote! { *item }, label);
+				quote! {
+					for item in (#value).iter() {
+						#write_item
+					}
+				}
+			},
+			FieldKind::Fragment(_) => {
+				quote! { buf.append(&mut ::packet::PacketFragment::serialize_for(&#value, packetver)?); }
+			},
+			FieldKind::String { len, encoding } => quote! {
+				buf.append(
+					&mut ::packet::encode_fixed_string(&#value, #len, ::packet::StringEncoding::#encoding)
+						.ok_or(::packet::PacketError::FieldEncode(#label))?
+				);
+			},
+		}
+	}
+
+	/// Expression reading a value from `cursor`. `Vec`s are read by `PacketField`.
+	pub fn read(&self, label: &str) -> TokenStream {
+		match self {
+			FieldKind::Primitive(ident) if ident == "u8" || ident == "i8" => {
+				let method_name = format_ident!("read_{}", ident);
+				quote! { cursor.#method_name().map_err(|_| ::packet::PacketError::FieldDecode(#label))? }
+			},
+			FieldKind::Primitive(ident) => {
+				let method_name = format_ident!("read_{}", ident);
+				quote! { cursor.#method_name::<LittleEndian>().map_err(|_| ::packet::PacketError::FieldDecode(#label))? }
+			},
+			FieldKind::Array { elem, len } if elem.is_byte() => quote! {
+				{
+					let mut array = [0u8; #len];
+					cursor.read_exact(&mut array).map_err(|_| ::packet::PacketError::FieldDecode(#label))?;
+					array
+				}
+			},
+			FieldKind::Array { elem, len } => {
+				let read_item = elem.read(label);
+				quote! {
+					{
+						let mut items = Vec::with_capacity(#len);
+						for _ in 0..(#len) {
+							items.push(#read_item);
+						}
+						items.try_into().map_err(|_| ::packet::PacketError::FieldDecode(#label))?
+					}
+				}
+			},
+			FieldKind::Vec(_) => unreachable!("Vec fields are read by PacketField, parse_all rejects nested ones"),
+			FieldKind::Fragment(ty) => quote! { <#ty as ::packet::PacketFragment>::deserialize_for(cursor, packetver)? },
+			FieldKind::String { len, encoding } => quote! {
+				{
+					let mut bytes = [0u8; #len];
+					cursor.read_exact(&mut bytes).map_err(|_| ::packet::PacketError::FieldDecode(#label))?;
+					::packet::decode_fixed_string(&bytes, ::packet::StringEncoding::#encoding)
+						.ok_or(::packet::PacketError::FieldDecode(#label))?
+				}
+			},
+		}
+	}
+
+	/// Whether items of this kind can only be measured by walking them.
+	pub fn has_fragment(&self) -> bool {
+		match self {
+			FieldKind::Primitive(_) | FieldKind::String { .. } => false,
+			FieldKind::Array { elem, .. } | FieldKind::Vec(elem) => elem.has_fragment(),
+			FieldKind::Fragment(_) => true,
+		}
+	}
+
+	/// Expression reading a primitive at `offset` of `buf` without a cursor.
+	/// The caller must have checked the bounds.
+	pub fn read_at(&self, buf: TokenStream, offset: TokenStream) -> TokenStream {
+		match self {
+			FieldKind::Primitive(ident) if ident == "u8" => quote! { #buf[#offset] },
+			FieldKind::Primitive(ident) if ident == "i8" => quote! { (#buf[#offset] as i8) },
+			FieldKind::Primitive(ident) => {
+				let method_name = format_ident!("read_{}", ident);
+				quote! { <byteorder::LittleEndian as byteorder::ByteOrder>::#method_name(&#buf[#offset..]) }
+			},
+			_ => unreachable!("only primitives can be read in place, parse_all rejects other lengths and counts"),
+		}
+	}
+
+	/// Statements moving `cursor` past `count` bytes, failing if there aren't enough.
+	pub fn skip_bytes(count: TokenStream) -> TokenStream {
+		quote! {
+			{
+				let end = (cursor.position() as usize).saturating_add(#count);
+				if end > cursor.get_ref().len() {
+					return Err(::packet::PacketError::Truncated { expected: end, actual: cursor.get_ref().len() });
+				}
+				cursor.set_position(end as u64);
+			}
+		}
+	}
+
+	/// Statements moving `cursor` past one value. `Vec`s are skipped by `PacketField`.
+	pub fn skip(&self) -> TokenStream {
+		match self {
+			FieldKind::Array { elem, len } if elem.has_fragment() => {
+				let skip_item = elem.skip();
+				quote! {
+					for _ in 0..(#len) {
+						#skip_item
+					}
+				}
+			},
+			FieldKind::Primitive(_) | FieldKind::String { .. } | FieldKind::Array { .. } => Self::skip_bytes(self.size(None)),
+			FieldKind::Vec(_) => unreachable!("Vec fields are skipped by PacketField, parse_all rejects nested ones"),
+			FieldKind::Fragment(ty) => quote! { <#ty as ::packet::PacketFragment>::skip_for(cursor, packetver)?; },
+		}
+	}
+}
+
+pub struct PacketField {
+	pub ident: Ident,
+	pub label: String,
+	pub kind: FieldKind,
+	/// Integer type written right before the items of a `Vec`.
+	pub len_prefix: Option<Ident>,
+	/// `Vec` field whose item count this field holds.
+	pub len_of: Option<Ident>,
+	/// Field holding the item count of this `Vec`, resolved from `len_of`.
+	pub count_field: Option<Ident>,
+	pub skip: bool,
+	pub since: Option<LitInt>,
+	pub ty: Type,
+}
+
+impl PacketField {
+	pub fn parse_all<'a>(fields: impl IntoIterator<Item = &'a Field>, open_ended: bool) -> syn::Result<Vec<Self>> {
+		let fields: Vec<&Field> = fields.into_iter().collect();
+		let mut parsed = fields.iter().map(|field| Self::parse(field)).collect::<syn::Result<Vec<_>>>()?;
+
+		// The code generation relies on these shapes never reaching it.
+		for (field, parsed) in fields.iter().zip(&parsed) {
+			if parsed.kind.has_nested_vec() {
+				return Err(syn::Error::new_spanned(field, "Vec fields can't be nested in arrays or other Vecs"));
+			}
+			if parsed.len_of.is_some() && parsed.skip {
+				return Err(syn::Error::new_spanned(field, "len_of fields can't be skipped"));
+			}
+		}
+
+		for index in 0..parsed.len() {
+			let Some(target) = parsed[index].len_of.clone() else {
+				continue;
+			};
+
+			let position = parsed.iter().position(|field| field.ident == target);
+			match position.map(|position| (position, &parsed[position].kind)) {
+				Some((position, FieldKind::Vec(_))) if position > index => {
+					parsed[position].count_field = Some(parsed[index].ident.clone());
+				},
+				_ => return Err(syn::Error::new_spanned(fields[index], "len_of must name a Vec field declared after this one")),
+			}
+		}
+
+		let last_wire_field = parsed.iter().rposition(|field| !field.skip);
+		for (index, field) in parsed.iter().enumerate() {
+			if let FieldKind::Vec(_) = field.kind {
+				if field.skip {
+					continue;
+				}
+				if Some(index) != last_wire_field {
+					return Err(syn::Error::new_spanned(fields[index], "Vec fields must be the last field"));
+				}
+				if field.len_prefix.is_some() && field.count_field.is_some() {
+					return Err(syn::Error::new_spanned(fields[index], "a Vec can't have both len_prefix and a len_of field"));
+				}
+				if !open_ended && field.len_prefix.is_none() && field.count_field.is_none() {
+					return Err(syn::Error::new_spanned(fields[index], "Vec fields of fragments need a len_prefix or a len_of field"));
+				}
+			} else if field.len_prefix.is_some() {
+				return Err(syn::Error::new_spanned(fields[index], "len_prefix is only supported on Vec fields"));
+			}
+		}
+
+		Ok(parsed)
+	}
+
+	fn parse(field: &Field) -> syn::Result<Self> {
+		let ident = field.ident.clone().ok_or_else(|| syn::Error::new_spanned(field, "packet fields must be named"))?;
+		let mut len_prefix = None;
+		let mut len_of = None;
+		let mut skip = false;
+		let mut since = None;
+		let mut string = None;
+
+		for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("packet")) {
+			attr.parse_nested_meta(|meta| {
+				if meta.path.is_ident("len_prefix") {
+					let v: LitStr = meta.value()?.parse()?;
+					if !INTEGERS.contains(&v.value().as_str()) {
+						return Err(meta.error("len_prefix must be an integer type"));
+					}
+					len_prefix = Some(Ident::new(&v.value(), v.span()));
+					return Ok(());
+				}
+
+				if meta.path.is_ident("len_of") {
+					let v: LitStr = meta.value()?.parse()?;
+					len_of = Some(Ident::new(&v.value(), v.span()));
+					return Ok(());
+				}
+
+				if meta.path.is_ident("skip") {
+					skip = true;
+					return Ok(());
+				}
+
+				if meta.path.is_ident("since") {
+					since = Some(meta.value()?.parse::<LitInt>()?);
+					return Ok(());
+				}
+
+				if meta.path.is_ident("string") {
+					let mut len = None;
+					let mut encoding = format_ident!("Cp949");
+
+					meta.parse_nested_meta(|inner| {
+						if inner.path.is_ident("len") {
+							len = Some(inner.value()?.parse::<syn::Expr>()?);
+							return Ok(());
+						}
+
+						if inner.path.is_ident("encoding") {
+							let v: LitStr = inner.value()?.parse()?;
+							encoding = match v.value().to_ascii_lowercase().as_str() {
+								"cp949" | "euc-kr" => format_ident!("Cp949"),
+								"utf-8" | "utf8" => format_ident!("Utf8"),
+								"latin1" | "windows-1252" => format_ident!("Latin1"),
+								_ => return Err(inner.error("unsupported string encoding")),
+							};
+							return Ok(());
+						}
+
+						Err(inner.error("unsupported string attribute"))
+					})?;
+
+					let len = len.ok_or_else(|| meta.error("string fields need a len"))?;
+					string = Some(FieldKind::String { len, encoding });
+					return Ok(());
+				}
+
+				Err(meta.error("unsupported packet field attribute"))
+			})?;
+		}
+
+		let is_string = matches!(&field.ty, Type::Path(syn::TypePath { path, .. }) if path.segments.last().is_some_and(|s| s.ident == "String"));
+		let kind = match string {
+			Some(kind) if is_string => kind,
+			Some(_) => return Err(syn::Error::new_spanned(field, "string attributes are only supported on String fields")),
+			None if skip => FieldKind::Primitive(format_ident!("u8")),
+			None => FieldKind::from_type(&field.ty)?,
+		};
+
+		if len_of.is_some() && !kind.is_integer() {
+			return Err(syn::Error::new_spanned(field, "len_of fields must be integers"));
+		}
+
+		Ok(Self {
+			label: ident.to_string(),
+			ident,
+			kind,
+			len_prefix,
+			len_of,
+			count_field: None,
+			skip,
+			since,
+			ty: field.ty.clone(),
+		})
+	}
+
+	/// `packet_len` fields are filled with the encoded size of the packet.
+	pub fn is_packet_len(&self) -> bool {
+		self.ident == "packet_len" && self.kind.is_integer() && !self.skip
+	}
+
+	fn gated(&self, tokens: TokenStream) -> TokenStream {
+		match &self.since {
+			Some(since) => quote! {
+				if packetver >= #since {
+					#tokens
+				}
+			},
+			None => tokens,
+		}
+	}
+
+	/// Size of the fixed part of the field for the client version in
+	/// `packetver`, or a constant for the latest layout when it is `None`.
+	pub fn fixed_size(&self, packetver: Option<&TokenStream>) -> TokenStream {
+		if self.skip {
+			return quote! { 0usize };
+		}
+
+		let size = match &self.len_prefix {
+			Some(prefix) => quote! { ::std::mem::size_of::<#prefix>() },
+			None => self.kind.size(packetver),
+		};
+
+		match (&self.since, packetver) {
+			(Some(since), Some(packetver)) => quote! { (if #packetver >= #since { #size } else { 0usize }) },
+			_ => size,
+		}
+	}
+
+	/// Encoded size of the field's current value for the client version in `packetver`.
+	pub fn encoded_len(&self) -> TokenStream {
+		let ident = &self.ident;
+
+		if self.skip {
+			return quote! { 0usize };
+		}
+
+		let len = match &self.len_prefix {
+			Some(prefix) => {
+				let items_len = self.kind.encoded_len(quote! { self.#ident });
+				quote! { (::std::mem::size_of::<#prefix>() + #items_len) }
+			},
+			None => self.kind.encoded_len(quote! { self.#ident }),
+		};
+
+		match &self.since {
+			Some(since) => quote! { (if packetver >= #since { #len } else { 0usize }) },
+			None => len,
+		}
+	}
+
+	pub fn is_variable(&self) -> TokenStream {
+		if self.skip {
+			quote! { false }
+		} else {
+			self.kind.is_variable()
+		}
+	}
+
+	pub fn serialize(&self) -> TokenStream {
+		let ident = &self.ident;
+		let label = self.label.as_str();
+
+		if self.skip {
+			return quote! {};
+		}
+
+		let tokens = match (&self.kind, &self.len_prefix, &self.len_of) {
+			(FieldKind::Vec(_), Some(prefix), _) => {
+				let write_len = FieldKind::Primitive(prefix.clone()).write(quote! { count }, label);
+				let write_items = self.kind.write(quote! { self.#ident }, label);
+				quote! {
+					let count = #prefix::try_from(self.#ident.len()).map_err(|_| ::packet::PacketError::FieldEncode(#label))?;
+					#write_len
+					#write_items
+				}
+			},
+			(FieldKind::Primitive(ty), _, Some(target)) => {
+				let write_len = self.kind.write(quote! { count }, label);
+				quote! {
+					let count = #ty::try_from(self.#target.len()).map_err(|_| ::packet::PacketError::FieldEncode(#label))?;
+					#write_len
+				}
+			},
+			(FieldKind::Primitive(ty), _, None) if self.is_packet_len() => {
+				let write_len = self.kind.write(quote! { packet_len }, label);
+				quote! {
+					let packet_len = #ty::try_from(self.len_for(packetver)).map_err(|_| ::packet::PacketError::FieldEncode(#label))?;
+					#write_len
+				}
+			},
+			(kind, _, _) => kind.write(quote! { self.#ident }, label),
+		};
+
+		self.gated(tokens)
+	}
+
+	/// Variable holding the item count read from a `len_of` field while skipping.
+	pub fn count_var(&self) -> Ident {
+		format_ident!("count_{}", self.ident)
+	}
+
+	fn skip_items(elem: &FieldKind) -> TokenStream {
+		if elem.has_fragment() {
+			let skip_item = elem.skip();
+			quote! {
+				for _ in 0..count {
+					#skip_item
+				}
+			}
+		} else {
+			let elem_size = elem.size(None);
+			FieldKind::skip_bytes(quote! { count.saturating_mul(#elem_size) })
+		}
+	}
+
+	/// Statements moving `cursor` past the field without decoding it. Open-ended
+	/// `Vec`s take the rest of the buffer.
+	pub fn skip_over(&self) -> TokenStream {
+		if self.skip {
+			return quote! {};
+		}
+
+		match (&self.kind, &self.len_prefix, &self.count_field) {
+			(FieldKind::Vec(elem), Some(prefix), _) => {
+				let prefix_kind = FieldKind::Primitive(prefix.clone());
+				let skip_len = prefix_kind.skip();
+				let read_len = prefix_kind.read_at(quote! { cursor.get_ref() }, quote! { at });
+				let skip_items = Self::skip_items(elem);
+				self.gated(quote! {
+					let at = cursor.position() as usize;
+					#skip_len
+					let count = (#read_len) as usize;
+					#skip_items
+				})
+			},
+			(FieldKind::Vec(elem), None, Some(count_field)) => {
+				let count_var = format_ident!("count_{}", count_field);
+				let skip_items = Self::skip_items(elem);
+				self.gated(quote! {
+					let count = #count_var;
+					#skip_items
+				})
+			},
+			(FieldKind::Vec(_), None, None) => self.gated(quote! { cursor.set_position(cursor.get_ref().len() as u64); }),
+			(kind, _, _) if self.len_of.is_some() => {
+				let count_var = self.count_var();
+				let skip = kind.skip();
+				let read = kind.read_at(quote! { cursor.get_ref() }, quote! { at });
+				match &self.since {
+					Some(since) => quote! {
+						let mut #count_var = 0usize;
+						if packetver >= #since {
+							let at = cursor.position() as usize;
+							#skip
+							#count_var = (#read) as usize;
+						}
+					},
+					None => quote! {
+						let at = cursor.position() as usize;
+						#skip
+						let #count_var = (#read) as usize;
+					},
+				}
+			},
+			(kind, _, _) => self.gated(kind.skip()),
+		}
+	}
+
+	/// Statements filling `target.<field>`. Open-ended `Vec`s read until `length`.
+	pub fn deserialize(&self, target: &Ident) -> TokenStream {
+		let ident = &self.ident;
+		let label = self.label.as_str();
+
+		if self.skip {
+			return quote! {};
+		}
+
+		let tokens = match (&self.kind, &self.len_prefix, &self.count_field) {
+			(FieldKind::Vec(elem), Some(prefix), _) => {
+				let read_len = FieldKind::Primitive(prefix.clone()).read(label);
+				let read_item = elem.read(label);
+				quote! {
+					let count = (#read_len) as usize;
+					for _ in 0..count {
+						#target.#ident.push(#read_item);
+					}
+				}
+			},
+			(FieldKind::Vec(elem), None, Some(count_field)) => {
+				let read_item = elem.read(label);
+				quote! {
+					for _ in 0..(#target.#count_field as usize) {
+						#target.#ident.push(#read_item);
+					}
+				}
+			},
+			(FieldKind::Vec(elem), None, None) => {
+				let read_item = elem.read(label);
+				quote! {
+					while (cursor.position() as usize) < length {
+						#target.#ident.push(#read_item);
+					}
+				}
+			},
+			(kind, _, _) => {
+				let read = kind.read(label);
+				quote! { #target.#ident = #read; }
+			},
+		};
+
+		self.gated(tokens)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use syn::{parse_quote, ItemStruct};
+
+	fn parse(item: ItemStruct) -> syn::Result<Vec<PacketField>> {
+		PacketField::parse_all(&item.fields, true)
+	}
+
+	#[test]
+	fn nested_vecs_are_rejected() {
+		let err = parse(parse_quote! { struct P { packet_id: u16, items: [Vec<u8>; 2] } }).err().unwrap();
+		assert_eq!(err.to_string(), "Vec fields can't be nested in arrays or other Vecs");
+
+		assert!(parse(parse_quote! { struct P { packet_id: u16, items: Vec<[Vec<u8>; 2]> } }).is_err());
+		assert!(parse(parse_quote! { struct P { packet_id: u16, items: Vec<Vec<u8>> } }).is_err());
+	}
+
+	#[test]
+	fn counts_are_read_in_place() {
+		let err = parse(parse_quote! {
+			struct P {
+				packet_id: u16,
+				#[packet(skip, len_of = "items")]
+				count: u8,
+				items: Vec<u16>,
+			}
+		}).err().unwrap();
+		assert_eq!(err.to_string(), "len_of fields can't be skipped");
+
+		assert!(parse(parse_quote! {
+			struct P {
+				packet_id: u16,
+				#[packet(len_of = "items")]
+				count: Fragment,
+				items: Vec<u16>,
+			}
+		}).is_err());
+	}
+
+	#[test]
+	fn supported_shapes_are_accepted() {
+		let fields = parse(parse_quote! {
+			struct P {
+				packet_id: u16,
+				#[packet(len_of = "items")]
+				count: u8,
+				grid: [[u8; 2]; 3],
+				items: Vec<[u16; 2]>,
+			}
+		}).unwrap();
+		assert_eq!(fields.len(), 4);
+		assert_eq!(fields[1].len_of.as_ref().unwrap(), "items");
+		assert_eq!(fields[3].count_field.as_ref().unwrap(), "count");
+	}
+}
@@ -1,15 +1,24 @@
 use proc_macro::TokenStream;
 use quote::{quote, format_ident};
-use syn::{parse_macro_input, ItemStruct, Type, PathArguments, GenericArgument, Meta, LitStr, PathSegment};
+use syn::{parse_macro_input, ItemStruct, Meta, LitStr, PathSegment};
 
+mod field;
+mod view;
 
-fn get_packet_id(attrs: &[syn::Attribute]) -> syn::Path {
+use field::{FieldKind, PacketField};
+
+
+struct PacketAttrs {
+	id: syn::Path,
+	name: String,
+}
+
+fn get_packet_attrs(attrs: &[syn::Attribute]) -> PacketAttrs {
 	let mut packet_id = syn::Path {
 			leading_colon: None,
 			segments: Default::default(),
 	};
-
-	// let mut packet_id = 0_u16;
+	let mut name = None;
 
 	for attr in attrs {
 		if !attr.path().is_ident("packet") {
@@ -23,16 +32,17 @@ fn get_packet_id(attrs: &[syn::Attribute]) -> syn::Path {
 		}
 
 		let _ = attr.parse_nested_meta(|meta| {
+			if meta.path.is_ident("name") {
+				let v: LitStr = meta.value()?.parse()?;
+				name = Some(v.value());
+				return Ok(());
+			}
+
 			if !meta.path.is_ident("id") {
 				return Ok(());
 			}
 			
 			let v = meta.value()?;
-			// let v: LitInt = v.parse()?;
-
-			// let v = v.base10_parse::<u16>()?;
-
-			// packet_id = v;
 			let v: LitStr = v.parse().unwrap();
 			
 			let segment = PathSegment {
@@ -41,7 +51,6 @@ fn get_packet_id(attrs: &[syn::Attribute]) -> syn::Path {
 			};
 
 			let ident = syn::Ident::new(&v.value(), v.span());
-			println!("Ident: {}", ident);
 			packet_id.segments.push(segment);
 			packet_id.segments.push(PathSegment {
 				ident,
@@ -53,208 +62,101 @@ fn get_packet_id(attrs: &[syn::Attribute]) -> syn::Path {
 
 	}
 
-	packet_id
+	// Packet names follow the packet tables, e.g. `CaSsoLoginReq` becomes `CA_SSO_LOGIN_REQ`.
+	let name = name.unwrap_or_else(|| {
+		let variant = packet_id.segments.last().map(|s| s.ident.to_string()).unwrap_or_default();
+		let mut name = String::new();
+		let mut prev: Option<char> = None;
+
+		for c in variant.chars() {
+			if c.is_ascii_uppercase() && prev.is_some_and(|p| p.is_ascii_lowercase() || p.is_ascii_digit()) {
+				name.push('_');
+			}
+			name.push(c.to_ascii_uppercase());
+			prev = Some(c);
+		}
+		name
+	});
+
+	PacketAttrs {
+		id: packet_id,
+		name,
+	}
 }
 
 #[proc_macro_derive(Packet, attributes(packet))]
 pub fn packet_derive(input: TokenStream) -> TokenStream {
-    let input: ItemStruct = parse_macro_input!(input as ItemStruct);
-
-    let struct_name = &input.ident;
-		let packet_id = get_packet_id(&input.attrs);
-
-    let serialization_fields = input.fields.iter().map(|field| {
-        let field_name = &field.ident.clone().unwrap();
-
-        match field.ty {
-            Type::Array(syn::TypeArray { ref len, ref elem, .. }) => {
-				match elem.as_ref() {
-					Type::Path(syn::TypePath {ref path, .. }) => {
-						let type_name = &*path.segments.last().unwrap().ident.to_string();
-						let method_name = format_ident!("write_{}", type_name);
-
-						match type_name {
-							"u8" => quote! { buf.write_all(&self.#field_name).ok()?; },
-							"i8" => quote! {
-								let mut i = 0usize;
-								while i < #len {
-									buf.write_i8(self.#field_name[i]).ok()?;
-									i += 1;
-								}
-							},
-							_ => quote! {
-								let mut i = 0usize;
-								while i < #len {
-									buf.#method_name::<LittleEndian>()(self.#field_name[i]).ok()?;
-									i += 1;
-								}
-							}
-						}
-					},
-					_ => quote! {}
-				}
+	let input: ItemStruct = parse_macro_input!(input as ItemStruct);
+
+	let struct_name = &input.ident;
+	let PacketAttrs { id: packet_id, name: packet_name } = get_packet_attrs(&input.attrs);
+
+	let fields = match PacketField::parse_all(&input.fields, true) {
+		Ok(fields) => fields,
+		Err(err) => return err.to_compile_error().into(),
+	};
+	let target = format_ident!("packet");
+
+	let serialization_fields = fields.iter().map(PacketField::serialize);
+	let deserialization_fields = fields.iter().map(|field| field.deserialize(&target));
+
+	let packetver = quote! { packetver };
+	let latest_sizes = fields.iter().map(|field| field.fixed_size(None));
+	let latest_fixed_len = quote! { (0usize #(+ #latest_sizes)*) };
+	let fixed_sizes = fields.iter().map(|field| field.fixed_size(Some(&packetver)));
+	let fixed_len = quote! { (0usize #(+ #fixed_sizes)*) };
+	let variable_flags = fields.iter().map(PacketField::is_variable);
+	let is_variable = quote! { (false #(|| #variable_flags)*) };
+
+	let encoded_lens = fields.iter().map(PacketField::encoded_len);
+
+	// Size of each item of the trailing Vec, if the packet has one.
+	let (item_len, item_is_variable) = match fields.iter().rev().find(|field| !field.skip).map(|field| &field.kind) {
+		Some(FieldKind::Vec(elem)) => (elem.size(Some(&packetver)), elem.is_variable()),
+		_ => (quote! { 0usize }, quote! { false }),
+	};
+
+	let valid_length = quote! {
+		{
+			let variable_len = #item_len;
+
+			match length.checked_sub(#fixed_len) {
+				None => false,
+				Some(0) => true,
+				// Items with their own Vec can't be checked without decoding them.
+				Some(_) if #item_is_variable => true,
+				Some(rest) => variable_len > 0 && rest % variable_len == 0,
 			}
-			Type::Path(syn::TypePath { ref path, ..}) => match path.segments.last().unwrap().ident.to_string().as_str() {
-				"u8" => quote! { buf.write_all(&[self.#field_name]).ok()?; },
-				"Vec" => match &path.segments.first().unwrap().arguments {
-					PathArguments::AngleBracketed(gargs) => {
-						match gargs.args.first().unwrap() {
-							GenericArgument::Type(ty) =>  match ty {
-								Type::Path(syn::TypePath { ref path, ..}) => match path.segments.last().unwrap().ident.to_string() {
-									x if x.starts_with("u") || x.starts_with("i") => {
-										let serialize_method_name = format_ident!("write_{}", x);
-										if x == "u8" || x == "i8" {
-											quote! {
-												for item in self.#field_name.iter() {
-													buf.#serialize_method_name(*item).ok()?
-												}
-											}
-										} else {
-											quote! {
-												for item in self.#field_name.iter() {
-													buf.#serialize_method_name::<LittleEndian>(*item).ok()?
-												}
-											}
-										}
-									},
-									_ => quote! {
-										for fragment in self.#field_name.iter() {
-											if let Some(mut serialized) = fragment.serialize() {
-												buf.append(&mut serialized);
-											}
-										}
-									}
-								},
-								_ => quote! {},
-							},
-							_ => quote! {}
-						}
-					},
-					_ => quote! {}
-				},
-				_ => quote! { buf.write_all(&self.#field_name.to_le_bytes()).ok()?; }
-			},
-            _ => quote! {}
-        }
-    });
-
-	let deserialization_fields = input.fields.iter().map(|field| {
-        let field_name = &field.ident.clone().unwrap();
-
-        match field.ty {
-            Type::Array(syn::TypeArray { ref len, ref elem, .. }) => {
-				match elem.as_ref() {
-					Type::Path(syn::TypePath {ref path, .. }) => {
-						let type_name = &*path.segments.last().unwrap().ident.to_string();
-						let method_name = format_ident!("read_{}", type_name);
-
-						match type_name {
-							"u8" => quote! {
-								cursor.read_exact(&mut packet.#field_name).ok()?;
-							},
-							"i8" => quote! {
-								let mut limited_cursor = cursor.take(#len as u64);
-								let mut i = 0usize;
-								while i < #len {
-									packet.#field_name[i] = limited_cursor.read_i8().ok()?;
-									i += 1;
-								}
-							},
-							_ => quote! {
-								let mut limited_cursor = cursor.take(#len as u64);
-								let i = 0usize;
-								while i < #len {
-									packet.#field_name[i] = limited_cursor.#method_name::<LittleEndian>().ok()?;
-									i += 1;
-								}
-							}
-						}
-					},
-					_ => quote! {}
-				}
-			}, // for [T; n]
-			Type::Path(syn::TypePath { ref path, ..}) => {
-				let type_name = &*path.segments.last().unwrap().ident.to_string();
-				let method_name = format_ident!("read_{}", type_name);
-				match type_name {
-					"u8" => quote! { packet.#field_name = cursor.read_u8().ok()?; },
-					"Vec" => match &path.segments.first().unwrap().arguments {
-						PathArguments::AngleBracketed(gargs) => {
-							match gargs.args.first().unwrap() {
-								GenericArgument::Type(ty) =>  match ty {
-									Type::Path(syn::TypePath { ref path, ..}) => match path.segments.last().unwrap().ident.to_string() {
-										x if x.starts_with("u") || x.starts_with("i") => {
-											let deserialize_method_name = format_ident!("read_{}", x);
-											if x == "u8" || x == "i8" {
-												quote! {
-													while (cursor.position() as usize) < length {
-														packet.#field_name.push(cursor.#deserialize_method_name().ok()?);
-													}
-												}
-											} else {
-												quote! {
-													while (cursor.position() as usize) < length {
-														packet.#field_name.push(cursor.#deserialize_method_name::<LittleEndian>().ok()?);
-													}
-												}
-											}
-										},
-										_ => quote! {
-											while (cursor.position() as usize) < length {
-												if let Some(item) = <#ty>::deserialize(cursor) {
-													packet.#field_name.push(item);
-												} else {
-													break;
-												}
-											}
-										}
-									},
-									_ => quote! {},
-								},
-								_ => quote! {}
-							}
-						},
-						_ => quote! {}
-					},
-					_ => quote! { packet.#field_name = cursor.#method_name::<LittleEndian>().ok()?; }
-				}
-			},
-            _ => quote! {}
-        }
-    });
-
-	let length_fields = input.fields.iter().map(|field| {
-		let field_type = &field.ty;
-
-        match field.ty {
-            Type::Array(syn::TypeArray { ref len, .. }) => quote! { fixed_len += #len; }, // for [T; n]
-			Type::Path(syn::TypePath { ref path, .. }) => match path.segments.last().unwrap().ident.to_string().as_str() {
-				"Vec" => match &path.segments.first().unwrap().arguments {
-					PathArguments::AngleBracketed(gargs) => {
-						match gargs.args.first().unwrap() {
-							GenericArgument::Type(ty) =>  match ty {
-								Type::Path(syn::TypePath { ref path, ..}) => match path.segments.last().unwrap().ident.to_string() {
-									x if x.starts_with("u") || x.starts_with("i") => {
-										quote! { variable_len += std::mem::size_of::<#ty>(); }
-									},
-									_ => quote! { variable_len += #ty::get_base_len(); }
-								},
-								_ => quote! {},
-							},
-							_ => quote! {}
-						}
-					},
-					_ => quote! {}
-				},
-				_ => quote! { fixed_len += std::mem::size_of::<#field_type>(); }
-			},
-            _ => quote! {}
-        }
-    });
-
-    // Generate the implementation of the Packet trait
-    let expanded = quote! {
+		}
+	};
+
+	// Decoded packets must agree with the frame about their own length.
+	let check_packet_len = fields.iter().find(|field| field.is_packet_len()).map(|field| {
+		let ident = &field.ident;
+		let since = field.since.as_ref().map(|since| quote! { packetver >= #since && });
+		quote! {
+			if #since usize::try_from(packet.#ident).ok() != Some(length) {
+				return Err(::packet::PacketError::LengthMismatch { packet_id: #packet_id as u16, length });
+			}
+		}
+	});
+
+	let view = view::expand(struct_name, &input.vis, &packet_id, &fields, &fixed_len, &valid_length);
+	let view_name = format_ident!("{}Ref", struct_name);
+
+	// Generate the implementation of the Packet trait
+	let expanded = quote! {
 		impl Packet for #struct_name {
+			type Ref<'a> = #view_name<'a>;
+
+			const ID: u16 = #packet_id as u16;
+			const NAME: &'static str = #packet_name;
+			const LENGTH: ::packet::PacketLen = if #is_variable {
+				::packet::PacketLen::Variable
+			} else {
+				::packet::PacketLen::Fixed(#latest_fixed_len as i16)
+			};
+
 			fn new() -> Self {
 				let mut packet = Self::default();
 
@@ -262,17 +164,17 @@ pub fn packet_derive(input: TokenStream) -> TokenStream {
 				packet
 			}
 
-			fn serialize(&self) -> Option<Vec<u8>> {
+			fn serialize_for(&self, packetver: u32) -> Result<Vec<u8>, ::packet::PacketError> {
 				use std::io::Write;
 				use byteorder::{LittleEndian, WriteBytesExt};
 				let mut buf: Vec<u8> = vec![];
 
 				#(#serialization_fields)*
 
-				Some(buf)
+				Ok(buf)
 			}
 
-			fn deserialize(buffer: &[u8]) -> Option<Self>
+			fn deserialize_for(buffer: &[u8], packetver: u32) -> Result<Self, ::packet::PacketError>
 			where
 				Self: Default,
 			{
@@ -282,133 +184,120 @@ pub fn packet_derive(input: TokenStream) -> TokenStream {
 				let mut cursor = &mut Cursor::new(buffer);
 				let mut packet = Self::default();
 				let length = cursor.get_ref().len() as usize;
+				let fixed_len = #fixed_len;
+
+				if length < fixed_len {
+					return Err(::packet::PacketError::Truncated { expected: fixed_len, actual: length });
+				}
 
-				if !packet.has_valid_length(length) {
-					return None;
+				if !#valid_length {
+					return Err(::packet::PacketError::LengthMismatch { packet_id: #packet_id as u16, length });
 				}
 
 				#(#deserialization_fields)*
 
-				Some(packet)
+				#check_packet_len
+
+				Ok(packet)
 			}
 
-			fn len(&self) -> usize {
-				// I don't think this is the best way to get the length
-				if let Some(buf) = self.serialize() {
-					return buf.len();
-				}
-				0usize
+			fn len_for(&self, packetver: u32) -> usize {
+				0usize #(+ #encoded_lens)*
 			}
 
 			fn has_valid_length(&self, length: usize) -> bool {
-				let mut fixed_len = 0usize;
-				let mut variable_len = 0usize;
-				
-				#(#length_fields)*
+				let packetver = ::packet::PACKETVER_LATEST;
+				#valid_length
+			}
+		}
 
-				if length - fixed_len == 0 || (variable_len > 0 && (length - fixed_len) % variable_len == 0) {
-					true
-				} else {
-					false
-				}
+		::packet::inventory::submit! {
+			::packet::DeclaredPacket {
+				module: module_path!(),
+				descriptor: ::packet::PacketDescriptor {
+					id: <#struct_name as ::packet::Packet>::ID,
+					name: <#struct_name as ::packet::Packet>::NAME,
+					len: <#struct_name as ::packet::Packet>::LENGTH,
+				},
 			}
 		}
-    };
 
-    TokenStream::from(expanded)
+		#view
+	};
+
+	TokenStream::from(expanded)
 }
 
+#[proc_macro_derive(PacketFragment, attributes(packet))]
+pub fn packet_fragment_derive(input: TokenStream) -> TokenStream {
+	let input: ItemStruct = parse_macro_input!(input as ItemStruct);
 
+	let struct_name = &input.ident;
 
-#[proc_macro_derive(PacketFragment)]
-pub fn packet_fragment_derive(input: TokenStream) -> TokenStream {
-    let input: ItemStruct = parse_macro_input!(input as ItemStruct);
-
-    let struct_name = &input.ident;
-
-    let serialization_fields = input.fields.iter().map(|field| {
-        let field_name = &field.ident.clone().unwrap();
-
-        match field.ty {
-            Type::Array(_) => { quote! { buf.write_all(&self.#field_name).ok()?; } } // for [T; n]
-			Type::Path(syn::TypePath { ref path, ..}) => match path.segments.last().unwrap().ident.to_string().as_str() {
-				"u8" => quote! { buf.write_all(&[self.#field_name]).ok()?; },
-				"Vec" => quote! { }, // Fragments can't have nested fields or array fields.
-				_ => quote! { buf.write_all(&self.#field_name.to_le_bytes()).ok()?; }
-			},
-            _ => { quote! {} }
-        }
-    });
-
-	let deserialization_fields = input.fields.iter().map(|field| {
-        let field_name = &field.ident.clone().unwrap();
-
-        match field.ty {
-            Type::Array(_) => quote! { cursor.read_exact(&mut fragment.#field_name).ok()?; }, // for [T; n]
-			Type::Path(syn::TypePath { ref path, ..}) => {
-				let type_name = &*path.segments.last().unwrap().ident.to_string();
-				let method_name = format_ident!("read_{}", type_name);
-				match type_name {
-					"u8" => quote! { fragment.#field_name = cursor.read_u8().ok()?; },
-					"Vec" => quote! {},
-					_ => quote! { fragment.#field_name = cursor.#method_name::<LittleEndian>().ok()?; }
-				}
-			},
-            _ => quote! {}
-        }
-    });
-
-	let fixed_length_fields = input.fields.iter().map(|field| {
-		let field_type = &field.ty;
-
-        match field.ty {
-            Type::Array(syn::TypeArray { ref len, .. }) => quote! { base_len += #len; }, // for [T; n]
-			Type::Path(syn::TypePath { ref path, .. }) => match path.segments.last().unwrap().ident.to_string().as_str() {
-				"Vec" => quote! {},
-				_ => quote! { base_len += std::mem::size_of::<#field_type>(); }
-			},
-            _ => quote! {}
-        }
-    });
-
-    // Generate the implementation of the Packet trait
-    let expanded = quote! {
+	let fields = match PacketField::parse_all(&input.fields, false) {
+		Ok(fields) => fields,
+		Err(err) => return err.to_compile_error().into(),
+	};
+	let target = format_ident!("fragment");
+
+	let serialization_fields = fields.iter().map(PacketField::serialize);
+	let deserialization_fields = fields.iter().map(|field| field.deserialize(&target));
+
+	let packetver = quote! { packetver };
+	let latest_sizes = fields.iter().map(|field| field.fixed_size(None));
+	let fixed_sizes = fields.iter().map(|field| field.fixed_size(Some(&packetver)));
+	let variable_flags = fields.iter().map(PacketField::is_variable);
+	let encoded_lens = fields.iter().map(PacketField::encoded_len);
+	let skip_fields = fields.iter().map(PacketField::skip_over);
+
+	// Generate the implementation of the PacketFragment trait
+	let expanded = quote! {
 		impl PacketFragment for #struct_name {
-			fn serialize(&self) -> Option<Vec<u8>> {
+			const BASE_LEN: usize = 0usize #(+ #latest_sizes)*;
+			const VARIABLE: bool = false #(|| #variable_flags)*;
+
+			fn serialize_for(&self, packetver: u32) -> Result<Vec<u8>, ::packet::PacketError> {
 				use std::io::Write;
 				use byteorder::{LittleEndian, WriteBytesExt};
 				let mut buf: Vec<u8> = vec![];
 
 				#(#serialization_fields)*
 
-				Some(buf)
+				Ok(buf)
 			}
 
-			fn get_base_len() -> usize {
-				let mut base_len = 0usize; 
-				
-				#(#fixed_length_fields)*
-				
-				base_len
+			fn base_len_for(packetver: u32) -> usize {
+				0usize #(+ #fixed_sizes)*
+			}
+
+			fn len_for(&self, packetver: u32) -> usize {
+				0usize #(+ #encoded_lens)*
 			}
 
-			fn deserialize(cursor: &mut std::io::Cursor<&[u8]>) -> Option<Self> {
+			fn deserialize_for(cursor: &mut std::io::Cursor<&[u8]>, packetver: u32) -> Result<Self, ::packet::PacketError> {
 				use byteorder::{LittleEndian, ReadBytesExt};
 				use std::io::Read;
 				
 				let mut fragment = Self::default();
-				let length = cursor.get_ref().len();
+				let remaining = cursor.get_ref().len().saturating_sub(cursor.position() as usize);
+				let base_len = Self::base_len_for(packetver);
 
-				if (length - cursor.position() as usize) < Self::get_base_len() {
-					return None;
+				if remaining < base_len {
+					return Err(::packet::PacketError::Truncated { expected: base_len, actual: remaining });
 				}
 
 				#(#deserialization_fields)*
 
-				Some(fragment)
+				Ok(fragment)
+			}
+
+			fn skip_for(cursor: &mut std::io::Cursor<&[u8]>, packetver: u32) -> Result<(), ::packet::PacketError> {
+				#(#skip_fields)*
+
+				Ok(())
 			}
 		}
-    };
+	};
 
-    TokenStream::from(expanded)
+	TokenStream::from(expanded)
 }
@@ -0,0 +1,249 @@
+use proc_macro2::TokenStream;
+use quote::{quote, format_ident};
+use syn::{GenericArgument, Ident, PathArguments, Type, Visibility};
+
+use crate::field::{FieldKind, PacketField};
+
+fn vec_item_type(ty: &Type) -> Option<&Type> {
+	let Type::Path(syn::TypePath { path, .. }) = ty else {
+		return None;
+	};
+
+	match &path.segments.last()?.arguments {
+		PathArguments::AngleBracketed(gargs) => match gargs.args.first() {
+			Some(GenericArgument::Type(elem)) => Some(elem),
+			_ => None,
+		},
+		_ => None,
+	}
+}
+
+/// Accessor reading one field out of the borrowed buffer on demand.
+fn accessor(field: &PacketField, index: usize, fields: &[PacketField]) -> TokenStream {
+	let label = field.label.as_str();
+	let ty = &field.ty;
+	let at = quote! { self.offsets[#index] };
+
+	let (return_type, body) = match (&field.kind, &field.len_prefix, &field.count_field) {
+		(FieldKind::Primitive(_), _, _) => (quote! { #ty }, field.kind.read_at(quote! { self.buffer }, at)),
+		(FieldKind::String { len, encoding }, _, _) => (
+			quote! { Result<::std::borrow::Cow<'a, str>, ::packet::PacketError> },
+			quote! {
+				let buffer: &'a [u8] = self.buffer;
+				::packet::decode_fixed_str(&buffer[#at..(#at + #len)], ::packet::StringEncoding::#encoding)
+					.ok_or(::packet::PacketError::FieldDecode(#label))
+			},
+		),
+		(FieldKind::Array { elem, len }, _, _) if matches!(elem.as_ref(), FieldKind::Primitive(ident) if ident == "u8") => (
+			quote! { &'a [u8] },
+			quote! {
+				let buffer: &'a [u8] = self.buffer;
+				&buffer[#at..(#at + #len)]
+			},
+		),
+		(FieldKind::Fragment(_), _, _) => (
+			quote! { Result<#ty, ::packet::PacketError> },
+			quote! { <#ty as ::packet::PacketFragment>::deserialize_for(&mut std::io::Cursor::new(&self.buffer[#at..]), self.packetver) },
+		),
+		(FieldKind::Array { .. }, _, _) => {
+			let read = field.kind.read(label);
+			(
+				quote! { Result<#ty, ::packet::PacketError> },
+				quote! {
+					#[allow(unused_imports)]
+					use std::io::Read;
+					#[allow(unused_imports)]
+					use byteorder::{LittleEndian, ReadBytesExt};
+
+					let packetver = self.packetver;
+					let cursor = &mut std::io::Cursor::new(&self.buffer[#at..]);
+					Ok(#read)
+				},
+			)
+		},
+		(FieldKind::Vec(elem), _, _) => {
+			let (start, count) = match (&field.len_prefix, &field.count_field) {
+				(Some(prefix), _) => {
+					let read_len = FieldKind::Primitive(prefix.clone()).read_at(quote! { self.buffer }, at.clone());
+					(quote! { #at + ::std::mem::size_of::<#prefix>() }, quote! { Some((#read_len) as usize) })
+				},
+				(None, Some(count_field)) => {
+					let position = fields.iter().position(|field| field.ident == *count_field).unwrap();
+					let count = &fields[position];
+					let read_count = count.kind.read_at(quote! { self.buffer }, quote! { self.offsets[#position] });
+					let count = match &count.since {
+						Some(since) => quote! { if self.packetver >= #since { (#read_count) as usize } else { 0usize } },
+						None => quote! { (#read_count) as usize },
+					};
+					(at.clone(), quote! { Some(#count) })
+				},
+				(None, None) => (at.clone(), quote! { None::<usize> }),
+			};
+
+			if let FieldKind::Primitive(ident) = elem.as_ref() {
+				if ident == "u8" {
+					let body = quote! {
+						let buffer: &'a [u8] = self.buffer;
+						let start = #start;
+						match #count {
+							Some(count) => &buffer[start..(start + count)],
+							None => &buffer[start..],
+						}
+					};
+					return wrap_accessor(field, quote! { &'a [u8] }, body);
+				}
+			}
+
+			let item_type = vec_item_type(ty).unwrap();
+			let read_item = match elem.as_ref() {
+				FieldKind::Fragment(elem_ty) => quote! { <#elem_ty as ::packet::PacketFragment>::deserialize_for(cursor, packetver) },
+				_ => {
+					let read_item = elem.read(label);
+					quote! { (|| -> Result<#item_type, ::packet::PacketError> { Ok(#read_item) })() }
+				},
+			};
+			(
+				quote! { impl Iterator<Item = Result<#item_type, ::packet::PacketError>> + 'a },
+				quote! {
+					#[allow(unused_imports)]
+					use std::io::Read;
+					#[allow(unused_imports)]
+					use byteorder::{LittleEndian, ReadBytesExt};
+
+					let packetver = self.packetver;
+					let count = #count;
+					let mut cursor = std::io::Cursor::new(&self.buffer[#start..]);
+					let mut read = 0usize;
+					let mut failed = false;
+
+					std::iter::from_fn(move || {
+						let done = match count {
+							Some(count) => read >= count,
+							None => cursor.position() as usize >= cursor.get_ref().len(),
+						};
+						if failed || done {
+							return None;
+						}
+
+						read += 1;
+						let cursor = &mut cursor;
+						let item = #read_item;
+						failed = item.is_err();
+						Some(item)
+					})
+				},
+			)
+		},
+	};
+
+	wrap_accessor(field, return_type, body)
+}
+
+fn wrap_accessor(field: &PacketField, return_type: TokenStream, body: TokenStream) -> TokenStream {
+	let ident = &field.ident;
+
+	match &field.since {
+		Some(since) => quote! {
+			pub fn #ident(&self) -> Option<#return_type> {
+				if self.packetver < #since {
+					return None;
+				}
+
+				Some({ #body })
+			}
+		},
+		None => quote! {
+			pub fn #ident(&self) -> #return_type {
+				#body
+			}
+		},
+	}
+}
+
+/// Generates `<Struct>Ref<'a>`, a borrowed view of a packet that finds the
+/// field offsets up front and decodes each field only when it is asked for.
+pub fn expand(
+	struct_name: &Ident,
+	vis: &Visibility,
+	packet_id: &syn::Path,
+	fields: &[PacketField],
+	fixed_len: &TokenStream,
+	valid_length: &TokenStream,
+) -> TokenStream {
+	let view_name = format_ident!("{}Ref", struct_name);
+	let field_count = fields.len();
+	let doc = format!("Borrowed view of a [`{}`] frame.", struct_name);
+
+	let skip_fields = fields.iter().enumerate().map(|(index, field)| {
+		let skip = field.skip_over();
+		quote! {
+			offsets[#index] = cursor.position() as usize;
+			#skip
+		}
+	});
+
+	let accessors = fields
+		.iter()
+		.enumerate()
+		.filter(|(_, field)| !field.skip)
+		.map(|(index, field)| accessor(field, index, fields));
+
+	let check_packet_len = fields.iter().position(|field| field.is_packet_len()).map(|index| {
+		let field = &fields[index];
+		let read = field.kind.read_at(quote! { buffer }, quote! { offsets[#index] });
+		let since = field.since.as_ref().map(|since| quote! { packetver >= #since && });
+		quote! {
+			if #since usize::try_from(#read).ok() != Some(length) {
+				return Err(::packet::PacketError::LengthMismatch { packet_id: #packet_id as u16, length });
+			}
+		}
+	});
+
+	quote! {
+		#[doc = #doc]
+		#[derive(Debug, Clone, Copy)]
+		#vis struct #view_name<'a> {
+			buffer: &'a [u8],
+			packetver: u32,
+			offsets: [usize; #field_count],
+		}
+
+		impl<'a> ::packet::PacketView<'a> for #view_name<'a> {
+			type Owned = #struct_name;
+
+			fn view_for(buffer: &'a [u8], packetver: u32) -> Result<Self, ::packet::PacketError> {
+				let length = buffer.len();
+				let fixed_len = #fixed_len;
+
+				if length < fixed_len {
+					return Err(::packet::PacketError::Truncated { expected: fixed_len, actual: length });
+				}
+
+				if !#valid_length {
+					return Err(::packet::PacketError::LengthMismatch { packet_id: #packet_id as u16, length });
+				}
+
+				let cursor = &mut std::io::Cursor::new(buffer);
+				let mut offsets = [0usize; #field_count];
+
+				#(#skip_fields)*
+
+				#check_packet_len
+
+				Ok(Self { buffer, packetver, offsets })
+			}
+
+			fn as_bytes(&self) -> &'a [u8] {
+				self.buffer
+			}
+
+			fn to_packet(&self) -> Result<#struct_name, ::packet::PacketError> {
+				<#struct_name as ::packet::Packet>::deserialize_for(self.buffer, self.packetver)
+			}
+		}
+
+		impl<'a> #view_name<'a> {
+			#(#accessors)*
+		}
+	}
+}
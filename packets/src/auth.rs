@@ -1,43 +1,160 @@
-use packet::{Packet, PacketFragment};
+use packet::{Packet, PacketFragment, PacketRegistry};
 
 #[derive(Debug)]
 #[repr(u16)]
 pub enum PacketId {
     // Received
     CaLogin = 0x0064,
+    CaLogin2 = 0x01DD,
+    CaLogin3 = 0x01FA,
+    CaLogin4 = 0x027C,
+    CaLoginPcbang = 0x0277,
+    CaLoginHan = 0x02B0,
+    CaLoginOtp = 0x0ACF,
+    CaReqHash = 0x01DB,
     CaSsoLoginReq = 0x0825,
     
     // Transmitted
     AcAcceptLogin = 0x0069,
     AcAcceptLogin2 = 0x0AC4,
     AcRefuseLogin = 0x006A,
+    AcAckHash = 0x01DC,
+    ScNotifyBan = 0x0081,
+    AcRefuseLoginR2 = 0x083E,
+    AcRefuseLoginR3 = 0x0B02,
 }
 
-#[derive(Debug, Default, Packet)]
+pub fn register(registry: &mut PacketRegistry) {
+    registry.register_module(module_path!());
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
 #[packet(id = "CaSsoLoginReq")]
 pub struct PacketCaSsoLoginReq {
 	pub packet_id: u16,
 	pub packet_len: i16,
 	pub version: u32,
 	pub client_type: u8,
-	pub id: [u8; 24],
-	pub password: [u8; 27],
-	pub mac_address: [i8; 17],
-	pub ip: [u8; 15],
+	#[packet(string(len = 24))]
+	pub id: String,
+	#[packet(string(len = 27))]
+	pub password: String,
+	#[packet(string(len = 17))]
+	pub mac_address: String,
+	#[packet(string(len = 15))]
+	pub ip: String,
 	pub t1: Vec<u8>
 }
 
-#[derive(Debug, Default, Packet)]
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
 #[packet(id = "CaLogin")]
 pub struct PacketCaLogin {
 	pub packet_id: u16,
     pub version: u32,
-    pub username: [u8; 24],
-    pub password: [u8; 24],
+    #[packet(string(len = 24))]
+    pub username: String,
+    #[packet(string(len = 24))]
+    pub password: String,
+    pub client_type: u8,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "CaLogin2")]
+pub struct PacketCaLogin2 {
+    pub packet_id: u16,
+    pub version: u32,
+    #[packet(string(len = 24))]
+    pub username: String,
+    pub password_md5: [u8; 16],
+    pub client_type: u8,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "CaLogin3")]
+pub struct PacketCaLogin3 {
+    pub packet_id: u16,
+    pub version: u32,
+    #[packet(string(len = 24))]
+    pub username: String,
+    pub password_md5: [u8; 16],
+    pub client_type: u8,
+    pub client_info: u8,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "CaLogin4")]
+pub struct PacketCaLogin4 {
+    pub packet_id: u16,
+    pub version: u32,
+    #[packet(string(len = 24))]
+    pub username: String,
+    pub password_md5: [u8; 16],
+    pub client_type: u8,
+    #[packet(string(len = 13))]
+    pub mac_address: String,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "CaLoginPcbang")]
+pub struct PacketCaLoginPcbang {
+    pub packet_id: u16,
+    pub version: u32,
+    #[packet(string(len = 24))]
+    pub username: String,
+    #[packet(string(len = 24))]
+    pub password: String,
+    pub client_type: u8,
+    #[packet(string(len = 16))]
+    pub ip: String,
+    #[packet(string(len = 13))]
+    pub mac_address: String,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "CaLoginHan")]
+pub struct PacketCaLoginHan {
+    pub packet_id: u16,
+    pub version: u32,
+    #[packet(string(len = 24))]
+    pub username: String,
+    #[packet(string(len = 24))]
+    pub password: String,
     pub client_type: u8,
+    #[packet(string(len = 16))]
+    pub ip: String,
+    #[packet(string(len = 13))]
+    pub mac_address: String,
+    pub is_han_game_user: u8,
 }
 
-#[derive(Debug, Default, Packet)]
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "CaLoginOtp")]
+pub struct PacketCaLoginOtp {
+    pub packet_id: u16,
+    #[packet(string(len = 25))]
+    pub username: String,
+    #[packet(string(len = 32))]
+    pub password: String,
+    #[packet(string(len = 5))]
+    pub flags: String,
+    pub unknown: u32,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "CaReqHash")]
+pub struct PacketCaReqHash {
+    pub packet_id: u16,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "AcAckHash")]
+pub struct PacketAcAckHash {
+    pub packet_id: u16,
+    pub packet_len: i16,
+    pub salt: Vec<u8>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
 #[packet(id = "AcAcceptLogin2")]
 pub struct PacketAcAcceptLogin2 {
     pub packet_id: u16,
@@ -46,22 +163,58 @@ pub struct PacketAcAcceptLogin2 {
     pub aid: u32,
     pub user_level: u32,
     pub last_login_ip: u32,
-    pub last_login_time: [u8; 26],
+    #[packet(string(len = 26))]
+    pub last_login_time: String,
     pub sex: u8,
     pub twitter_auth_token: [u8; 16],
     pub twitter_flag: u8,
     pub char_server_list: Vec<CharServerList>,
 }
 
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "AcRefuseLogin")]
+pub struct PacketAcRefuseLogin {
+    pub packet_id: u16,
+    pub error_code: u8,
+    #[packet(string(len = 20))]
+    pub block_date: String,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "AcRefuseLoginR2")]
+pub struct PacketAcRefuseLoginR2 {
+    pub packet_id: u16,
+    pub error_code: u32,
+    #[packet(string(len = 20))]
+    pub block_date: String,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "AcRefuseLoginR3")]
+pub struct PacketAcRefuseLoginR3 {
+    pub packet_id: u16,
+    pub error_code: u32,
+    #[packet(string(len = 20))]
+    pub block_date: String,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "ScNotifyBan")]
+pub struct PacketScNotifyBan {
+    pub packet_id: u16,
+    pub error_code: u8,
+}
+
 // Helper Structs
-#[derive(Debug, PacketFragment)]
+#[derive(Debug, Clone, PartialEq, PacketFragment)]
 pub struct CharServerList {
     pub ip: u32,
     pub port: i16,
-    pub name: [u8; 20],
+    #[packet(string(len = 20))]
+    pub name: String,
     pub usercount: u16,
-    pub is_new: u16,
     pub server_type: u16,
+    pub is_new: u16,
     pub unknown2: [u8; 128],
 }
 
@@ -70,10 +223,10 @@ impl Default for CharServerList {
         Self {
             ip: 0,
             port: 0,
-            name: [0; 20],
+            name: String::new(),
             usercount: 0,
-            is_new: 0,
             server_type: 0,
+            is_new: 0,
             unknown2: [0; 128],
         }
     }
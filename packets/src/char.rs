@@ -0,0 +1,227 @@
+use packet::{Packet, PacketFragment, PacketRegistry};
+
+#[derive(Debug)]
+#[repr(u16)]
+pub enum PacketId {
+    // Received
+    ChEnter = 0x0065,
+    ChSelectChar = 0x0066,
+    ChMakeChar = 0x0067,
+    ChDeleteChar = 0x0068,
+    ChPing = 0x0187,
+    ChMakeNewChar = 0x0A39,
+
+    // Transmitted
+    HcAcceptEnter = 0x006B,
+    HcRefuseEnter = 0x006C,
+    HcAcceptMakechar = 0x006D,
+    HcRefuseMakechar = 0x006E,
+    HcAcceptDeletechar = 0x006F,
+    HcRefuseDeletechar = 0x0070,
+    HcNotifyZonesvr = 0x0071,
+    HcNotifyZonesvr2 = 0x0AC5,
+}
+
+pub fn register(registry: &mut PacketRegistry) {
+    registry.register_module(module_path!());
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "ChEnter")]
+pub struct PacketChEnter {
+    pub packet_id: u16,
+    pub aid: u32,
+    pub auth_code: u32,
+    pub user_level: u32,
+    pub unknown: u16,
+    pub sex: u8,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "ChSelectChar")]
+pub struct PacketChSelectChar {
+    pub packet_id: u16,
+    pub slot: u8,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "ChMakeChar")]
+pub struct PacketChMakeChar {
+    pub packet_id: u16,
+    #[packet(string(len = 24))]
+    pub name: String,
+    pub str: u8,
+    pub agi: u8,
+    pub vit: u8,
+    pub int: u8,
+    pub dex: u8,
+    pub luk: u8,
+    pub slot: u8,
+    pub hair_color: u16,
+    pub hair_style: u16,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "ChMakeNewChar")]
+pub struct PacketChMakeNewChar {
+    pub packet_id: u16,
+    #[packet(string(len = 24))]
+    pub name: String,
+    pub slot: u8,
+    pub hair_color: u16,
+    pub hair_style: u16,
+    pub job: u16,
+    pub unknown: u16,
+    pub sex: u8,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "ChDeleteChar")]
+pub struct PacketChDeleteChar {
+    pub packet_id: u16,
+    pub gid: u32,
+    #[packet(string(len = 40))]
+    pub key: String,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "ChPing")]
+pub struct PacketChPing {
+    pub packet_id: u16,
+    pub aid: u32,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "HcAcceptEnter")]
+pub struct PacketHcAcceptEnter {
+    pub packet_id: u16,
+    pub packet_len: i16,
+    pub max_slots: u8,
+    pub available_slots: u8,
+    pub premium_slots: u8,
+    pub unknown: [u8; 20],
+    pub characters: Vec<CharacterInfo>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "HcRefuseEnter")]
+pub struct PacketHcRefuseEnter {
+    pub packet_id: u16,
+    pub error_code: u8,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "HcAcceptMakechar")]
+pub struct PacketHcAcceptMakechar {
+    pub packet_id: u16,
+    pub character: CharacterInfo,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "HcRefuseMakechar")]
+pub struct PacketHcRefuseMakechar {
+    pub packet_id: u16,
+    pub error_code: u8,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "HcAcceptDeletechar")]
+pub struct PacketHcAcceptDeletechar {
+    pub packet_id: u16,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "HcRefuseDeletechar")]
+pub struct PacketHcRefuseDeletechar {
+    pub packet_id: u16,
+    pub error_code: u8,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "HcNotifyZonesvr")]
+pub struct PacketHcNotifyZonesvr {
+    pub packet_id: u16,
+    pub gid: u32,
+    #[packet(string(len = 16))]
+    pub map_name: String,
+    pub ip: u32,
+    pub port: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Packet)]
+#[packet(id = "HcNotifyZonesvr2")]
+pub struct PacketHcNotifyZonesvr2 {
+    pub packet_id: u16,
+    pub gid: u32,
+    #[packet(string(len = 16))]
+    pub map_name: String,
+    pub ip: u32,
+    pub port: u16,
+    pub unknown: [u8; 128],
+}
+
+impl Default for PacketHcNotifyZonesvr2 {
+    fn default() -> Self {
+        Self {
+            packet_id: 0,
+            gid: 0,
+            map_name: String::new(),
+            ip: 0,
+            port: 0,
+            unknown: [0; 128],
+        }
+    }
+}
+
+// Helper Structs
+
+/// A character in the select screen, as sent to clients from 2017-08-30 on.
+#[derive(Debug, Default, Clone, PartialEq, PacketFragment)]
+pub struct CharacterInfo {
+    pub gid: u32,
+    pub exp: i64,
+    pub money: i32,
+    pub job_exp: i64,
+    pub job_level: i32,
+    pub body_state: i32,
+    pub health_state: i32,
+    pub effect_state: i32,
+    pub virtue: i32,
+    pub honor: i32,
+    pub status_point: i16,
+    pub hp: i32,
+    pub max_hp: i32,
+    pub sp: i16,
+    pub max_sp: i16,
+    pub speed: i16,
+    pub job: i16,
+    pub head: i16,
+    pub body: i16,
+    pub weapon: i16,
+    pub level: i16,
+    pub skill_point: i16,
+    pub head_bottom: i16,
+    pub shield: i16,
+    pub head_top: i16,
+    pub head_mid: i16,
+    pub hair_color: i16,
+    pub clothes_color: i16,
+    #[packet(string(len = 24))]
+    pub name: String,
+    pub str: u8,
+    pub agi: u8,
+    pub vit: u8,
+    pub int: u8,
+    pub dex: u8,
+    pub luk: u8,
+    pub slot: u16,
+    /// 0 once the character was renamed.
+    pub can_rename: u16,
+    #[packet(string(len = 16))]
+    pub map_name: String,
+    pub delete_date: u32,
+    pub robe: u32,
+    pub slot_change_count: u32,
+    pub rename_count: u32,
+    pub sex: u8,
+}
@@ -0,0 +1,215 @@
+use packet::{Packet, PacketRegistry};
+
+/// Version of the packets below, bumped whenever one of them changes.
+/// Servers only register with servers speaking the same version.
+pub const INTER_VERSION: u16 = 1;
+
+/// Results of `AH_CHARSERVER_CONNECT_ACK` and `HZ_MAPSERVER_CONNECT_ACK`.
+pub const CONNECT_ACCEPTED: u8 = 0;
+pub const CONNECT_VERSION_MISMATCH: u8 = 1;
+pub const CONNECT_REFUSED: u8 = 3;
+
+/// Packets exchanged between the login, char and map servers.
+#[derive(Debug)]
+#[repr(u16)]
+pub enum PacketId {
+    // Char server to login server and back
+    HaCharserverConnect = 0x2710,
+    AhCharserverConnectAck = 0x2711,
+    HaAuthRequest = 0x2712,
+    AhAuthResult = 0x2713,
+    HaUserCount = 0x2714,
+    HaPing = 0x2715,
+    AhPong = 0x2716,
+    AhKickAccount = 0x2717,
+
+    // Map server to char server and back
+    ZhMapserverConnect = 0x2B00,
+    HzMapserverConnectAck = 0x2B01,
+    ZhAuthRequest = 0x2B02,
+    HzAuthResult = 0x2B03,
+    ZhUserCount = 0x2B04,
+    ZhPing = 0x2B05,
+    HzPong = 0x2B06,
+    HzKickAccount = 0x2B07,
+}
+
+/// Every inter-server packet.
+pub fn register(registry: &mut PacketRegistry) {
+    registry.register_module(module_path!());
+}
+
+/// Packets between the login server and char servers, named after the
+/// servers they go between, `A` being the login server and `H` a char server.
+pub fn register_login_link(registry: &mut PacketRegistry) {
+    register_between(registry, ["AH_", "HA_"]);
+}
+
+/// Packets between a char server and map servers, `Z` being a map server.
+pub fn register_map_link(registry: &mut PacketRegistry) {
+    register_between(registry, ["HZ_", "ZH_"]);
+}
+
+fn register_between(registry: &mut PacketRegistry, prefixes: [&str; 2]) {
+    registry.register_declared(|declared| {
+        declared.module == module_path!() && prefixes.iter().any(|prefix| declared.descriptor.name.starts_with(prefix))
+    });
+}
+
+/// Sent by a char server to the login server to be listed to players.
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "HaCharserverConnect")]
+pub struct PacketHaCharserverConnect {
+    pub packet_id: u16,
+    /// `INTER_VERSION` of the char server.
+    pub version: u16,
+    /// Shared by every server of the cluster.
+    #[packet(string(len = 32))]
+    pub secret: String,
+    pub ip: u32,
+    pub port: u16,
+    #[packet(string(len = 20))]
+    pub name: String,
+    pub server_type: u16,
+    pub is_new: u16,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "AhCharserverConnectAck")]
+pub struct PacketAhCharserverConnectAck {
+    pub packet_id: u16,
+    /// One of the `CONNECT_*` results.
+    pub result: u8,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "HaUserCount")]
+pub struct PacketHaUserCount {
+    pub packet_id: u16,
+    pub users: u32,
+}
+
+/// Asks the login server whether a player logging into a char server got
+/// this auth code from it.
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "HaAuthRequest")]
+pub struct PacketHaAuthRequest {
+    pub packet_id: u16,
+    pub aid: u32,
+    pub auth_code: u32,
+    pub user_level: u32,
+    pub sex: u8,
+    pub ip: u32,
+    /// Echoed back in `AH_AUTH_RESULT`.
+    pub request_id: u32,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "AhAuthResult")]
+pub struct PacketAhAuthResult {
+    pub packet_id: u16,
+    pub aid: u32,
+    pub auth_code: u32,
+    pub user_level: u32,
+    pub sex: u8,
+    /// 0 when the auth code is valid.
+    pub result: u8,
+    pub request_id: u32,
+}
+
+/// Keeps the link up, answered with `AH_PONG`.
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "HaPing")]
+pub struct PacketHaPing {
+    pub packet_id: u16,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "AhPong")]
+pub struct PacketAhPong {
+    pub packet_id: u16,
+}
+
+/// Asks a char server to disconnect an account, and its map servers to
+/// do the same.
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "AhKickAccount")]
+pub struct PacketAhKickAccount {
+    pub packet_id: u16,
+    pub aid: u32,
+}
+
+/// Sent by a map server to the char server, which sends players to it.
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "ZhMapserverConnect")]
+pub struct PacketZhMapserverConnect {
+    pub packet_id: u16,
+    /// `INTER_VERSION` of the map server.
+    pub version: u16,
+    #[packet(string(len = 32))]
+    pub secret: String,
+    pub ip: u32,
+    pub port: u16,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "HzMapserverConnectAck")]
+pub struct PacketHzMapserverConnectAck {
+    pub packet_id: u16,
+    /// One of the `CONNECT_*` results.
+    pub result: u8,
+}
+
+/// Asks the char server whether a player entering a map server selected
+/// this character after logging in with this auth code.
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "ZhAuthRequest")]
+pub struct PacketZhAuthRequest {
+    pub packet_id: u16,
+    pub aid: u32,
+    pub gid: u32,
+    pub auth_code: u32,
+    pub sex: u8,
+    pub ip: u32,
+    /// Echoed back in `HZ_AUTH_RESULT`.
+    pub request_id: u32,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "HzAuthResult")]
+pub struct PacketHzAuthResult {
+    pub packet_id: u16,
+    pub aid: u32,
+    pub gid: u32,
+    pub auth_code: u32,
+    /// 0 when the player may enter.
+    pub result: u8,
+    pub request_id: u32,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "ZhUserCount")]
+pub struct PacketZhUserCount {
+    pub packet_id: u16,
+    pub users: u32,
+}
+
+/// Keeps the link up, answered with `HZ_PONG`.
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "ZhPing")]
+pub struct PacketZhPing {
+    pub packet_id: u16,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "HzPong")]
+pub struct PacketHzPong {
+    pub packet_id: u16,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "HzKickAccount")]
+pub struct PacketHzKickAccount {
+    pub packet_id: u16,
+    pub aid: u32,
+}
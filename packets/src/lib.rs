@@ -1 +1,16 @@
+use packet::PacketRegistry;
+
 pub mod auth;
+pub mod char;
+pub mod inter;
+pub mod map;
+
+/// Every packet defined in this crate.
+pub fn registry() -> PacketRegistry {
+    let mut registry = PacketRegistry::new();
+    auth::register(&mut registry);
+    char::register(&mut registry);
+    inter::register(&mut registry);
+    map::register(&mut registry);
+    registry
+}
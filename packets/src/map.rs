@@ -0,0 +1,156 @@
+use packet::{Packet, PacketRegistry};
+
+#[derive(Debug)]
+#[repr(u16)]
+pub enum PacketId {
+    // Received
+    CzNotifyActorinit = 0x007D,
+    CzRequestMove = 0x0085,
+    CzRequestMove2 = 0x035F,
+    CzRequestTime2 = 0x0360,
+    CzEnter2 = 0x0436,
+
+    // Transmitted
+    ZcRefuseEnter = 0x0074,
+    ZcNotifyTime = 0x007F,
+    ZcNotifyMove = 0x0086,
+    ZcNotifyPlayermove = 0x0087,
+    ZcAid = 0x0283,
+    ZcAcceptEnter2 = 0x02EB,
+    ZcAcceptEnter3 = 0x0A18,
+}
+
+pub fn register(registry: &mut PacketRegistry) {
+    registry.register_module(module_path!());
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "CzNotifyActorinit")]
+pub struct PacketCzNotifyActorinit {
+    pub packet_id: u16,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "CzRequestMove")]
+pub struct PacketCzRequestMove {
+    pub packet_id: u16,
+    pub dest: [u8; 3],
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "CzRequestMove2")]
+pub struct PacketCzRequestMove2 {
+    pub packet_id: u16,
+    pub dest: [u8; 3],
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "CzRequestTime2")]
+pub struct PacketCzRequestTime2 {
+    pub packet_id: u16,
+    pub client_time: u32,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "CzEnter2")]
+pub struct PacketCzEnter2 {
+    pub packet_id: u16,
+    pub aid: u32,
+    pub gid: u32,
+    pub auth_code: u32,
+    pub client_time: u32,
+    pub sex: u8,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "ZcRefuseEnter")]
+pub struct PacketZcRefuseEnter {
+    pub packet_id: u16,
+    pub error_code: u8,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "ZcNotifyTime")]
+pub struct PacketZcNotifyTime {
+    pub packet_id: u16,
+    pub time: u32,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "ZcNotifyMove")]
+pub struct PacketZcNotifyMove {
+    pub packet_id: u16,
+    pub gid: u32,
+    pub move_data: [u8; 6],
+    pub move_start_time: u32,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "ZcNotifyPlayermove")]
+pub struct PacketZcNotifyPlayermove {
+    pub packet_id: u16,
+    pub move_start_time: u32,
+    pub move_data: [u8; 6],
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "ZcAid")]
+pub struct PacketZcAid {
+    pub packet_id: u16,
+    pub aid: u32,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "ZcAcceptEnter2")]
+pub struct PacketZcAcceptEnter2 {
+    pub packet_id: u16,
+    pub start_time: u32,
+    pub pos_dir: [u8; 3],
+    pub x_size: u8,
+    pub y_size: u8,
+    pub font: u16,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Packet)]
+#[packet(id = "ZcAcceptEnter3")]
+pub struct PacketZcAcceptEnter3 {
+    pub packet_id: u16,
+    pub start_time: u32,
+    pub pos_dir: [u8; 3],
+    pub x_size: u8,
+    pub y_size: u8,
+    pub font: u16,
+    pub sex: u8,
+}
+
+// Helper Functions
+
+/// Packs a cell and a direction the way positions are sent in 3 bytes.
+pub fn encode_pos_dir(x: u16, y: u16, dir: u8) -> [u8; 3] {
+    [
+        (x >> 2) as u8,
+        ((x << 6) as u8) | ((y >> 4) as u8 & 0x3F),
+        ((y << 4) as u8) | (dir & 0x0F),
+    ]
+}
+
+pub fn decode_pos_dir(pos_dir: [u8; 3]) -> (u16, u16, u8) {
+    let x = ((pos_dir[0] as u16) << 2) | (pos_dir[1] as u16 >> 6);
+    let y = (((pos_dir[1] & 0x3F) as u16) << 4) | (pos_dir[2] as u16 >> 4);
+    (x, y, pos_dir[2] & 0x0F)
+}
+
+/// Packs the start and end cells of a walk, with the sub-cell offsets at
+/// the center of the start cell.
+pub fn encode_move_data(from: (u16, u16), to: (u16, u16)) -> [u8; 6] {
+    let (x0, y0) = from;
+    let (x1, y1) = to;
+    [
+        (x0 >> 2) as u8,
+        ((x0 << 6) as u8) | ((y0 >> 4) as u8 & 0x3F),
+        ((y0 << 4) as u8) | ((x1 >> 6) as u8 & 0x0F),
+        ((x1 << 2) as u8) | ((y1 >> 8) as u8 & 0x03),
+        y1 as u8,
+        0x88,
+    ]
+}
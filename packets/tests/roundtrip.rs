@@ -0,0 +1,222 @@
+use packet::{Packet, PacketError, PacketLen, PacketView};
+use packets::auth::*;
+use packets::map::{decode_pos_dir, encode_pos_dir};
+use proptest::collection::vec;
+use proptest::prelude::*;
+
+fn name(max_len: usize) -> impl Strategy<Value = String> {
+    proptest::string::string_regex(&format!("[a-zA-Z0-9_]{{0,{}}}", max_len)).unwrap()
+}
+
+fn char_server() -> impl Strategy<Value = CharServerList> {
+    (any::<u32>(), any::<i16>(), name(20), any::<u16>(), any::<u16>(), any::<u16>(), vec(any::<u8>(), 128))
+        .prop_map(|(ip, port, name, usercount, is_new, server_type, unknown2)| CharServerList {
+            ip,
+            port,
+            name,
+            usercount,
+            is_new,
+            server_type,
+            unknown2: unknown2.try_into().unwrap(),
+        })
+}
+
+fn ca_login() -> impl Strategy<Value = PacketCaLogin> {
+    (any::<u32>(), name(24), name(24), any::<u8>()).prop_map(|(version, username, password, client_type)| {
+        PacketCaLogin {
+            version,
+            username,
+            password,
+            client_type,
+            ..PacketCaLogin::new()
+        }
+    })
+}
+
+fn ca_sso_login_req() -> impl Strategy<Value = PacketCaSsoLoginReq> {
+    (any::<u32>(), any::<u8>(), name(24), name(27), name(17), name(15), vec(any::<u8>(), 0..64)).prop_map(
+        |(version, client_type, id, password, mac_address, ip, t1)| PacketCaSsoLoginReq {
+            version,
+            client_type,
+            id,
+            password,
+            mac_address,
+            ip,
+            t1,
+            ..PacketCaSsoLoginReq::new()
+        },
+    )
+}
+
+fn ac_accept_login2() -> impl Strategy<Value = PacketAcAcceptLogin2> {
+    (
+        (any::<i32>(), any::<u32>(), any::<u32>(), any::<u32>(), name(26)),
+        (any::<u8>(), any::<[u8; 16]>(), any::<u8>(), vec(char_server(), 0..8)),
+    )
+        .prop_map(
+            |((auth_code, aid, user_level, last_login_ip, last_login_time), (sex, twitter_auth_token, twitter_flag, char_server_list))| {
+                PacketAcAcceptLogin2 {
+                    auth_code,
+                    aid,
+                    user_level,
+                    last_login_ip,
+                    last_login_time,
+                    sex,
+                    twitter_auth_token,
+                    twitter_flag,
+                    char_server_list,
+                    ..PacketAcAcceptLogin2::new()
+                }
+            },
+        )
+}
+
+fn ac_refuse_login_r2() -> impl Strategy<Value = PacketAcRefuseLoginR2> {
+    (any::<u32>(), name(20)).prop_map(|(error_code, block_date)| PacketAcRefuseLoginR2 {
+        error_code,
+        block_date,
+        ..PacketAcRefuseLoginR2::new()
+    })
+}
+
+fn check_roundtrip<P: Packet + Clone + PartialEq>(packet: P, set_packet_len: impl Fn(&mut P, i16)) -> Result<(), TestCaseError> {
+    let buf = packet.serialize().unwrap();
+    prop_assert_eq!(buf.len(), packet.len());
+    prop_assert!(packet.has_valid_length(buf.len()));
+    prop_assert_eq!(u16::from_le_bytes([buf[0], buf[1]]), P::ID);
+
+    if let PacketLen::Fixed(len) = P::LENGTH {
+        prop_assert_eq!(buf.len(), len as usize);
+    } else {
+        prop_assert_eq!(i16::from_le_bytes([buf[2], buf[3]]) as usize, buf.len());
+    }
+
+    let mut expected = packet.clone();
+    set_packet_len(&mut expected, buf.len() as i16);
+    prop_assert_eq!(P::deserialize(&buf).unwrap(), expected);
+    Ok(())
+}
+
+fn check_truncated<P: Packet>(packet: P, cut: usize) -> Result<(), TestCaseError> {
+    let buf = packet.serialize().unwrap();
+    let cut = cut % buf.len();
+
+    prop_assert!(P::deserialize(&buf[..cut]).is_err());
+    Ok(())
+}
+
+proptest! {
+    #[test]
+    fn ca_login_roundtrip(packet in ca_login()) {
+        check_roundtrip(packet, |_, _| {})?;
+    }
+
+    #[test]
+    fn ca_sso_login_req_roundtrip(packet in ca_sso_login_req()) {
+        check_roundtrip(packet, |p, len| p.packet_len = len)?;
+    }
+
+    #[test]
+    fn ac_accept_login2_roundtrip(packet in ac_accept_login2()) {
+        check_roundtrip(packet, |p, len| p.packet_len = len)?;
+    }
+
+    #[test]
+    fn ac_refuse_login_r2_roundtrip(packet in ac_refuse_login_r2()) {
+        check_roundtrip(packet, |_, _| {})?;
+    }
+
+    #[test]
+    fn pos_dir_roundtrip(x in 0..1024_u16, y in 0..1024_u16, dir in 0..16_u8) {
+        prop_assert_eq!(decode_pos_dir(encode_pos_dir(x, y, dir)), (x, y, dir));
+    }
+
+    #[test]
+    fn ca_login_rejects_truncated(packet in ca_login(), cut in any::<usize>()) {
+        check_truncated(packet, cut)?;
+    }
+
+    #[test]
+    fn ac_accept_login2_rejects_truncated(packet in ac_accept_login2(), cut in any::<usize>()) {
+        check_truncated(packet, cut)?;
+    }
+
+    #[test]
+    fn ca_login_view_matches_decoded(packet in ca_login()) {
+        let buf = packet.serialize().unwrap();
+        let view = PacketCaLoginRef::view(&buf).unwrap();
+
+        prop_assert_eq!(view.version(), packet.version);
+        prop_assert_eq!(view.username().unwrap(), packet.username.as_str());
+        prop_assert_eq!(view.password().unwrap(), packet.password.as_str());
+        prop_assert_eq!(view.client_type(), packet.client_type);
+        prop_assert_eq!(view.to_packet().unwrap(), packet);
+    }
+
+    #[test]
+    fn ca_sso_login_req_view_matches_decoded(packet in ca_sso_login_req()) {
+        let buf = packet.serialize().unwrap();
+        let view = PacketCaSsoLoginReqRef::view(&buf).unwrap();
+
+        prop_assert_eq!(view.packet_len() as usize, buf.len());
+        prop_assert_eq!(view.id().unwrap(), packet.id.as_str());
+        prop_assert_eq!(view.t1(), packet.t1.as_slice());
+    }
+
+    #[test]
+    fn ac_accept_login2_view_matches_decoded(packet in ac_accept_login2()) {
+        let buf = packet.serialize().unwrap();
+        let view = PacketAcAcceptLogin2Ref::view(&buf).unwrap();
+
+        prop_assert_eq!(view.aid(), packet.aid);
+        prop_assert_eq!(view.last_login_time().unwrap(), packet.last_login_time.as_str());
+        prop_assert_eq!(view.twitter_auth_token(), packet.twitter_auth_token.as_slice());
+        prop_assert_eq!(view.char_server_list().collect::<Result<Vec<_>, _>>().unwrap(), packet.char_server_list);
+    }
+
+    #[test]
+    fn has_valid_length_never_panics(length in 0usize..4096) {
+        let _ = PacketCaLogin::default().has_valid_length(length);
+        let _ = PacketCaSsoLoginReq::default().has_valid_length(length);
+        let _ = PacketAcAcceptLogin2::default().has_valid_length(length);
+    }
+
+    #[test]
+    fn arbitrary_bytes_never_panic(buf in vec(any::<u8>(), 0..512)) {
+        let _ = PacketCaLogin::deserialize(&buf);
+        let _ = PacketCaSsoLoginReq::deserialize(&buf);
+        let _ = PacketAcAcceptLogin2::deserialize(&buf);
+    }
+
+    #[test]
+    fn arbitrary_bytes_never_panic_views(buf in vec(any::<u8>(), 0..512)) {
+        if let Ok(view) = PacketCaLoginRef::view(&buf) {
+            let _ = view.username();
+        }
+        if let Ok(view) = PacketCaSsoLoginReqRef::view(&buf) {
+            let _ = (view.id(), view.t1());
+        }
+        if let Ok(view) = PacketAcAcceptLogin2Ref::view(&buf) {
+            let _ = view.char_server_list().count();
+        }
+    }
+}
+
+#[test]
+fn short_buffer_is_truncated_error() {
+    assert!(matches!(
+        PacketCaLogin::deserialize(&[0x64, 0x00]),
+        Err(PacketError::Truncated { expected: 55, actual: 2 })
+    ));
+}
+
+#[test]
+fn mismatched_packet_len_is_rejected() {
+    let mut buf = PacketAcAcceptLogin2::new().serialize().unwrap();
+    buf[2] = buf[2].wrapping_add(1);
+
+    assert!(matches!(
+        PacketAcAcceptLogin2::deserialize(&buf),
+        Err(PacketError::LengthMismatch { .. })
+    ));
+}
@@ -0,0 +1,39 @@
+use std::path::Path;
+
+use packet::{PacketRegistry, PacketTable};
+
+/// Checks the packets a server registers against the table it ships with,
+/// the same way the servers do on startup.
+fn check(table: &str, register: &[fn(&mut PacketRegistry)], expected: usize) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("..").join(table);
+    let table = PacketTable::from_path(&path).unwrap();
+
+    let mut registry = PacketRegistry::new();
+    for register in register {
+        register(&mut registry);
+    }
+
+    assert_eq!(registry.descriptors().count(), expected);
+    let mismatches: Vec<String> = registry.check_against(&table, None).iter().map(ToString::to_string).collect();
+    assert!(mismatches.is_empty(), "{}: {:#?}", path.display(), mismatches);
+}
+
+#[test]
+fn auth_table_matches() {
+    check("auth_packets.txt", &[packets::auth::register], 15);
+}
+
+#[test]
+fn char_table_matches() {
+    check("char_packets.txt", &[packets::char::register], 14);
+}
+
+#[test]
+fn map_table_matches() {
+    check("map_packets.txt", &[packets::map::register], 12);
+}
+
+#[test]
+fn every_packet_is_declared() {
+    assert_eq!(packets::registry().descriptors().count(), 15 + 14 + 12 + 16);
+}
@@ -0,0 +1,135 @@
+use std::error::Error;
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::time::Duration;
+
+use characters::{Position, SqliteStore};
+use config::{CharConfig, ConfigSource};
+use network::{InterServerLink, Reactor, ReactorConfig};
+use packet::{Packet, PacketParser, PacketRegistry, PacketTable};
+use packets::inter::{PacketHaCharserverConnect, PacketHaPing, INTER_VERSION};
+use systems::char::{char_system, map_server_system, CharServerConfig};
+use systems::System;
+use tracing::{error, info, warn};
+
+const CONFIG_PATH: &str = "conf/char.toml";
+
+/// How often the config files are checked for changes.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(5);
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (mut source, args) = ConfigSource::from_args(CONFIG_PATH, &args)?;
+    if let Some(arg) = args.first() {
+        return Err(format!("unknown argument '{}'", arg).into());
+    }
+    let config: CharConfig = source.load()?;
+    let log = config.log.init()?;
+    info!(path = %source.path().display(), "Loaded the configuration");
+
+    let store = SqliteStore::open(&config.char.characters_db)?;
+    info!(path = %config.char.characters_db.display(), "Loaded characters");
+
+    let addr = config.network.bind;
+    let tcp_listener = TcpListener::bind(addr)?;
+    let table_path = &config.network.packet_table;
+    let packet_table = PacketTable::from_path(table_path)?;
+    let packetver = config.network.packetver;
+    let packet_parser = Arc::new(PacketParser::from_table(&packet_table, packetver));
+    info!(path = %table_path.display(), packets = packet_parser.len(), ?packetver, "Loaded packet lengths");
+    let mut registry = PacketRegistry::new();
+    packets::char::register(&mut registry);
+    for mismatch in registry.check_against(&packet_table, packetver) {
+        warn!(%mismatch, "Packet table mismatch");
+    }
+
+    let reactor = Reactor::new(packet_parser, config.network.reactor_config())
+        .on_disconnect(systems::char::on_disconnect);
+    let sessions = reactor.registry().clone();
+
+    // Map servers get their own listener, the players' one not knowing
+    // the inter-server packets.
+    let inter_addr = config.char.inter_bind;
+    let inter_listener = TcpListener::bind(inter_addr)?;
+    let mut map_link = PacketRegistry::new();
+    packets::inter::register_map_link(&mut map_link);
+    let inter_reactor = Reactor::new(
+        Arc::new(PacketParser::from_registry(&map_link)),
+        ReactorConfig { workers: 1, ..config.network.reactor_config() },
+    )
+    .on_disconnect(systems::char::on_map_server_disconnect);
+
+    let mut inter = PacketRegistry::new();
+    packets::inter::register_login_link(&mut inter);
+    let connect = PacketHaCharserverConnect {
+        version: INTER_VERSION,
+        secret: config.inter.secret.clone(),
+        ip: u32::from_le_bytes(config.char.public_address.ip().octets()),
+        port: config.char.public_address.port(),
+        name: config.char.name.clone(),
+        server_type: config.char.server_type as u16,
+        is_new: config.char.new.into(),
+        ..PacketHaCharserverConnect::new()
+    }
+    .serialize()?;
+    let link_sessions = sessions.clone();
+    let login = InterServerLink::connect(
+        &config.inter.connect,
+        Arc::new(PacketParser::from_registry(&inter)),
+        config.inter.link_config(PacketHaPing::new().serialize()?),
+        move |link| link.send(&connect),
+        move |packet| systems::char::on_login_packet(&link_sessions, packet),
+    )?;
+
+    let user_count_link = login.clone();
+    let user_count_sessions = sessions.clone();
+    let user_count_interval = config.inter.user_count_interval();
+    std::thread::spawn(move || loop {
+        std::thread::sleep(user_count_interval);
+        if let Err(err) = systems::char::send_user_count(&user_count_link, &user_count_sessions) {
+            warn!(%err, "Couldn't send the user count to the login server");
+        }
+    });
+
+    systems::char::init(CharServerConfig {
+        store: Arc::new(store),
+        login,
+        map_server: config.char.map_server,
+        map_links: inter_reactor.registry().clone(),
+        secret: config.inter.secret.clone(),
+        start: Position {
+            map: config.char.start_map.clone(),
+            x: config.char.start_x,
+            y: config.char.start_y,
+        },
+        delete_key: config.char.delete_key.clone(),
+    });
+    source.watch(config, RELOAD_INTERVAL, move |config: &CharConfig| {
+        if let Err(err) = log.set_level(&config.log.level) {
+            warn!(%err, "Couldn't change the log level");
+        }
+    });
+
+    let systems: Vec<System> = vec![
+        char_system,
+    ];
+
+    info!(%addr, "Listening");
+    info!(addr = %inter_addr, "Listening for map servers");
+    std::thread::spawn(move || {
+        let systems: Vec<System> = vec![
+            map_server_system,
+        ];
+        let result = inter_reactor.run(inter_listener, move |session, packet| {
+            systems::dispatch(&systems, session, packet);
+        });
+        if let Err(err) = result {
+            error!(%err, "The map server listener stopped");
+        }
+    });
+    reactor.run(tcp_listener, move |session, packet| {
+        systems::dispatch(&systems, session, packet);
+    })?;
+
+    Ok(())
+}
@@ -1,58 +1,54 @@
-use network::PlayerSession;
-use packet::{Packet, PacketParser};
+use network::{Connection, PlayerSession, ReadStatus, Watermarks};
+use packet::{Packet, PacketParser, PacketTable};
 use std::io::{self, Write};
 use std::net::TcpStream;
 
-use packets::auth::{PacketAcAcceptLogin2, PacketCaLogin};
+use packets::auth::{PacketAcAcceptLogin2, PacketAcRefuseLoginR3, PacketCaLogin};
 
 fn main() -> io::Result<()> {
     // Connect to localhost on port 6900
     let mut stream = TcpStream::connect("127.0.0.1:6900")?;
-    let parser = PacketParser::new("auth_packets.txt");
+    let table = PacketTable::from_path("auth_packets.txt").map_err(io::Error::other)?;
+    let parser = PacketParser::from_table_transmitted(&table, None);
 
     // Send a message to the server
     let mut pkt = PacketCaLogin::new();
-    let username = b"mpereti";
-    let password = b"8509d0ea";
-    pkt.username[..username.len()].copy_from_slice(username);
-    pkt.password[..password.len()].copy_from_slice(password);
+    pkt.username = "mpereti".to_string();
+    pkt.password = "8509d0ea".to_string();
     pkt.client_type = 22;
     stream.write_all(pkt.serialize().unwrap().as_slice())?;
 
     stream.set_nonblocking(true)?;
 
-    let mut session = PlayerSession::new(stream);
+    let peer_addr = stream.peer_addr()?;
+    let mut connection = Connection::new(stream, PlayerSession::new(peer_addr), Watermarks::default());
 
     loop {
-        use network::UpdateResponse;
-        match session.update_sockets() {
-            UpdateResponse::Ok(data) => {
-                session
-                    .recv_queue
-                    .extend(parser.extract_packets(data.as_slice()));
-                println!("Hellow");
-            }
-            UpdateResponse::NoContent => {}
-            _ => {
-                std::thread::sleep(std::time::Duration::from_micros(500));
-            }
+        let status = connection.receive(&parser);
+        if let Err(err) = &status {
+            println!("Failed to decode response: {}", err);
         }
 
-        println!("Packets {}", session.recv_queue.len());
-
+        let session = &mut connection.session;
         while let Some(packet) = session.recv_queue.pop_front() {
             println!(
                 "Received response with packet {:#06X} with {} bytes",
                 packet.packet_id, packet.length
             );
 
-            match packet.packet_id {
-                0x0AC4 => {
-                    let pkt = packet.parse::<PacketAcAcceptLogin2>();
-                    println!(" Packet: {:?}", pkt);
-                }
-                _ => {}
+            if packet.packet_id == 0x0AC4 {
+                let pkt = packet.parse::<PacketAcAcceptLogin2>();
+                println!(" Packet: {:?}", pkt);
             }
+            if packet.packet_id == 0x0B02 {
+                let pkt = packet.parse::<PacketAcRefuseLoginR3>();
+                println!(" Packet: {:?}", pkt);
+            }
+        }
+
+        if let Ok(ReadStatus::Closed) = status {
+            println!("Server closed the connection");
+            return Ok(());
         }
 
         // Add a delay or implement some logic to determine when to break out of the loop
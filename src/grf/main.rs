@@ -0,0 +1,128 @@
+use griffon::{Grf, GrfError, GrfWriter, ThorPatch};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+const USAGE: &str = "usage: grf [--level <0-9>] <command> <archive> [<path>...]
+commands:
+  list     lists the entries
+  create   creates the archive with the files at the paths, directories included
+  add      adds or replaces the files at the paths
+  remove   removes the entries at the paths
+  patch    applies the Thor patches at the paths
+  compact  reclaims the space of removed entries
+  repack   compresses every entry again and compacts";
+
+fn main() -> ExitCode {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+
+    let mut level = None;
+    if let Some(index) = args.iter().position(|arg| arg == "--level") {
+        match args.get(index + 1).and_then(|level| level.parse::<u8>().ok()) {
+            Some(value) if value <= 9 => level = Some(value),
+            _ => return usage(),
+        }
+        args.drain(index..index + 2);
+    }
+
+    let (command, archive, paths) = match args.as_slice() {
+        [command, archive, paths @ ..] => (command.as_str(), Path::new(archive), paths),
+        _ => return usage(),
+    };
+
+    let result = match command {
+        "list" => list(archive),
+        "create" => GrfWriter::create(archive).and_then(|writer| add(writer, level, paths)),
+        "add" => GrfWriter::open(archive).and_then(|writer| add(writer, level, paths)),
+        "remove" => GrfWriter::open(archive).and_then(|mut writer| {
+            for path in paths {
+                if !writer.remove(Path::new(path)) {
+                    println!("Not in the archive: {}", path);
+                }
+            }
+            writer.finish()
+        }),
+        "patch" => GrfWriter::open(archive).and_then(|mut writer| {
+            for path in paths {
+                let mut patch = ThorPatch::from_path(Path::new(path))?;
+                if let Err(err) = patch.check_target(archive) {
+                    println!("Skipped {}: {:?}", path, err);
+                    continue;
+                }
+                patch.apply(&mut writer)?;
+                println!("Applied {}", path);
+            }
+            writer.finish()
+        }),
+        "compact" => GrfWriter::open(archive).and_then(|mut writer| {
+            println!("Reclaiming {} bytes", writer.wasted_space());
+            writer.compact()?;
+            writer.finish()
+        }),
+        "repack" => GrfWriter::open(archive).and_then(|mut writer| {
+            if let Some(level) = level {
+                writer.set_compression_level(level);
+            }
+            writer.recompress()?;
+            writer.finish()
+        }),
+        _ => return usage(),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{}: {:?}", archive.display(), err);
+            ExitCode::FAILURE
+        },
+    }
+}
+
+fn usage() -> ExitCode {
+    eprintln!("{}", USAGE);
+    ExitCode::FAILURE
+}
+
+fn list(archive: &Path) -> Result<(), GrfError> {
+    let grf = Grf::from_path(archive)?;
+
+    let mut entries: Vec<_> = grf.files_table.files.iter().collect();
+    entries.sort_by_key(|(path, _)| *path);
+    for (path, file_entry) in entries {
+        println!("{:>10} {:>10} {}", file_entry.uncompressed_size, file_entry.compressed_size, path.display());
+    }
+    Ok(())
+}
+
+/// Entries are named after the paths as given, `data/sprite` adding
+/// everything under it as `data\sprite\...`.
+fn add(mut writer: GrfWriter, level: Option<u8>, paths: &[String]) -> Result<(), GrfError> {
+    if let Some(level) = level {
+        writer.set_compression_level(level);
+    }
+
+    let mut files = Vec::new();
+    for path in paths {
+        collect_files(Path::new(path.trim_start_matches("./")), &mut files)?;
+    }
+
+    for file in files {
+        writer.add(&file, &fs::read(&file)?)?;
+        println!("Added {}", file.display());
+    }
+    writer.finish()
+}
+
+fn collect_files(path: &Path, files: &mut Vec<PathBuf>) -> Result<(), GrfError> {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+
+    let mut entries = fs::read_dir(path)?.map(|entry| entry.map(|entry| entry.path())).collect::<Result<Vec<_>, _>>()?;
+    entries.sort();
+    for entry in entries {
+        collect_files(&entry, files)?;
+    }
+    Ok(())
+}
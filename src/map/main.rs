@@ -0,0 +1,105 @@
+use std::error::Error;
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::time::Duration;
+
+use characters::SqliteStore;
+use config::{ConfigSource, MapConfig};
+use griffon::GrfSet;
+use network::{InterServerLink, Reactor};
+use packet::{Packet, PacketParser, PacketRegistry, PacketTable};
+use packets::inter::{PacketZhMapserverConnect, PacketZhPing, INTER_VERSION};
+use systems::map::{map_system, MapServerConfig};
+use systems::System;
+use tracing::{info, warn};
+
+const CONFIG_PATH: &str = "conf/map.toml";
+
+/// How often the config files are checked for changes.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(5);
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (mut source, args) = ConfigSource::from_args(CONFIG_PATH, &args)?;
+    if let Some(arg) = args.first() {
+        return Err(format!("unknown argument '{}'", arg).into());
+    }
+    let config: MapConfig = source.load()?;
+    let log = config.log.init()?;
+    info!(path = %source.path().display(), "Loaded the configuration");
+
+    let store = SqliteStore::open(&config.map.characters_db)?;
+    info!(path = %config.map.characters_db.display(), "Loaded characters");
+
+    let grf_path = &config.map.grf;
+    let mut grfs = GrfSet::open(grf_path).map_err(|err| format!("couldn't open '{}': {:?}", grf_path.display(), err))?;
+    grfs.set_overlay(config.map.overlay.clone());
+    let maps = maps::load_from_grf(&grfs, config.map.map_names())?;
+    info!(maps = maps.len(), archives = grfs.archives().len(), path = %grf_path.display(), "Loaded maps");
+
+    let addr = config.network.bind;
+    let tcp_listener = TcpListener::bind(addr)?;
+    let table_path = &config.network.packet_table;
+    let packet_table = PacketTable::from_path(table_path)?;
+    let packetver = config.network.packetver;
+    let packet_parser = Arc::new(PacketParser::from_table(&packet_table, packetver));
+    info!(path = %table_path.display(), packets = packet_parser.len(), ?packetver, "Loaded packet lengths");
+    let mut registry = PacketRegistry::new();
+    packets::map::register(&mut registry);
+    for mismatch in registry.check_against(&packet_table, packetver) {
+        warn!(%mismatch, "Packet table mismatch");
+    }
+
+    let reactor = Reactor::new(packet_parser, config.network.reactor_config())
+        .on_disconnect(systems::map::on_disconnect);
+    let sessions = reactor.registry().clone();
+
+    let mut inter = PacketRegistry::new();
+    packets::inter::register_map_link(&mut inter);
+    let connect = PacketZhMapserverConnect {
+        version: INTER_VERSION,
+        secret: config.inter.secret.clone(),
+        ip: u32::from_le_bytes(config.map.public_address.ip().octets()),
+        port: config.map.public_address.port(),
+        ..PacketZhMapserverConnect::new()
+    }
+    .serialize()?;
+    let char_server = InterServerLink::connect(
+        &config.inter.connect,
+        Arc::new(PacketParser::from_registry(&inter)),
+        config.inter.link_config(PacketZhPing::new().serialize()?),
+        move |link| link.send(&connect),
+        move |packet| systems::map::on_char_packet(&sessions, packet),
+    )?;
+
+    let user_count_link = char_server.clone();
+    let user_count_interval = config.inter.user_count_interval();
+    std::thread::spawn(move || loop {
+        std::thread::sleep(user_count_interval);
+        if let Err(err) = systems::map::send_user_count(&user_count_link) {
+            warn!(%err, "Couldn't send the user count to the char server");
+        }
+    });
+
+    systems::map::init(MapServerConfig {
+        store: Arc::new(store),
+        char_server,
+        maps,
+    });
+    source.watch(config, RELOAD_INTERVAL, move |config: &MapConfig| {
+        if let Err(err) = log.set_level(&config.log.level) {
+            warn!(%err, "Couldn't change the log level");
+        }
+    });
+
+    let systems: Vec<System> = vec![
+        map_system,
+    ];
+
+    info!(%addr, "Listening");
+    reactor.run(tcp_listener, move |session, packet| {
+        systems::dispatch(&systems, session, packet);
+    })?;
+
+    Ok(())
+}
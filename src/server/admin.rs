@@ -0,0 +1,99 @@
+use std::error::Error;
+use std::io::{self, BufRead};
+use std::net::Ipv4Addr;
+
+use accounts::{AccountState, IpBan};
+use network::SessionRegistry;
+
+const HELP: &str = "Commands:
+  ban <username> [seconds]         bans an account, for good without a duration
+  lock <username> [seconds]        locks an account, until unlocked without a duration
+  unban <username>                 lifts a ban or a lock
+  unlock <username>                same as unban
+  ipban <ip>[-<ip>] [seconds] [reason]
+  ipunban <ip>[-<ip>]
+  ipbans                           lists the address bans";
+
+/// Reads admin commands from stdin until it is closed.
+pub fn run_console(registry: SessionRegistry) {
+    for line in io::stdin().lock().lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        let args: Vec<&str> = line.split_whitespace().collect();
+        if args.is_empty() {
+            continue;
+        }
+
+        match run_command(&registry, &args) {
+            Ok(message) => println!("{}", message),
+            Err(err) => println!("{}: {}", args[0], err),
+        }
+    }
+}
+
+fn run_command(registry: &SessionRegistry, args: &[&str]) -> Result<String, Box<dyn Error>> {
+    let now = accounts::unix_time();
+
+    match args {
+        ["ban" | "lock", username, rest @ ..] => {
+            let until = parse_duration(rest.first())?.map(|duration| now.saturating_add(duration));
+            let state = if args[0] == "ban" {
+                AccountState::Banned { until }
+            } else {
+                AccountState::Locked { until }
+            };
+            let account = set_state(registry, username, state)?;
+            Ok(format!("Account '{}' is now {:?}", account.username, account.state))
+        },
+        ["unban" | "unlock", username] => {
+            let account = set_state(registry, username, AccountState::Active)?;
+            Ok(format!("Account '{}' is now active", account.username))
+        },
+        ["ipban", range, rest @ ..] => {
+            let (start, end) = parse_range(range)?;
+            let until = parse_duration(rest.first())?.map(|duration| now.saturating_add(duration));
+            let reason = rest.get(1..).unwrap_or_default().join(" ");
+            systems::auth::ban_ip(registry, IpBan { start, end, until, reason })?;
+            Ok(format!("Banned {}-{}", start, end))
+        },
+        ["ipunban", range] => {
+            let (start, end) = parse_range(range)?;
+            let removed = systems::auth::store()?.remove_ip_ban(start, end)?;
+            Ok(format!("Removed {} ban(s) of {}-{}", removed, start, end))
+        },
+        ["ipbans"] => {
+            let bans = systems::auth::store()?.ip_bans()?;
+            Ok(bans
+                .iter()
+                .filter(|ban| ban.is_active(now))
+                .map(|ban| match ban.until {
+                    Some(until) => format!("{}-{} until {} {}", ban.start, ban.end, accounts::format_time(until), ban.reason),
+                    None => format!("{}-{} {}", ban.start, ban.end, ban.reason),
+                })
+                .collect::<Vec<_>>()
+                .join("\n"))
+        },
+        _ => Ok(HELP.to_string()),
+    }
+}
+
+fn set_state(registry: &SessionRegistry, username: &str, state: AccountState) -> Result<accounts::Account, Box<dyn Error>> {
+    let account = systems::auth::store()?
+        .find_by_username(username)?
+        .ok_or_else(|| format!("no account named '{}'", username))?;
+    Ok(systems::auth::set_account_state(registry, account.account_id, state)?)
+}
+
+fn parse_duration(arg: Option<&&str>) -> Result<Option<u64>, Box<dyn Error>> {
+    Ok(arg.map(|duration| duration.parse::<u64>()).transpose()?)
+}
+
+fn parse_range(range: &str) -> Result<(Ipv4Addr, Ipv4Addr), Box<dyn Error>> {
+    let (start, end) = range.split_once('-').unwrap_or((range, range));
+    let (start, end) = (start.parse::<Ipv4Addr>()?, end.parse::<Ipv4Addr>()?);
+    if start > end {
+        return Err(format!("{} comes after {}", start, end).into());
+    }
+    Ok((start, end))
+}
@@ -1,112 +1,156 @@
-use std::collections::VecDeque;
 use std::error::Error;
 use std::net::TcpListener;
-use std::sync::{Arc, Mutex};
-use std::thread::{self, sleep};
+use std::sync::Arc;
 use std::time::Duration;
 
-use network::PlayerSession;
-use packet::PacketParser;
+use accounts::{AccountStore, LoginPolicy, NewAccount, PasswordScheme, Sex, SqliteStore};
+use config::{ConfigSource, LoginConfig, LoginSection};
+use network::{Reactor, ReactorConfig};
+use packet::{PacketParser, PacketRegistry, PacketTable};
 use systems::System;
-use systems::auth::auth_system;
+use systems::char_servers::{CharServer, CharServerRegistry};
+use systems::auth::{auth_system, char_server_system};
+use tracing::{error, info, warn};
 
+mod admin;
 
 
+const CONFIG_PATH: &str = "conf/login.toml";
+
+/// How often the config files are checked for changes.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(5);
+
+/// `server --add-account <username> <password> [F|M] [plain]` creates an account and exits.
+/// Plain passwords are stored in clear, only for clients logging in with
+/// `CA_REQ_HASH`, and need `login.allow_plain_passwords`.
+fn add_account(store: &SqliteStore, config: &LoginSection, args: &[String]) -> Result<(), Box<dyn Error>> {
+    let [username, password, rest @ ..] = args else {
+        return Err("usage: server --add-account <username> <password> [F|M] [plain]".into());
+    };
+    let sex = match rest.first() {
+        Some(sex) => sex.chars().next().and_then(Sex::from_char).ok_or("sex must be F or M")?,
+        None => Sex::Male,
+    };
+    let account = match rest.get(1).map(String::as_str) {
+        None => NewAccount::new(username, password, sex, PasswordScheme::Argon2)?,
+        Some("plain") if config.allow_plain_passwords => NewAccount::with_plain_password(username, password, sex),
+        Some("plain") => return Err("plain passwords need login.allow_plain_passwords = true".into()),
+        Some(_) => return Err("the only option after the sex is plain".into()),
+    };
+
+    let account = store.create(account)?;
+    println!("Created account '{}' with id {}", account.username, account.account_id);
+    Ok(())
+}
+
+/// Char servers listed while none is connected.
+fn static_char_servers(config: &LoginSection) -> Vec<CharServer> {
+    config
+        .char_servers
+        .iter()
+        .map(|server| CharServer {
+            name: server.name.clone(),
+            ip: *server.address.ip(),
+            port: server.address.port(),
+            users: 0,
+            server_type: server.server_type,
+            is_new: server.new,
+        })
+        .collect()
+}
+
+fn login_policy(config: &LoginSection) -> LoginPolicy {
+    LoginPolicy {
+        allow_legacy_md5: config.allow_legacy_md5,
+        allow_plain_passwords: config.allow_plain_passwords,
+        max_failed_attempts: (config.max_failed_attempts > 0).then_some(config.max_failed_attempts),
+        lock_duration: (config.lock_duration > 0).then_some(config.lock_duration),
+    }
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
-    let thread_count = 2;
-    let addr = "127.0.0.1:6900";
-    let tcp_listener = Arc::new(TcpListener::bind(&addr)?);
-    let connections: Arc<Mutex<VecDeque<PlayerSession>>> = Arc::new(Mutex::new(VecDeque::new()));
-    let packet_parser = Arc::new(PacketParser::new("auth_packets.txt"));
-    println!("Listening on: {}", addr);
-
-    let mut threads = Vec::new();
-
-    let listener = tcp_listener.clone();
-    let conn = connections.clone();
-    let listener_thread = thread::spawn(move || loop {
-        let (socket, _) = listener.accept().expect("Failed to accept connection!");
-
-        let player_session = PlayerSession::new(socket);
-        println!("Player connected {:?}", &player_session);
-        let mut sessions = conn.lock().unwrap();
-        sessions.push_back(player_session);
-        drop(sessions);
-        sleep(Duration::from_micros(500));
-    });
-    threads.push(listener_thread);
-
-    for n in 0..thread_count {
-        let connections = connections.clone();
-        let packet_parser = packet_parser.clone();
-        
-        let network_thread = thread::Builder::new().name(format!("Network Thread {}", n)).spawn(move || {
-            let tick_duration = Duration::from_micros(500);
-
-            loop {
-                let mut sessions = connections.lock().unwrap();
-                let mut session = match sessions.pop_front() {
-                    Some(s) => s,
-                    None => {
-                        drop(sessions);
-                        sleep(tick_duration);
-                        continue;
-                    }
-                };
-            
-                drop(sessions);
-
-                use network::UpdateResponse;
-                match session.update_sockets() {
-                    UpdateResponse::Ok(data) => {
-                        session.recv_queue.extend(packet_parser.extract_packets(data.as_slice()));
-                    },
-                    UpdateResponse::NoContent => {}
-                    _ => {
-                        sleep(tick_duration);
-                        continue;
-                    },
-                }
-
-                let mut sessions = connections.lock().unwrap();
-                session.transmit();
-                sessions.push_back(session);
-                drop(sessions);
-                sleep(tick_duration);
-            }
-        });
-        threads.push(network_thread.unwrap());
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (mut source, args) = ConfigSource::from_args(CONFIG_PATH, &args)?;
+    let config: LoginConfig = source.load()?;
+    let log = config.log.init()?;
+    info!(path = %source.path().display(), "Loaded the configuration");
+
+    let store = SqliteStore::open(&config.login.accounts_db)?;
+    match args.first().map(String::as_str) {
+        Some("--add-account") => return add_account(&store, &config.login, &args[1..]),
+        Some(arg) => return Err(format!("unknown argument '{}'", arg).into()),
+        None => {},
+    }
+
+    // Char servers can't register without it.
+    let inter_secret = Some(config.login.inter_secret.clone()).filter(|secret| !secret.is_empty());
+    if inter_secret.is_none() {
+        warn!("login.inter_secret isn't set, char servers won't be able to register");
+    }
+
+    // Char servers get their own listener, the players' one not knowing
+    // the inter-server packets.
+    let inter_addr = config.login.inter_bind;
+    let inter_listener = TcpListener::bind(inter_addr)?;
+    let mut inter = PacketRegistry::new();
+    packets::inter::register_login_link(&mut inter);
+    let inter_reactor = Reactor::new(
+        Arc::new(PacketParser::from_registry(&inter)),
+        ReactorConfig { workers: 1, ..config.network.reactor_config() },
+    )
+    .on_disconnect(systems::auth::on_char_server_disconnect);
+
+    let char_servers = CharServerRegistry::new(static_char_servers(&config.login), inter_reactor.registry().clone());
+    systems::auth::init(Arc::new(store), login_policy(&config.login), char_servers, inter_secret);
+    info!(path = %config.login.accounts_db.display(), "Loaded accounts");
+
+    let addr = config.network.bind;
+    let tcp_listener = TcpListener::bind(addr)?;
+    let table_path = &config.network.packet_table;
+    let packet_table = PacketTable::from_path(table_path)?;
+    let packetver = config.network.packetver;
+    let packet_parser = Arc::new(PacketParser::from_table(&packet_table, packetver));
+    info!(path = %table_path.display(), packets = packet_parser.len(), ?packetver, "Loaded packet lengths");
+    let mut registry = PacketRegistry::new();
+    packets::auth::register(&mut registry);
+    for mismatch in registry.check_against(&packet_table, packetver) {
+        warn!(%mismatch, "Packet table mismatch");
     }
-    println!("Running a total of {} Network threads", threads.len());
+    info!(%addr, "Listening");
 
-    
-    let systems: Arc<Vec<System>> = Arc::new(vec![
+    let systems: Vec<System> = vec![
         auth_system,
-    ]);
-    let conn = connections.clone();
-    let systems = systems.clone();
-    let server_thread = thread::spawn(move || loop {
-        let tick_duration = Duration::from_micros(5);
-        let mut sessions = conn.lock().unwrap();
-
-        for session in sessions.iter_mut() {
-            while let Some(packet) = session.recv_queue.pop_front() {
-                println!("Received packet {:#06X} with {} bytes", packet.packet_id, packet.length);
-                use systems::SystemResult::*;
-                systems.iter().any(|s| match s(session, &packet) {
-                    Processed => true,
-                    NotProcessed => false
-                });
-            }
+    ];
+
+    let reactor = Reactor::new(packet_parser, config.network.reactor_config())
+        .on_disconnect(systems::auth::on_disconnect);
+    systems::auth::spawn_login_threads(reactor.registry().clone(), config.login.login_threads);
+    source.watch(config, RELOAD_INTERVAL, move |config: &LoginConfig| {
+        if let Err(err) = log.set_level(&config.log.level) {
+            warn!(%err, "Couldn't change the log level");
         }
-        drop(sessions);
-        sleep(tick_duration);
+        systems::auth::set_policy(login_policy(&config.login));
+        systems::auth::set_fallback_char_servers(static_char_servers(&config.login));
     });
-    threads.push(server_thread);
 
-    for thread in threads {
-        let _ = thread.join();
-    }
+    let registry = reactor.registry().clone();
+    std::thread::spawn(move || admin::run_console(registry));
+
+    info!(addr = %inter_addr, "Listening for char servers");
+    std::thread::spawn(move || {
+        let systems: Vec<System> = vec![
+            char_server_system,
+        ];
+        let result = inter_reactor.run(inter_listener, move |session, packet| {
+            systems::dispatch(&systems, session, packet);
+        });
+        if let Err(err) = result {
+            error!(%err, "The char server listener stopped");
+        }
+    });
+    reactor.run(tcp_listener, move |session, packet| {
+        systems::dispatch(&systems, session, packet);
+    })?;
 
     Ok(())
 }
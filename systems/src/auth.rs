@@ -1,50 +1,539 @@
-use std::net::Ipv4Addr;
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::thread;
 
-use packet::{Packet, RawPacket};
-use network::PlayerSession;
+use accounts::{Account, AccountState, AccountStore, Credentials, IpBan, LoginError, LoginPolicy, LoginSuccess, Sex, StoreError};
+use packet::{Packet, RawPacket, PACKETVER_LATEST};
+use network::{DisconnectReason, PlayerSession, SessionId, SessionRegistry, SessionState};
 use packets::auth::*;
+use packets::inter::{
+	PacketAhAuthResult, PacketAhCharserverConnectAck, PacketAhKickAccount, PacketAhPong, PacketHaAuthRequest,
+	PacketHaCharserverConnect, PacketHaPing, PacketHaUserCount, CONNECT_ACCEPTED, CONNECT_REFUSED, CONNECT_VERSION_MISMATCH, INTER_VERSION,
+};
+use tracing::{debug, error, info, warn};
+
+use crate::char_servers::{CharServer, CharServerRegistry, CharServerType};
 
 use super::SystemResult::{self, *};
 
+const REFUSE_UNREGISTERED_ID: u8 = 0;
+const REFUSE_INCORRECT_PASSWORD: u8 = 1;
+const REFUSE_EXPIRED: u8 = 2;
+const REFUSE_REJECTED: u8 = 3;
+const REFUSE_BLOCKED: u8 = 4;
+const REFUSE_BANNED_UNTIL: u8 = 6;
+
+const NOTIFY_KICKED: u8 = 15;
+
+/// Seconds a player has to reach a char server after logging in.
+const AUTH_CODE_LIFETIME: u64 = 60;
+
+static ACCOUNTS: OnceLock<(Arc<dyn AccountStore>, RwLock<LoginPolicy>)> = OnceLock::new();
+static CHAR_SERVERS: OnceLock<CharServerRegistry> = OnceLock::new();
+
+/// Secret char servers register with, none are accepted without it.
+static INTER_SECRET: OnceLock<Option<String>> = OnceLock::new();
+
+/// Challenges sent in `AC_ACK_HASH`, until the session logs in or leaves.
+static CHALLENGES: Mutex<BTreeMap<SessionId, Vec<u8>>> = Mutex::new(BTreeMap::new());
+
+/// Auth codes handed to players, by account, until a char server checks them.
+static AUTH_CODES: Mutex<BTreeMap<u32, AuthCode>> = Mutex::new(BTreeMap::new());
+
+/// Logins waiting for a login thread to check their password.
+static LOGIN_JOBS: OnceLock<Sender<LoginJob>> = OnceLock::new();
+
+/// Sessions whose login is being checked, each checked once at a time.
+static PENDING_LOGINS: Mutex<BTreeSet<SessionId>> = Mutex::new(BTreeSet::new());
+
+/// A login checked by a login thread, the reply going back to the session
+/// through `SessionRegistry::run`.
+struct LoginJob {
+	id: SessionId,
+	packetver: u32,
+	username: String,
+	credentials: LoginCredentials,
+	ip: Option<Ipv4Addr>,
+}
+
+/// `Credentials` owned by a login job.
+enum LoginCredentials {
+	Password(String),
+	Md5Digest { digest: [u8; 16], challenge: Vec<u8> },
+}
+
+impl LoginCredentials {
+	fn as_credentials(&self) -> Credentials<'_> {
+		match self {
+			LoginCredentials::Password(password) => Credentials::Password(password),
+			LoginCredentials::Md5Digest { digest, challenge } => Credentials::Md5Digest { digest: *digest, challenge },
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy)]
+struct AuthCode {
+	auth_code: u32,
+	user_level: u32,
+	sex: u8,
+	expires: u64,
+}
+
+/// Sets the accounts checked by `auth_system`, the char servers listed to
+/// players and the secret they register with, returns `false` if they were
+/// already set.
+pub fn init(store: Arc<dyn AccountStore>, policy: LoginPolicy, char_servers: CharServerRegistry, inter_secret: Option<String>) -> bool {
+	ACCOUNTS.set((store, RwLock::new(policy))).is_ok() & CHAR_SERVERS.set(char_servers).is_ok() & INTER_SECRET.set(inter_secret).is_ok()
+}
+
+/// Starts the threads checking passwords, which take too long to be checked
+/// on the network threads of `sessions`. Returns `false` if they were
+/// already started.
+pub fn spawn_login_threads(sessions: SessionRegistry, threads: usize) -> bool {
+	let (sender, receiver) = mpsc::channel::<LoginJob>();
+	if LOGIN_JOBS.set(sender).is_err() {
+		return false;
+	}
+
+	let receiver = Arc::new(Mutex::new(receiver));
+	for i in 0..threads.max(1) {
+		let receiver = receiver.clone();
+		let sessions = sessions.clone();
+		let spawned = thread::Builder::new().name(format!("login-{}", i)).spawn(move || loop {
+			let job = receiver.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).recv();
+			match job {
+				Ok(job) => check_login(&sessions, job),
+				Err(_) => break,
+			}
+		});
+		if let Err(err) = spawned {
+			error!(%err, "Couldn't start a login thread");
+		}
+	}
+	true
+}
+
+/// Applies to the logins after it, returns `false` before `init`.
+pub fn set_policy(policy: LoginPolicy) -> bool {
+	let Some((_, current)) = ACCOUNTS.get() else {
+		return false;
+	};
+	*current.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = policy;
+	true
+}
+
+/// Char servers listed while none is connected, returns `false` before `init`.
+pub fn set_fallback_char_servers(servers: Vec<CharServer>) -> bool {
+	let Some(char_servers) = CHAR_SERVERS.get() else {
+		return false;
+	};
+	char_servers.set_fallback(servers);
+	true
+}
+
+pub fn store() -> Result<&'static dyn AccountStore, StoreError> {
+	ACCOUNTS.get()
+		.map(|(store, _)| store.as_ref())
+		.ok_or_else(|| StoreError::Backend("no account store was set".to_string()))
+}
+
+/// Changes the state of an account and kicks it from the login and char
+/// servers when it can't log in anymore.
+pub fn set_account_state(registry: &SessionRegistry, account_id: u32, state: AccountState) -> Result<Account, StoreError> {
+	let account = accounts::set_account_state(store()?, account_id, state)?;
+
+	if state != AccountState::Active {
+		if let Some(id) = registry.find_by_account(account_id) {
+			kick(registry, id);
+		}
+		kick_from_char_servers(account_id);
+	}
+	Ok(account)
+}
+
+/// Asks every char server to disconnect the account, wherever it is.
+pub fn kick_from_char_servers(account_id: u32) {
+	let Ok(buf) = (PacketAhKickAccount { aid: account_id, ..PacketAhKickAccount::new() }).serialize() else {
+		return;
+	};
+	if let Some(char_servers) = CHAR_SERVERS.get() {
+		char_servers.broadcast(buf);
+	}
+}
+
+/// Bans a range of addresses and kicks every session connected from it.
+pub fn ban_ip(registry: &SessionRegistry, ban: IpBan) -> Result<(), StoreError> {
+	store()?.add_ip_ban(ban.clone())?;
+
+	for session in registry.sessions() {
+		if let IpAddr::V4(ip) = session.peer_addr.ip() {
+			if ban.contains(ip) {
+				kick(registry, session.id);
+			}
+		}
+	}
+	Ok(())
+}
+
+fn kick(registry: &SessionRegistry, id: SessionId) {
+	if let Ok(buf) = (PacketScNotifyBan { error_code: NOTIFY_KICKED, ..PacketScNotifyBan::new() }).serialize() {
+		registry.send_to(id, buf);
+	}
+	registry.close(id);
+}
+
 pub fn auth_system(session: &mut PlayerSession, packet: &RawPacket) -> SystemResult {
+	let packetver = packet.packetver();
 	let result = match packet.packet_id {
 		0x0064 => packet.parse::<PacketCaLogin>()
-			.map(|p| process_login(session, p)),
-		_ => None, // if not processed it can be processed by another system
+			.map(|p| process_login(session, packetver, &p.username, Credentials::Password(&p.password))),
+		0x0277 => packet.parse::<PacketCaLoginPcbang>()
+			.map(|p| process_login(session, packetver, &p.username, Credentials::Password(&p.password))),
+		0x02B0 => packet.parse::<PacketCaLoginHan>()
+			.map(|p| process_login(session, packetver, &p.username, Credentials::Password(&p.password))),
+		0x0ACF => packet.parse::<PacketCaLoginOtp>()
+			.map(|p| process_login(session, packetver, &p.username, Credentials::Password(&p.password))),
+		0x0825 => packet.parse::<PacketCaSsoLoginReq>()
+			.map(|p| process_sso_login(session, packetver, p)),
+		0x01DD => packet.parse::<PacketCaLogin2>()
+			.map(|p| process_hashed_login(session, packetver, &p.username, p.password_md5)),
+		0x01FA => packet.parse::<PacketCaLogin3>()
+			.map(|p| process_hashed_login(session, packetver, &p.username, p.password_md5)),
+		0x027C => packet.parse::<PacketCaLogin4>()
+			.map(|p| process_hashed_login(session, packetver, &p.username, p.password_md5)),
+		0x01DB => packet.parse::<PacketCaReqHash>()
+			.map(|_| process_hash_request(session)),
+		_ => return NotProcessed, // if not processed it can be processed by another system
 	};
 
-	match result {
-		Some(_) => Processed,
-		None => NotProcessed
+	if let Err(err) = result {
+		warn!(id = %format_args!("{:#06X}", packet.packet_id), %err, "Couldn't parse packet");
+		session.close();
 	}
+
+	Processed
 }
 
-fn process_login(session: &mut PlayerSession, _pkt: PacketCaLogin) {
-	let mut accepted: PacketAcAcceptLogin2 = PacketAcAcceptLogin2::new();
+/// Packets of the char servers, on their own listener so that players
+/// can't send them.
+pub fn char_server_system(session: &mut PlayerSession, packet: &RawPacket) -> SystemResult {
+	let result = match packet.packet_id {
+		0x2710 => packet.parse::<PacketHaCharserverConnect>()
+			.map(|p| process_char_server_connect(session, p)),
+		0x2714 => packet.parse::<PacketHaUserCount>()
+			.map(|p| process_user_count(session, p)),
+		0x2712 => packet.parse::<PacketHaAuthRequest>()
+			.map(|p| process_auth_request(session, p)),
+		0x2715 => packet.parse::<PacketHaPing>()
+			.map(|_| process_ping(session)),
+		_ => return NotProcessed, // if not processed it can be processed by another system
+	};
 
-	accepted.aid = 2000000;
-	accepted.auth_code = 2000000;
+	if let Err(err) = result {
+		warn!(id = %format_args!("{:#06X}", packet.packet_id), %err, "Couldn't parse packet");
+		session.close();
+	}
 
-	let mut server = CharServerList::default();
-	server.ip = u32::from_be_bytes(Ipv4Addr::new(127, 0, 0, 1).octets());
+	Processed
+}
 
+pub fn on_disconnect(session: &mut PlayerSession, reason: DisconnectReason) {
+	take_challenge(session.id());
+	PENDING_LOGINS.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).remove(&session.id());
+	if session.state() == SessionState::Connecting {
+		debug!(?reason, "Left before logging in");
+	}
+}
 
-	let v = b"Einbroch";
-	server.name[..v.len()].copy_from_slice(v);
+pub fn on_char_server_disconnect(session: &mut PlayerSession, reason: DisconnectReason) {
+	if let Some(server) = CHAR_SERVERS.get().and_then(|servers| servers.unregister(session.id())) {
+		info!(name = %server.name, ?reason, "Char server disconnected");
+	}
+}
+
+fn take_challenge(id: SessionId) -> Option<Vec<u8>> {
+	CHALLENGES.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).remove(&id)
+}
 
-	server.port = 6121;
-	server.usercount = 10;
+fn process_hash_request(session: &mut PlayerSession) {
+	let mut random = [0u8; 16];
+	if let Err(err) = getrandom::fill(&mut random) {
+		error!(%err, "Dropping session: couldn't generate a challenge");
+		session.close();
+		return;
+	}
 
+	// Clients read the challenge as a C string, so it can't contain a NUL.
+	let len = 12 + random[0] as usize % 4;
+	let salt: Vec<u8> = random[1..=len].iter().map(|byte| 1 + byte % 255).collect();
+
+	match (PacketAcAckHash { salt: salt.clone(), ..PacketAcAckHash::new() }).serialize() {
+		Ok(buf) => {
+			session.send_queue.push_back(buf);
+			CHALLENGES.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).insert(session.id(), salt);
+		},
+		Err(err) => error!(%err, "Couldn't serialize packet"),
+	}
+}
+
+/// Only after `CA_REQ_HASH`, a digest without a challenge being replayable.
+fn process_hashed_login(session: &mut PlayerSession, packetver: u32, username: &str, digest: [u8; 16]) {
+	let Some(challenge) = take_challenge(session.id()) else {
+		warn!(username, "Refusing hashed login: no challenge was requested");
+		refuse_login(session, packetver, REFUSE_REJECTED, String::new());
+		return;
+	};
+	process_login(session, packetver, username, Credentials::Md5Digest { digest, challenge: &challenge });
+}
+
+/// The launcher puts the password, or a token standing for it, after the fixed fields.
+fn process_sso_login(session: &mut PlayerSession, packetver: u32, pkt: PacketCaSsoLoginReq) {
+	let token_len = pkt.t1.iter().position(|&b| b == 0).unwrap_or(pkt.t1.len());
+	let password = match std::str::from_utf8(&pkt.t1[..token_len]) {
+		Ok(token) if !token.is_empty() => token,
+		_ => pkt.password.as_str(),
+	};
+	process_login(session, packetver, &pkt.id, Credentials::Password(password));
+}
+
+/// Hands the login to the login threads, `finish_login` replying once the
+/// password is checked.
+fn process_login(session: &mut PlayerSession, packetver: u32, username: &str, credentials: Credentials) {
+	let Some(jobs) = LOGIN_JOBS.get() else {
+		error!(username, "Refusing login: no login thread was started");
+		refuse_login(session, packetver, REFUSE_REJECTED, String::new());
+		return;
+	};
+	if !PENDING_LOGINS.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).insert(session.id()) {
+		warn!(username, "Ignoring login: the previous one is still being checked");
+		return;
+	}
+
+	let job = LoginJob {
+		id: session.id(),
+		packetver,
+		username: username.to_string(),
+		credentials: match credentials {
+			Credentials::Password(password) => LoginCredentials::Password(password.to_string()),
+			Credentials::Md5Digest { digest, challenge } => LoginCredentials::Md5Digest { digest, challenge: challenge.to_vec() },
+		},
+		ip: match session.peer_addr.ip() {
+			IpAddr::V4(ip) => Some(ip),
+			IpAddr::V6(ip) => ip.to_ipv4_mapped(),
+		},
+	};
+	if jobs.send(job).is_err() {
+		PENDING_LOGINS.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).remove(&session.id());
+		error!(username, "Refusing login: the login threads stopped");
+		refuse_login(session, packetver, REFUSE_REJECTED, String::new());
+	}
+}
+
+/// Runs on a login thread.
+fn check_login(sessions: &SessionRegistry, job: LoginJob) {
+	let result = match ACCOUNTS.get() {
+		Some((store, policy)) => {
+			let policy = *policy.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+			accounts::authenticate(store.as_ref(), &job.username, job.credentials.as_credentials(), job.ip, policy)
+		},
+		None => Err(LoginError::Store(StoreError::Backend("no account store was set".to_string()))),
+	};
+
+	let LoginJob { id, packetver, username, .. } = job;
+	sessions.run(id, Box::new(move |session| {
+		PENDING_LOGINS.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).remove(&id);
+		finish_login(session, packetver, &username, result);
+	}));
+}
+
+fn finish_login(session: &mut PlayerSession, packetver: u32, username: &str, result: Result<LoginSuccess, LoginError>) {
+	match result {
+		Ok(login) if login.account.sex == Sex::Server => {
+			warn!(username, "Refusing login of a server account");
+			refuse_login(session, packetver, REFUSE_REJECTED, String::new());
+		},
+		Ok(login) => accept_login(session, packetver, login),
+		Err(err) => {
+			info!(username, %err, "Refusing login");
+			let (error_code, block_date) = match err {
+				LoginError::UnregisteredId => (REFUSE_UNREGISTERED_ID, String::new()),
+				LoginError::IncorrectPassword => (REFUSE_INCORRECT_PASSWORD, String::new()),
+				LoginError::Expired => (REFUSE_EXPIRED, String::new()),
+				LoginError::Banned { until: Some(until) } => (REFUSE_BANNED_UNTIL, accounts::format_time(until)),
+				LoginError::Banned { until: None } => (REFUSE_BLOCKED, String::new()),
+				LoginError::Locked { until: Some(until) } => (REFUSE_BANNED_UNTIL, accounts::format_time(until)),
+				LoginError::Locked { until: None } => (REFUSE_BLOCKED, String::new()),
+				LoginError::IpBanned { .. } | LoginError::Store(_) => (REFUSE_REJECTED, String::new()),
+			};
+			refuse_login(session, packetver, error_code, block_date);
+		},
+	}
+}
+
+fn accept_login(session: &mut PlayerSession, packetver: u32, login: LoginSuccess) {
+	let account = login.account;
+	let mut accepted: PacketAcAcceptLogin2 = PacketAcAcceptLogin2::new();
+
+	// Char servers trust these to tell who the player is, they can't be guessable.
+	let (auth_code, user_level) = match (getrandom::u32(), getrandom::u32()) {
+		(Ok(auth_code), Ok(user_level)) => (auth_code, user_level),
+		(Err(err), _) | (_, Err(err)) => {
+			error!(username = %account.username, %err, "Refusing login: couldn't generate an auth code");
+			refuse_login(session, packetver, REFUSE_REJECTED, String::new());
+			return;
+		},
+	};
+
+	accepted.aid = account.account_id;
+	accepted.auth_code = auth_code as i32;
+	accepted.user_level = user_level;
+	accepted.last_login_ip = login.previous_ip.map_or(0, |ip| u32::from_le_bytes(ip.octets()));
+	accepted.last_login_time = login.previous_login.map(accounts::format_time).unwrap_or_default();
+	accepted.sex = match account.sex {
+		Sex::Female => 0,
+		Sex::Male => 1,
+		Sex::Server => 2,
+	};
+
+	accepted.char_server_list = CHAR_SERVERS.get()
+		.map(|servers| servers.servers().iter().map(CharServer::to_list_entry).collect())
+		.unwrap_or_default();
 
-	accepted.char_server_list.push(server);
-	accepted.packet_len = accepted.len() as i16;
 	match accepted.serialize() {
-		Some(buf) => {
+		Ok(buf) => {
+			let code = AuthCode {
+				auth_code: accepted.auth_code as u32,
+				user_level: accepted.user_level,
+				sex: accepted.sex,
+				expires: accounts::unix_time() + AUTH_CODE_LIFETIME,
+			};
+			AUTH_CODES.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).insert(accepted.aid, code);
+
 			session.send_queue.push_back(buf);
-			println!("Added packet to the send list of session '{:?}'", session.socket.peer_addr());
+			session.set_account_id(Some(accepted.aid));
+			session.authenticate();
+			info!(username = %account.username, "Logged in");
 		},
-		None => {
-			println!("Couldn't serialize packet! {:?}", accepted);
+		Err(err) => {
+			error!(%err, ?accepted, "Couldn't serialize packet");
 		}
 	};
 }
+
+/// Clients of an unknown version, when no `packetver` is configured, get
+/// the oldest refusal since every client understands it.
+fn refuse_login(session: &mut PlayerSession, packetver: u32, error_code: u8, block_date: String) {
+	let result = match packetver {
+		PACKETVER_LATEST => PacketAcRefuseLogin { error_code, block_date, ..PacketAcRefuseLogin::new() }.serialize(),
+		20180627.. => PacketAcRefuseLoginR3 { error_code: error_code.into(), block_date, ..PacketAcRefuseLoginR3::new() }.serialize(),
+		20120000.. => PacketAcRefuseLoginR2 { error_code: error_code.into(), block_date, ..PacketAcRefuseLoginR2::new() }.serialize(),
+		_ => PacketAcRefuseLogin { error_code, block_date, ..PacketAcRefuseLogin::new() }.serialize(),
+	};
+
+	match result {
+		Ok(buf) => session.send_queue.push_back(buf),
+		Err(err) => error!(%err, "Couldn't serialize packet"),
+	}
+	session.close();
+}
+
+fn process_char_server_connect(session: &mut PlayerSession, pkt: PacketHaCharserverConnect) {
+	let (Some(Some(secret)), Some(char_servers)) = (INTER_SECRET.get(), CHAR_SERVERS.get()) else {
+		warn!(name = %pkt.name, "Refusing char server: no inter-server secret is set");
+		ack_char_server(session, CONNECT_REFUSED);
+		return;
+	};
+	if pkt.version != INTER_VERSION {
+		warn!(name = %pkt.name, version = pkt.version, expected = INTER_VERSION, "Refusing char server: wrong inter-server version");
+		ack_char_server(session, CONNECT_VERSION_MISMATCH);
+		return;
+	}
+	if !crate::secret_matches(secret, &pkt.secret) {
+		warn!(name = %pkt.name, "Refusing char server: wrong secret");
+		ack_char_server(session, CONNECT_REFUSED);
+		return;
+	}
+
+	let server = CharServer {
+		name: pkt.name,
+		ip: Ipv4Addr::from(pkt.ip.to_le_bytes()),
+		port: pkt.port,
+		users: 0,
+		server_type: CharServerType::from_u16(pkt.server_type),
+		is_new: pkt.is_new != 0,
+	};
+	info!(name = %server.name, ip = %server.ip, port = server.port, "Char server connected");
+
+	session.authenticate();
+	char_servers.register(session.id(), server);
+	ack_char_server(session, CONNECT_ACCEPTED);
+}
+
+fn ack_char_server(session: &mut PlayerSession, result: u8) {
+	match (PacketAhCharserverConnectAck { result, ..PacketAhCharserverConnectAck::new() }).serialize() {
+		Ok(buf) => session.send_queue.push_back(buf),
+		Err(err) => error!(%err, "Couldn't serialize packet"),
+	}
+	if result != CONNECT_ACCEPTED {
+		session.close();
+	}
+}
+
+fn process_ping(session: &mut PlayerSession) {
+	if !CHAR_SERVERS.get().is_some_and(|servers| servers.is_registered(session.id())) {
+		warn!("Sent a ping without registering as a char server");
+		session.close();
+		return;
+	}
+	match PacketAhPong::new().serialize() {
+		Ok(buf) => session.send_queue.push_back(buf),
+		Err(err) => error!(%err, "Couldn't serialize packet"),
+	}
+}
+
+fn process_user_count(session: &mut PlayerSession, pkt: PacketHaUserCount) {
+	let users = pkt.users.min(u16::MAX.into()) as u16;
+	if !CHAR_SERVERS.get().is_some_and(|servers| servers.set_users(session.id(), users)) {
+		warn!("Sent a user count without registering as a char server");
+		session.close();
+	}
+}
+
+/// Checks the auth code a player showed to a char server. Each code is
+/// accepted once.
+fn process_auth_request(session: &mut PlayerSession, pkt: PacketHaAuthRequest) {
+	if !CHAR_SERVERS.get().is_some_and(|servers| servers.is_registered(session.id())) {
+		warn!("Sent an auth request without registering as a char server");
+		session.close();
+		return;
+	}
+
+	let now = accounts::unix_time();
+	let valid = {
+		let mut codes = AUTH_CODES.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+		codes.retain(|_, code| code.expires > now);
+		match codes.get(&pkt.aid) {
+			Some(code) if code.auth_code == pkt.auth_code && code.user_level == pkt.user_level && code.sex == pkt.sex => {
+				codes.remove(&pkt.aid);
+				true
+			},
+			_ => false,
+		}
+	};
+
+	let result = PacketAhAuthResult {
+		aid: pkt.aid,
+		auth_code: pkt.auth_code,
+		user_level: pkt.user_level,
+		sex: pkt.sex,
+		result: if valid { 0 } else { 1 },
+		request_id: pkt.request_id,
+		..PacketAhAuthResult::new()
+	};
+	match result.serialize() {
+		Ok(buf) => session.send_queue.push_back(buf),
+		Err(err) => error!(%err, "Couldn't serialize packet"),
+	}
+}
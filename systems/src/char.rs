@@ -0,0 +1,633 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::net::SocketAddrV4;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use characters::{Character, CharacterStore, Look, NewCharacter, Position, Sex, Stats, StoreError, MAX_CHARS};
+use network::{DisconnectReason, InterServerLink, PlayerSession, SessionId, SessionRegistry};
+use packet::{Packet, RawPacket};
+use packets::auth::PacketScNotifyBan;
+use packets::char::*;
+use packets::inter::*;
+use tracing::{error, info, warn};
+
+use super::SystemResult::{self, *};
+
+const REFUSE_ENTER_REJECTED: u8 = 0;
+
+const REFUSE_MAKE_NAME_TAKEN: u8 = 0x00;
+const REFUSE_MAKE_SYMBOLS: u8 = 0x02;
+const REFUSE_MAKE_SLOT: u8 = 0x03;
+const REFUSE_MAKE_DENIED: u8 = 0xFF;
+
+const REFUSE_DELETE_WRONG_KEY: u8 = 0x00;
+const REFUSE_DELETE_DENIED: u8 = 0x01;
+
+const NOTIFY_KICKED: u8 = 15;
+
+/// Time a player has to reach the map server after selecting a character.
+const HANDOFF_LIFETIME: Duration = Duration::from_secs(60);
+
+const JOB_NOVICE: u16 = 0;
+const NOVICE_HP: u32 = 40;
+const NOVICE_SP: u16 = 11;
+const DEFAULT_WALK_SPEED: i16 = 150;
+const MAX_HAIR_STYLE: u16 = 29;
+const MAX_HAIR_COLOR: u16 = 8;
+
+pub struct CharServerConfig {
+	pub store: Arc<dyn CharacterStore>,
+	/// Link to the login server, checking the auth codes of players.
+	pub login: Arc<InterServerLink>,
+	/// Map server players are sent to while none registered.
+	pub map_server: SocketAddrV4,
+	/// Sessions of the map servers, on their own listener.
+	pub map_links: SessionRegistry,
+	/// Secret map servers register with.
+	pub secret: String,
+	/// Where new characters start.
+	pub start: Position,
+	/// Asked to delete a character, in place of the e-mail accounts don't have.
+	pub delete_key: String,
+}
+
+static CONFIG: OnceLock<CharServerConfig> = OnceLock::new();
+
+/// Sessions waiting for the login server to check their auth code, by request id.
+static PENDING: Mutex<BTreeMap<u32, SessionId>> = Mutex::new(BTreeMap::new());
+static NEXT_REQUEST: AtomicU32 = AtomicU32::new(1);
+
+/// What the players showed in `CH_ENTER`, once the login server accepted it.
+static ENTERED: Mutex<BTreeMap<SessionId, PacketChEnter>> = Mutex::new(BTreeMap::new());
+
+/// Map servers registered on this char server, by session.
+static MAP_SERVERS: Mutex<BTreeMap<SessionId, MapServer>> = Mutex::new(BTreeMap::new());
+
+/// Characters selected, by account, until a map server checks them.
+static HANDOFFS: Mutex<BTreeMap<u32, Handoff>> = Mutex::new(BTreeMap::new());
+
+#[derive(Debug, Clone, Copy)]
+struct MapServer {
+	addr: SocketAddrV4,
+	users: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Handoff {
+	char_id: u32,
+	auth_code: u32,
+	sex: u8,
+	expires: Instant,
+}
+
+/// Sets up `char_system` and `map_server_system`, returns `false` if it was already set up.
+pub fn init(config: CharServerConfig) -> bool {
+	CONFIG.set(config).is_ok()
+}
+
+pub fn char_system(session: &mut PlayerSession, packet: &RawPacket) -> SystemResult {
+	let Some(config) = CONFIG.get() else {
+		error!("Dropping session: the char server isn't initialized");
+		session.close();
+		return Processed;
+	};
+
+	let result = match packet.packet_id {
+		0x0065 => packet.parse::<PacketChEnter>()
+			.map(|p| process_enter(session, config, p)),
+		0x0187 => Ok(()),
+		_ if session.account_id().is_none() => {
+			warn!("Sent a packet before entering");
+			session.close();
+			return Processed;
+		},
+		0x0066 => packet.parse::<PacketChSelectChar>()
+			.map(|p| process_select(session, config, packet.packetver(), p)),
+		0x0067 => packet.parse::<PacketChMakeChar>()
+			.map(|p| process_make_char(session, config, p)),
+		0x0A39 => packet.parse::<PacketChMakeNewChar>()
+			.map(|p| process_make_new_char(session, config, p)),
+		0x0068 => packet.parse::<PacketChDeleteChar>()
+			.map(|p| process_delete(session, config, p)),
+		_ => return NotProcessed,
+	};
+
+	if let Err(err) = result {
+		warn!(id = %format_args!("{:#06X}", packet.packet_id), %err, "Couldn't parse packet");
+		session.close();
+	}
+
+	Processed
+}
+
+/// Handles the map servers, connected to their own listener.
+pub fn map_server_system(session: &mut PlayerSession, packet: &RawPacket) -> SystemResult {
+	let Some(config) = CONFIG.get() else {
+		error!("Dropping map server: the char server isn't initialized");
+		session.close();
+		return Processed;
+	};
+
+	let result = match packet.packet_id {
+		0x2B00 => packet.parse::<PacketZhMapserverConnect>()
+			.map(|p| process_map_server_connect(session, config, p)),
+		_ if !is_map_server(session.id()) => {
+			warn!("Sent a packet before registering");
+			session.close();
+			return Processed;
+		},
+		0x2B02 => packet.parse::<PacketZhAuthRequest>()
+			.map(|p| process_map_auth_request(session, p)),
+		0x2B04 => packet.parse::<PacketZhUserCount>()
+			.map(|p| process_map_user_count(session, p)),
+		0x2B05 => packet.parse::<PacketZhPing>()
+			.map(|_| send_pong(session)),
+		_ => return NotProcessed,
+	};
+
+	if let Err(err) = result {
+		warn!(id = %format_args!("{:#06X}", packet.packet_id), %err, "Couldn't parse packet");
+		session.close();
+	}
+
+	Processed
+}
+
+pub fn on_disconnect(session: &mut PlayerSession, _reason: DisconnectReason) {
+	let id = session.id();
+	PENDING.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).retain(|_, pending| *pending != id);
+	ENTERED.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).remove(&id);
+}
+
+pub fn on_map_server_disconnect(session: &mut PlayerSession, reason: DisconnectReason) {
+	if let Some(server) = MAP_SERVERS.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).remove(&session.id()) {
+		info!(addr = %server.addr, ?reason, "Map server disconnected");
+	}
+}
+
+/// Handles a packet the login server sent over the link.
+pub fn on_login_packet(registry: &SessionRegistry, packet: &RawPacket) {
+	match packet.packet_id {
+		0x2711 => match packet.parse::<PacketAhCharserverConnectAck>() {
+			Ok(ack) if ack.result == 0 => info!("Registered with the login server"),
+			Ok(ack) => error!(result = ack.result, "The login server refused this char server"),
+			Err(err) => warn!(id = %format_args!("{:#06X}", packet.packet_id), %err, "Couldn't parse packet"),
+		},
+		0x2713 => match packet.parse::<PacketAhAuthResult>() {
+			Ok(result) => {
+				let pending = PENDING.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).remove(&result.request_id);
+				if let Some(id) = pending {
+					registry.run(id, Box::new(move |session| {
+						if let Some(config) = CONFIG.get() {
+							process_auth_result(session, config, result);
+						}
+					}));
+				}
+			},
+			Err(err) => warn!(id = %format_args!("{:#06X}", packet.packet_id), %err, "Couldn't parse packet"),
+		},
+		0x2716 => {},
+		0x2717 => match packet.parse::<PacketAhKickAccount>() {
+			Ok(kick) => kick_account(registry, kick.aid),
+			Err(err) => warn!(id = %format_args!("{:#06X}", packet.packet_id), %err, "Couldn't parse packet"),
+		},
+		_ => warn!(id = %format_args!("{:#06X}", packet.packet_id), "Unexpected packet from the login server"),
+	}
+}
+
+/// Disconnects an account from this char server and its map servers.
+pub fn kick_account(registry: &SessionRegistry, account_id: u32) {
+	HANDOFFS.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).remove(&account_id);
+
+	if let Some(id) = registry.find_by_account(account_id) {
+		if let Ok(buf) = (PacketScNotifyBan { error_code: NOTIFY_KICKED, ..PacketScNotifyBan::new() }).serialize() {
+			registry.send_to(id, buf);
+		}
+		registry.close(id);
+	}
+
+	let (Some(config), Ok(buf)) = (CONFIG.get(), (PacketHzKickAccount { aid: account_id, ..PacketHzKickAccount::new() }).serialize()) else {
+		return;
+	};
+	let map_servers: Vec<SessionId> = MAP_SERVERS.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).keys().copied().collect();
+	for id in map_servers {
+		config.map_links.send_to(id, buf.clone());
+	}
+}
+
+/// Tells the login server how many players are on this char server and
+/// its map servers.
+pub fn send_user_count(link: &InterServerLink, registry: &SessionRegistry) -> io::Result<()> {
+	let selecting = registry.sessions().iter().filter(|session| session.account_id.is_some()).count() as u32;
+	let playing: u32 = MAP_SERVERS.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).values().map(|server| server.users).sum();
+	let packet = PacketHaUserCount { users: selecting + playing, ..PacketHaUserCount::new() };
+	link.send(&packet.serialize().map_err(io::Error::other)?)
+}
+
+fn is_map_server(id: SessionId) -> bool {
+	MAP_SERVERS.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).contains_key(&id)
+}
+
+fn process_map_server_connect(session: &mut PlayerSession, config: &CharServerConfig, pkt: PacketZhMapserverConnect) {
+	let result = if pkt.version != INTER_VERSION {
+		warn!(version = pkt.version, expected = INTER_VERSION, "Refusing map server: wrong inter-server version");
+		CONNECT_VERSION_MISMATCH
+	} else if !crate::secret_matches(&config.secret, &pkt.secret) {
+		warn!("Refusing map server: wrong secret");
+		CONNECT_REFUSED
+	} else {
+		let addr = SocketAddrV4::new(pkt.ip.to_le_bytes().into(), pkt.port);
+		info!(%addr, "Map server connected");
+		MAP_SERVERS.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).insert(session.id(), MapServer { addr, users: 0 });
+		session.authenticate();
+		CONNECT_ACCEPTED
+	};
+
+	match (PacketHzMapserverConnectAck { result, ..PacketHzMapserverConnectAck::new() }).serialize() {
+		Ok(buf) => session.send_queue.push_back(buf),
+		Err(err) => error!(%err, "Couldn't serialize packet"),
+	}
+	if result != CONNECT_ACCEPTED {
+		session.close();
+	}
+}
+
+/// Checks that a player entering a map server selected this character
+/// here. Each selection is accepted once.
+fn process_map_auth_request(session: &mut PlayerSession, pkt: PacketZhAuthRequest) {
+	let now = Instant::now();
+	let valid = {
+		let mut handoffs = HANDOFFS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+		handoffs.retain(|_, handoff| handoff.expires > now);
+		match handoffs.get(&pkt.aid) {
+			Some(handoff) if handoff.char_id == pkt.gid && handoff.auth_code == pkt.auth_code && handoff.sex == pkt.sex => {
+				handoffs.remove(&pkt.aid);
+				true
+			},
+			_ => false,
+		}
+	};
+
+	let result = PacketHzAuthResult {
+		aid: pkt.aid,
+		gid: pkt.gid,
+		auth_code: pkt.auth_code,
+		result: if valid { 0 } else { 1 },
+		request_id: pkt.request_id,
+		..PacketHzAuthResult::new()
+	};
+	match result.serialize() {
+		Ok(buf) => session.send_queue.push_back(buf),
+		Err(err) => error!(%err, "Couldn't serialize packet"),
+	}
+}
+
+fn process_map_user_count(session: &mut PlayerSession, pkt: PacketZhUserCount) {
+	if let Some(server) = MAP_SERVERS.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).get_mut(&session.id()) {
+		server.users = pkt.users;
+	}
+}
+
+fn send_pong(session: &mut PlayerSession) {
+	match PacketHzPong::new().serialize() {
+		Ok(buf) => session.send_queue.push_back(buf),
+		Err(err) => error!(%err, "Couldn't serialize packet"),
+	}
+}
+
+fn process_enter(session: &mut PlayerSession, config: &CharServerConfig, pkt: PacketChEnter) {
+	let id = session.id();
+	let already_entered = ENTERED.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).contains_key(&id);
+	if already_entered || session.account_id().is_some() {
+		warn!("Sent CH_ENTER twice");
+		session.close();
+		return;
+	}
+
+	// Clients expect their account id alone before any packet.
+	session.send_queue.push_back(pkt.aid.to_le_bytes().to_vec());
+
+	let request_id = NEXT_REQUEST.fetch_add(1, Ordering::Relaxed);
+	let request = PacketHaAuthRequest {
+		aid: pkt.aid,
+		auth_code: pkt.auth_code,
+		user_level: pkt.user_level,
+		sex: pkt.sex,
+		ip: match session.peer_addr {
+			std::net::SocketAddr::V4(addr) => u32::from_le_bytes(addr.ip().octets()),
+			std::net::SocketAddr::V6(_) => 0,
+		},
+		request_id,
+		..PacketHaAuthRequest::new()
+	};
+
+	PENDING.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).insert(request_id, id);
+	ENTERED.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).insert(id, pkt);
+
+	let sent = request.serialize().map_err(io::Error::other).and_then(|buf| config.login.send(&buf));
+	if let Err(err) = sent {
+		warn!(%err, "Couldn't ask the login server about the session");
+		PENDING.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).remove(&request_id);
+		refuse_enter(session);
+	}
+}
+
+fn process_auth_result(session: &mut PlayerSession, config: &CharServerConfig, pkt: PacketAhAuthResult) {
+	let entered = ENTERED.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).get(&session.id()).cloned();
+	let Some(entered) = entered else {
+		return;
+	};
+
+	let matches = entered.aid == pkt.aid && entered.auth_code == pkt.auth_code && entered.user_level == pkt.user_level;
+	if pkt.result != 0 || !matches {
+		info!(account_id = entered.aid, "The login server refused the account");
+		refuse_enter(session);
+		return;
+	}
+
+	session.set_account_id(Some(entered.aid));
+	session.authenticate();
+	info!("Entered the char server");
+	send_characters(session, config);
+}
+
+fn send_characters(session: &mut PlayerSession, config: &CharServerConfig) {
+	let Some(account_id) = session.account_id() else {
+		return;
+	};
+
+	let characters = match config.store.characters_of(account_id) {
+		Ok(characters) => characters,
+		Err(err) => {
+			error!(%err, "Couldn't load the characters");
+			refuse_enter(session);
+			return;
+		},
+	};
+
+	let accepted = PacketHcAcceptEnter {
+		max_slots: MAX_CHARS,
+		available_slots: MAX_CHARS,
+		premium_slots: MAX_CHARS,
+		characters: characters.iter().map(character_info).collect(),
+		..PacketHcAcceptEnter::new()
+	};
+	match accepted.serialize() {
+		Ok(buf) => session.send_queue.push_back(buf),
+		Err(err) => error!(%err, "Couldn't serialize packet"),
+	}
+}
+
+fn refuse_enter(session: &mut PlayerSession) {
+	match (PacketHcRefuseEnter { error_code: REFUSE_ENTER_REJECTED, ..PacketHcRefuseEnter::new() }).serialize() {
+		Ok(buf) => session.send_queue.push_back(buf),
+		Err(err) => error!(%err, "Couldn't serialize packet"),
+	}
+	session.close();
+}
+
+fn character_info(character: &Character) -> CharacterInfo {
+	CharacterInfo {
+		gid: character.char_id,
+		exp: character.base_exp.min(i64::MAX as u64) as i64,
+		money: character.zeny.min(i32::MAX as u32) as i32,
+		job_exp: character.job_exp.min(i64::MAX as u64) as i64,
+		job_level: character.job_level.into(),
+		status_point: character.status_points as i16,
+		hp: character.hp.min(i32::MAX as u32) as i32,
+		max_hp: character.max_hp.min(i32::MAX as u32) as i32,
+		sp: character.sp as i16,
+		max_sp: character.max_sp as i16,
+		speed: DEFAULT_WALK_SPEED,
+		job: character.class as i16,
+		head: character.look.hair as i16,
+		weapon: character.look.weapon as i16,
+		level: character.base_level as i16,
+		skill_point: character.skill_points as i16,
+		head_bottom: character.look.head_bottom as i16,
+		shield: character.look.shield as i16,
+		head_top: character.look.head_top as i16,
+		head_mid: character.look.head_mid as i16,
+		hair_color: character.look.hair_color as i16,
+		clothes_color: character.look.clothes_color as i16,
+		name: character.name.clone(),
+		str: character.stats.str,
+		agi: character.stats.agi,
+		vit: character.stats.vit,
+		int: character.stats.int,
+		dex: character.stats.dex,
+		luk: character.stats.luk,
+		slot: character.slot.into(),
+		can_rename: 1,
+		map_name: format!("{}.gat", character.position.map),
+		robe: character.look.robe.into(),
+		sex: sex_to_u8(character.sex),
+		..Default::default()
+	}
+}
+
+fn sex_to_u8(sex: Sex) -> u8 {
+	match sex {
+		Sex::Female => 0,
+		_ => 1,
+	}
+}
+
+fn sex_from_u8(sex: u8) -> Sex {
+	if sex == 0 { Sex::Female } else { Sex::Male }
+}
+
+fn find_in_slot(session: &PlayerSession, config: &CharServerConfig, slot: u8) -> Result<Option<Character>, StoreError> {
+	let Some(account_id) = session.account_id() else {
+		return Ok(None);
+	};
+	Ok(config.store.characters_of(account_id)?.into_iter().find(|character| character.slot == slot))
+}
+
+fn process_select(session: &mut PlayerSession, config: &CharServerConfig, packetver: u32, pkt: PacketChSelectChar) {
+	let character = match find_in_slot(session, config, pkt.slot) {
+		Ok(Some(character)) => character,
+		Ok(None) => {
+			warn!(slot = pkt.slot, "Selected an empty slot");
+			refuse_enter(session);
+			return;
+		},
+		Err(err) => {
+			error!(slot = pkt.slot, %err, "Couldn't load the character");
+			refuse_enter(session);
+			return;
+		},
+	};
+
+	let entered = ENTERED.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).get(&session.id()).cloned();
+	let Some(entered) = entered else {
+		return;
+	};
+	let handoff = Handoff {
+		char_id: character.char_id,
+		auth_code: entered.auth_code,
+		sex: entered.sex,
+		expires: Instant::now() + HANDOFF_LIFETIME,
+	};
+	HANDOFFS.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).insert(entered.aid, handoff);
+
+	let map_server = MAP_SERVERS.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+		.values()
+		.next()
+		.map_or(config.map_server, |server| server.addr);
+	session.set_char_id(Some(character.char_id));
+	info!(name = %character.name, %map_server, "Character selected");
+
+	let map_name = format!("{}.gat", character.position.map);
+	let ip = u32::from_le_bytes(map_server.ip().octets());
+	let port = map_server.port();
+	let result = if packetver >= 20170315 {
+		PacketHcNotifyZonesvr2 { gid: character.char_id, map_name, ip, port, ..PacketHcNotifyZonesvr2::new() }.serialize()
+	} else {
+		PacketHcNotifyZonesvr { gid: character.char_id, map_name, ip, port, ..PacketHcNotifyZonesvr::new() }.serialize()
+	};
+
+	match result {
+		Ok(buf) => session.send_queue.push_back(buf),
+		Err(err) => error!(%err, "Couldn't serialize packet"),
+	}
+}
+
+fn valid_name(name: &str) -> bool {
+	(4..=23).contains(&name.len()) && name.trim() == name && !name.chars().any(char::is_control)
+}
+
+fn process_make_char(session: &mut PlayerSession, config: &CharServerConfig, pkt: PacketChMakeChar) {
+	let stats = Stats { str: pkt.str, agi: pkt.agi, vit: pkt.vit, int: pkt.int, dex: pkt.dex, luk: pkt.luk };
+	let all = [stats.str, stats.agi, stats.vit, stats.int, stats.dex, stats.luk];
+	// Opposite stats always add up to 10.
+	let valid_stats = all.iter().all(|stat| (1..=9).contains(stat))
+		&& stats.str + stats.int == 10
+		&& stats.agi + stats.luk == 10
+		&& stats.vit + stats.dex == 10;
+	if !valid_stats {
+		refuse_make(session, REFUSE_MAKE_DENIED);
+		return;
+	}
+
+	let sex = ENTERED.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+		.get(&session.id())
+		.map_or(Sex::Male, |entered| sex_from_u8(entered.sex));
+	make_char(session, config, pkt.name, pkt.slot, sex, stats, pkt.hair_style, pkt.hair_color);
+}
+
+fn process_make_new_char(session: &mut PlayerSession, config: &CharServerConfig, pkt: PacketChMakeNewChar) {
+	if pkt.job != JOB_NOVICE {
+		refuse_make(session, REFUSE_MAKE_DENIED);
+		return;
+	}
+
+	let stats = Stats { str: 1, agi: 1, vit: 1, int: 1, dex: 1, luk: 1 };
+	make_char(session, config, pkt.name, pkt.slot, sex_from_u8(pkt.sex), stats, pkt.hair_style, pkt.hair_color);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn make_char(
+	session: &mut PlayerSession,
+	config: &CharServerConfig,
+	name: String,
+	slot: u8,
+	sex: Sex,
+	stats: Stats,
+	hair_style: u16,
+	hair_color: u16,
+) {
+	let Some(account_id) = session.account_id() else {
+		return;
+	};
+	if !valid_name(&name) {
+		refuse_make(session, REFUSE_MAKE_SYMBOLS);
+		return;
+	}
+	if slot >= MAX_CHARS {
+		refuse_make(session, REFUSE_MAKE_SLOT);
+		return;
+	}
+	if !(1..=MAX_HAIR_STYLE).contains(&hair_style) || hair_color > MAX_HAIR_COLOR {
+		refuse_make(session, REFUSE_MAKE_DENIED);
+		return;
+	}
+
+	let new_character = NewCharacter {
+		account_id,
+		slot,
+		name,
+		sex,
+		class: JOB_NOVICE,
+		stats,
+		hp: NOVICE_HP,
+		sp: NOVICE_SP,
+		look: Look { hair: hair_style, hair_color, ..Default::default() },
+		start: config.start.clone(),
+	};
+
+	match config.store.create(new_character) {
+		Ok(character) => {
+			info!(name = %character.name, slot = character.slot, "Character made");
+			let accepted = PacketHcAcceptMakechar { character: character_info(&character), ..PacketHcAcceptMakechar::new() };
+			match accepted.serialize() {
+				Ok(buf) => session.send_queue.push_back(buf),
+				Err(err) => error!(%err, "Couldn't serialize packet"),
+			}
+		},
+		Err(StoreError::NameTaken(_)) => refuse_make(session, REFUSE_MAKE_NAME_TAKEN),
+		Err(StoreError::SlotTaken(_)) => refuse_make(session, REFUSE_MAKE_SLOT),
+		Err(err) => {
+			error!(%err, "Couldn't make a character");
+			refuse_make(session, REFUSE_MAKE_DENIED);
+		},
+	}
+}
+
+fn refuse_make(session: &mut PlayerSession, error_code: u8) {
+	match (PacketHcRefuseMakechar { error_code, ..PacketHcRefuseMakechar::new() }).serialize() {
+		Ok(buf) => session.send_queue.push_back(buf),
+		Err(err) => error!(%err, "Couldn't serialize packet"),
+	}
+}
+
+/// Accounts have no e-mail to confirm the deletion with, so the key is
+/// checked against the configured one, regardless of case like e-mails.
+fn process_delete(session: &mut PlayerSession, config: &CharServerConfig, pkt: PacketChDeleteChar) {
+	if !pkt.key.eq_ignore_ascii_case(&config.delete_key) {
+		warn!(char_id = pkt.gid, "Wrong key to delete a character");
+		match (PacketHcRefuseDeletechar { error_code: REFUSE_DELETE_WRONG_KEY, ..PacketHcRefuseDeletechar::new() }).serialize() {
+			Ok(buf) => session.send_queue.push_back(buf),
+			Err(err) => error!(%err, "Couldn't serialize packet"),
+		}
+		return;
+	}
+
+	let owned = config.store.find(pkt.gid)
+		.map(|character| character.is_some_and(|character| Some(character.account_id) == session.account_id()));
+
+	let deleted = match owned {
+		Ok(true) => config.store.delete(pkt.gid),
+		Ok(false) => Ok(false),
+		Err(err) => Err(err),
+	};
+
+	let result = match deleted {
+		Ok(true) => {
+			info!(char_id = pkt.gid, "Character deleted");
+			PacketHcAcceptDeletechar::new().serialize()
+		},
+		Ok(false) => PacketHcRefuseDeletechar { error_code: REFUSE_DELETE_DENIED, ..PacketHcRefuseDeletechar::new() }.serialize(),
+		Err(err) => {
+			error!(char_id = pkt.gid, %err, "Couldn't delete the character");
+			PacketHcRefuseDeletechar { error_code: REFUSE_DELETE_DENIED, ..PacketHcRefuseDeletechar::new() }.serialize()
+		},
+	};
+
+	match result {
+		Ok(buf) => session.send_queue.push_back(buf),
+		Err(err) => error!(%err, "Couldn't serialize packet"),
+	}
+}
@@ -0,0 +1,113 @@
+use std::collections::BTreeMap;
+use std::net::Ipv4Addr;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use network::{SessionId, SessionRegistry};
+use packets::auth::CharServerList;
+
+pub use config::CharServerType;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CharServer {
+	pub name: String,
+	pub ip: Ipv4Addr,
+	pub port: u16,
+	pub users: u16,
+	pub server_type: CharServerType,
+	/// Shows the server with a "new" mark.
+	pub is_new: bool,
+}
+
+impl CharServer {
+	pub fn to_list_entry(&self) -> CharServerList {
+		CharServerList {
+			// Sent in network byte order.
+			ip: u32::from_le_bytes(self.ip.octets()),
+			port: self.port as i16,
+			name: self.name.clone(),
+			usercount: self.users,
+			server_type: self.server_type as u16,
+			is_new: self.is_new.into(),
+			..Default::default()
+		}
+	}
+}
+
+/// Char servers connected to the login server, keyed by the session they
+/// registered on. Cloning is cheap and every clone refers to the same servers.
+#[derive(Debug, Clone, Default)]
+pub struct CharServerRegistry {
+	live: Arc<RwLock<BTreeMap<SessionId, CharServer>>>,
+	fallback: Arc<RwLock<Vec<CharServer>>>,
+	/// Sessions of the listener char servers connect to.
+	links: SessionRegistry,
+}
+
+impl CharServerRegistry {
+	/// `fallback` is listed while no char server is connected, for running
+	/// everything on a single machine.
+	pub fn new(fallback: Vec<CharServer>, links: SessionRegistry) -> Self {
+		Self {
+			live: Arc::default(),
+			fallback: Arc::new(RwLock::new(fallback)),
+			links,
+		}
+	}
+
+	pub fn set_fallback(&self, fallback: Vec<CharServer>) {
+		*self.fallback.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = fallback;
+	}
+
+	fn read(&self) -> RwLockReadGuard<'_, BTreeMap<SessionId, CharServer>> {
+		self.live.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+	}
+
+	fn write(&self) -> RwLockWriteGuard<'_, BTreeMap<SessionId, CharServer>> {
+		self.live.write().unwrap_or_else(|poisoned| poisoned.into_inner())
+	}
+
+	pub fn register(&self, id: SessionId, server: CharServer) {
+		self.write().insert(id, server);
+	}
+
+	pub fn unregister(&self, id: SessionId) -> Option<CharServer> {
+		self.write().remove(&id)
+	}
+
+	pub fn is_registered(&self, id: SessionId) -> bool {
+		self.read().contains_key(&id)
+	}
+
+	/// Sessions the char servers registered on.
+	pub fn sessions(&self) -> Vec<SessionId> {
+		self.read().keys().copied().collect()
+	}
+
+	/// Sends a packet to every registered char server.
+	pub fn broadcast(&self, packet: Vec<u8>) {
+		for id in self.sessions() {
+			self.links.send_to(id, packet.clone());
+		}
+	}
+
+	/// Returns `false` if no char server registered on this session.
+	pub fn set_users(&self, id: SessionId, users: u16) -> bool {
+		match self.write().get_mut(&id) {
+			Some(server) => {
+				server.users = users;
+				true
+			},
+			None => false,
+		}
+	}
+
+	/// Servers to list to players, the static ones when none is connected.
+	pub fn servers(&self) -> Vec<CharServer> {
+		let live = self.read();
+		if live.is_empty() {
+			self.fallback.read().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+		} else {
+			live.values().cloned().collect()
+		}
+	}
+}
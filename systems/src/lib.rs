@@ -3,6 +3,9 @@ use packet::RawPacket;
 use network::PlayerSession;
 
 pub mod auth;
+pub mod char;
+pub mod char_servers;
+pub mod map;
 
 #[derive(Debug)]
 pub enum SystemResult {
@@ -10,4 +13,20 @@ pub enum SystemResult {
   NotProcessed
 }
 
-pub type System = fn(session: &mut PlayerSession, packet: &RawPacket) -> SystemResult;
\ No newline at end of file
+pub type System = fn(session: &mut PlayerSession, packet: &RawPacket) -> SystemResult;
+
+/// Runs `packet` through `systems` until one of them processes it.
+pub fn dispatch(systems: &[System], session: &mut PlayerSession, packet: &RawPacket) -> SystemResult {
+  for system in systems {
+    if let SystemResult::Processed = system(session, packet) {
+      return SystemResult::Processed;
+    }
+  }
+  SystemResult::NotProcessed
+}
+
+/// Compares the secret another server registered with to ours, taking the
+/// same time wherever they differ.
+pub(crate) fn secret_matches(expected: &str, given: &str) -> bool {
+  expected.len() == given.len() && expected.bytes().zip(given.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
@@ -0,0 +1,377 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Instant;
+
+use characters::{CharacterStore, Sex};
+use maps::{MapData, MAX_WALK_PATH};
+use network::{DisconnectReason, InterServerLink, PlayerSession, SessionId, SessionRegistry};
+use packet::{Packet, RawPacket};
+use packets::auth::PacketScNotifyBan;
+use packets::inter::{PacketHzAuthResult, PacketHzKickAccount, PacketHzMapserverConnectAck, PacketZhAuthRequest, PacketZhUserCount};
+use packets::map::*;
+use tracing::{error, info, warn};
+
+use super::SystemResult::{self, *};
+
+const REFUSE_ENTER_REJECTED: u8 = 0;
+
+const NOTIFY_KICKED: u8 = 15;
+
+/// Cells around a player in which others see what they do.
+const AREA_SIZE: u16 = 14;
+const DEFAULT_WALK_SPEED: u32 = 150;
+const DIR_SOUTH: u8 = 4;
+
+pub struct MapServerConfig {
+	pub store: Arc<dyn CharacterStore>,
+	/// Link to the char server, checking players entering.
+	pub char_server: Arc<InterServerLink>,
+	/// Maps served here, by name without the `.gat` extension.
+	pub maps: BTreeMap<String, MapData>,
+}
+
+static CONFIG: OnceLock<MapServerConfig> = OnceLock::new();
+static STARTED: OnceLock<Instant> = OnceLock::new();
+
+/// Characters on a map, by session.
+static PLAYERS: Mutex<BTreeMap<SessionId, Player>> = Mutex::new(BTreeMap::new());
+
+/// Sessions waiting for the char server to let them in, by request id.
+static PENDING: Mutex<BTreeMap<u32, SessionId>> = Mutex::new(BTreeMap::new());
+static NEXT_REQUEST: AtomicU32 = AtomicU32::new(1);
+
+/// What the players showed in `CZ_ENTER` and the packet version they spoke,
+/// until the char server answered.
+static ENTERING: Mutex<BTreeMap<SessionId, (PacketCzEnter2, u32)>> = Mutex::new(BTreeMap::new());
+
+struct Walk {
+	from: (u16, u16),
+	path: Vec<(u16, u16)>,
+	step_times: Vec<u32>,
+	started: Instant,
+}
+
+struct Player {
+	char_id: u32,
+	map: String,
+	/// Where the player stood when `walk` started.
+	position: (u16, u16),
+	walk: Option<Walk>,
+}
+
+impl Player {
+	/// The last cell the player reached at `now`.
+	fn position_at(&self, now: Instant) -> (u16, u16) {
+		let Some(walk) = &self.walk else {
+			return self.position;
+		};
+
+		let elapsed = now.saturating_duration_since(walk.started).as_millis();
+		let mut walked = 0_u128;
+		let mut position = walk.from;
+		for (&cell, &time) in walk.path.iter().zip(&walk.step_times) {
+			walked += time as u128;
+			if walked > elapsed {
+				break;
+			}
+			position = cell;
+		}
+		position
+	}
+}
+
+/// Sets up `map_system`, returns `false` if it was already set up.
+pub fn init(config: MapServerConfig) -> bool {
+	STARTED.get_or_init(Instant::now);
+	CONFIG.set(config).is_ok()
+}
+
+/// Milliseconds since the server started, the clock clients sync with.
+fn tick() -> u32 {
+	STARTED.get_or_init(Instant::now).elapsed().as_millis() as u32
+}
+
+pub fn map_system(session: &mut PlayerSession, packet: &RawPacket) -> SystemResult {
+	let Some(config) = CONFIG.get() else {
+		error!("Dropping session: the map server isn't initialized");
+		session.close();
+		return Processed;
+	};
+
+	let result = match packet.packet_id {
+		0x0436 => packet.parse::<PacketCzEnter2>()
+			.map(|p| process_enter(session, config, packet.packetver(), p)),
+		0x0360 => packet.parse::<PacketCzRequestTime2>()
+			.map(|_| send_time(session)),
+		_ if session.char_id().is_none() => {
+			warn!("Sent a packet before entering");
+			session.close();
+			return Processed;
+		},
+		0x007D => Ok(()),
+		0x0085 => packet.parse::<PacketCzRequestMove>()
+			.map(|p| process_move(session, config, p.dest)),
+		0x035F => packet.parse::<PacketCzRequestMove2>()
+			.map(|p| process_move(session, config, p.dest)),
+		_ => return NotProcessed,
+	};
+
+	if let Err(err) = result {
+		warn!(id = %format_args!("{:#06X}", packet.packet_id), %err, "Couldn't parse packet");
+		session.close();
+	}
+
+	Processed
+}
+
+/// Saves where the character stood.
+pub fn on_disconnect(session: &mut PlayerSession, _reason: DisconnectReason) {
+	let id = session.id();
+	PENDING.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).retain(|_, pending| *pending != id);
+	ENTERING.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).remove(&id);
+
+	let player = PLAYERS.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).remove(&id);
+	let (Some(player), Some(config)) = (player, CONFIG.get()) else {
+		return;
+	};
+
+	let (x, y) = player.position_at(Instant::now());
+	let saved = config.store.find(player.char_id).and_then(|character| match character {
+		Some(mut character) => {
+			character.position.map = player.map;
+			character.position.x = x;
+			character.position.y = y;
+			config.store.update(&character)
+		},
+		None => Ok(()),
+	});
+	if let Err(err) = saved {
+		error!(char_id = player.char_id, %err, "Couldn't save the position");
+	}
+}
+
+/// Handles a packet the char server sent over the link.
+pub fn on_char_packet(registry: &SessionRegistry, packet: &RawPacket) {
+	match packet.packet_id {
+		0x2B01 => match packet.parse::<PacketHzMapserverConnectAck>() {
+			Ok(ack) if ack.result == 0 => info!("Registered with the char server"),
+			Ok(ack) => error!(result = ack.result, "The char server refused this map server"),
+			Err(err) => warn!(id = %format_args!("{:#06X}", packet.packet_id), %err, "Couldn't parse packet"),
+		},
+		0x2B03 => match packet.parse::<PacketHzAuthResult>() {
+			Ok(result) => {
+				let pending = PENDING.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).remove(&result.request_id);
+				if let Some(id) = pending {
+					registry.run(id, Box::new(move |session| {
+						if let Some(config) = CONFIG.get() {
+							process_auth_result(session, config, result);
+						}
+					}));
+				}
+			},
+			Err(err) => warn!(id = %format_args!("{:#06X}", packet.packet_id), %err, "Couldn't parse packet"),
+		},
+		0x2B06 => {},
+		0x2B07 => match packet.parse::<PacketHzKickAccount>() {
+			Ok(kick) => {
+				if let Some(id) = registry.find_by_account(kick.aid) {
+					if let Ok(buf) = (PacketScNotifyBan { error_code: NOTIFY_KICKED, ..PacketScNotifyBan::new() }).serialize() {
+						registry.send_to(id, buf);
+					}
+					registry.close(id);
+				}
+			},
+			Err(err) => warn!(id = %format_args!("{:#06X}", packet.packet_id), %err, "Couldn't parse packet"),
+		},
+		_ => warn!(id = %format_args!("{:#06X}", packet.packet_id), "Unexpected packet from the char server"),
+	}
+}
+
+/// Tells the char server how many players are on this map server.
+pub fn send_user_count(link: &InterServerLink) -> io::Result<()> {
+	let users = PLAYERS.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).len();
+	let packet = PacketZhUserCount { users: users as u32, ..PacketZhUserCount::new() };
+	link.send(&packet.serialize().map_err(io::Error::other)?)
+}
+
+fn refuse_enter(session: &mut PlayerSession) {
+	match (PacketZcRefuseEnter { error_code: REFUSE_ENTER_REJECTED, ..PacketZcRefuseEnter::new() }).serialize() {
+		Ok(buf) => session.send_queue.push_back(buf),
+		Err(err) => error!(%err, "Couldn't serialize packet"),
+	}
+	session.close();
+}
+
+fn send_time(session: &mut PlayerSession) {
+	match (PacketZcNotifyTime { time: tick(), ..PacketZcNotifyTime::new() }).serialize() {
+		Ok(buf) => session.send_queue.push_back(buf),
+		Err(err) => error!(%err, "Couldn't serialize packet"),
+	}
+}
+
+fn process_enter(session: &mut PlayerSession, config: &MapServerConfig, packetver: u32, pkt: PacketCzEnter2) {
+	let id = session.id();
+	let already_entering = ENTERING.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).contains_key(&id);
+	if already_entering || session.char_id().is_some() {
+		warn!("Sent CZ_ENTER twice");
+		session.close();
+		return;
+	}
+
+	let request_id = NEXT_REQUEST.fetch_add(1, Ordering::Relaxed);
+	let request = PacketZhAuthRequest {
+		aid: pkt.aid,
+		gid: pkt.gid,
+		auth_code: pkt.auth_code,
+		sex: pkt.sex,
+		ip: match session.peer_addr {
+			SocketAddr::V4(addr) => u32::from_le_bytes(addr.ip().octets()),
+			SocketAddr::V6(_) => 0,
+		},
+		request_id,
+		..PacketZhAuthRequest::new()
+	};
+
+	PENDING.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).insert(request_id, id);
+	ENTERING.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).insert(id, (pkt, packetver));
+
+	let sent = request.serialize().map_err(io::Error::other).and_then(|buf| config.char_server.send(&buf));
+	if let Err(err) = sent {
+		warn!(%err, "Couldn't ask the char server about the session");
+		PENDING.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).remove(&request_id);
+		refuse_enter(session);
+	}
+}
+
+fn process_auth_result(session: &mut PlayerSession, config: &MapServerConfig, result: PacketHzAuthResult) {
+	let entering = ENTERING.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).remove(&session.id());
+	let Some((pkt, packetver)) = entering else {
+		return;
+	};
+
+	let matches = pkt.aid == result.aid && pkt.gid == result.gid && pkt.auth_code == result.auth_code;
+	if result.result != 0 || !matches {
+		info!(account_id = pkt.aid, char_id = pkt.gid, "The char server refused the character");
+		refuse_enter(session);
+		return;
+	}
+
+	let character = match config.store.find(pkt.gid) {
+		Ok(Some(character)) if character.account_id == pkt.aid => character,
+		Ok(_) => {
+			warn!(account_id = pkt.aid, char_id = pkt.gid, "Tried to enter with a character of another account");
+			refuse_enter(session);
+			return;
+		},
+		Err(err) => {
+			error!(char_id = pkt.gid, %err, "Couldn't load the character");
+			refuse_enter(session);
+			return;
+		},
+	};
+	if session.registry().find_by_char(character.char_id).is_some() {
+		info!(name = %character.name, "Character is already on the map server");
+		refuse_enter(session);
+		return;
+	}
+	let Some(map) = config.maps.get(&character.position.map) else {
+		warn!(name = %character.name, map = %character.position.map, "Character is on a map which isn't loaded");
+		refuse_enter(session);
+		return;
+	};
+
+	session.set_account_id(Some(pkt.aid));
+	session.set_char_id(Some(character.char_id));
+	session.authenticate();
+
+	let position = (character.position.x, character.position.y);
+	PLAYERS.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).insert(session.id(), Player {
+		char_id: character.char_id,
+		map: map.name.clone(),
+		position,
+		walk: None,
+	});
+	info!(name = %character.name, map = %map.name, ?position, "Character entered");
+
+	let pos_dir = encode_pos_dir(position.0, position.1, DIR_SOUTH);
+	let accepted = if packetver >= 20141022 {
+		PacketZcAcceptEnter3 {
+			start_time: tick(),
+			pos_dir,
+			x_size: 5,
+			y_size: 5,
+			sex: if character.sex == Sex::Female { 0 } else { 1 },
+			..PacketZcAcceptEnter3::new()
+		}
+		.serialize()
+	} else {
+		PacketZcAcceptEnter2 { start_time: tick(), pos_dir, x_size: 5, y_size: 5, ..PacketZcAcceptEnter2::new() }.serialize()
+	};
+
+	let aid = PacketZcAid { aid: pkt.aid, ..PacketZcAid::new() }.serialize();
+	match aid.and_then(|aid| Ok((aid, accepted?))) {
+		Ok((aid, accepted)) => {
+			session.send_queue.push_back(aid);
+			session.send_queue.push_back(accepted);
+		},
+		Err(err) => error!(%err, "Couldn't serialize packet"),
+	}
+}
+
+fn in_view(a: (u16, u16), b: (u16, u16)) -> bool {
+	a.0.abs_diff(b.0) <= AREA_SIZE && a.1.abs_diff(b.1) <= AREA_SIZE
+}
+
+fn process_move(session: &mut PlayerSession, config: &MapServerConfig, dest: [u8; 3]) {
+	let (x, y, _) = decode_pos_dir(dest);
+	let now = Instant::now();
+	let mut players = PLAYERS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+	let Some(player) = players.get_mut(&session.id()) else {
+		return;
+	};
+	let Some(map) = config.maps.get(&player.map) else {
+		return;
+	};
+	let from = player.position_at(now);
+	let Some(path) = map.find_path(from, (x, y), MAX_WALK_PATH) else {
+		return;
+	};
+
+	player.position = from;
+	player.walk = Some(Walk {
+		from,
+		step_times: maps::step_times(from, &path, DEFAULT_WALK_SPEED),
+		path,
+		started: now,
+	});
+	let (char_id, map_name) = (player.char_id, player.map.clone());
+
+	let move_data = encode_move_data(from, (x, y));
+	let move_start_time = tick();
+	match (PacketZcNotifyPlayermove { move_start_time, move_data, ..PacketZcNotifyPlayermove::new() }).serialize() {
+		Ok(buf) => session.send_queue.push_back(buf),
+		Err(err) => error!(%err, "Couldn't serialize packet"),
+	}
+
+	let notify = match (PacketZcNotifyMove { gid: char_id, move_data, move_start_time, ..PacketZcNotifyMove::new() }).serialize() {
+		Ok(buf) => buf,
+		Err(err) => {
+			error!(%err, "Couldn't serialize packet");
+			return;
+		},
+	};
+	let watchers: Vec<SessionId> = players
+		.iter()
+		.filter(|(&id, other)| id != session.id() && other.map == map_name && in_view(other.position_at(now), from))
+		.map(|(&id, _)| id)
+		.collect();
+	drop(players);
+
+	for id in watchers {
+		session.send_to(id, notify.clone());
+	}
+}